@@ -0,0 +1,369 @@
+use crate::error::{Error, Result};
+use crate::package::Packages;
+use log::warn;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::path::Path;
+
+pub const LOG_NAME: &str = "build-time.log";
+
+/// Changes below that many seconds are not reported by default.
+pub const MIN_SECONDS: f64 = 1.0;
+/// Changes below that percentage are not reported by default.
+pub const MIN_PERCENT: f64 = 10.0;
+
+/// `build-time.log` for an input: the file itself, or
+/// `build/build-time.log` next to a show-info JSON or `.config` in an output
+/// directory. Mk files, trees and other inputs are not in an output
+/// directory, so no log is looked for.
+pub fn find_log(path: &str) -> Option<String> {
+    let path = Path::new(path);
+    if path.file_name().is_some_and(|x| x == LOG_NAME) {
+        return Some(path.to_string_lossy().to_string());
+    }
+    let in_output = path.file_name().is_some_and(|x| x == ".config")
+        || path.extension().is_some_and(|x| x == "json");
+    if !in_output || !path.is_file() {
+        return None;
+    }
+    let log = path.parent()?.join("build").join(LOG_NAME);
+    if log.is_file() {
+        Some(log.to_string_lossy().to_string())
+    } else {
+        None
+    }
+}
+
+/// Durations of package steps (download, extract, build...) in seconds.
+/// A step run several times (rebuilds) has the sum of its runs.
+#[derive(Debug, Default)]
+pub struct BuildTimes {
+    pub packages: BTreeMap<String, BTreeMap<String, f64>>,
+}
+
+impl BuildTimes {
+    pub fn read(path: &str) -> Result<BuildTimes> {
+        let content = std::fs::read_to_string(path).map_err(|err| Error::io(path, err))?;
+        BuildTimes::parse(path, &content)
+    }
+
+    /// Lines are `<timestamp>:<start|end>:<step>:<package>`, fields padded
+    /// with spaces.
+    pub fn parse(file: &str, content: &str) -> Result<BuildTimes> {
+        let mut result = BuildTimes::default();
+        let mut started: HashMap<(String, String), f64> = HashMap::new();
+        for (idx, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let error = |message| Error::parse(file, Some(idx + 1), message);
+            let fields: Vec<&str> = line.splitn(4, ':').map(|x| x.trim()).collect();
+            let (time, event, step, package) = match fields[..] {
+                [time, event, step, package] if !step.is_empty() && !package.is_empty() => {
+                    (time, event, step, package)
+                }
+                _ => return Err(error("expected '<time>:<start|end>:<step>:<package>'")),
+            };
+            let time: f64 = time.parse().map_err(|_| error("invalid timestamp"))?;
+            let key = (package.to_owned(), step.to_owned());
+            match event {
+                "start" => {
+                    started.insert(key, time);
+                }
+                "end" => match started.remove(&key) {
+                    Some(start) => {
+                        *result
+                            .packages
+                            .entry(key.0)
+                            .or_default()
+                            .entry(key.1)
+                            .or_default() += (time - start).max(0.0);
+                    }
+                    None => warn!(
+                        "{}:{}: {} {} ends before it starts",
+                        file,
+                        idx + 1,
+                        package,
+                        step
+                    ),
+                },
+                _ => return Err(error("expected 'start' or 'end'")),
+            }
+        }
+        for (package, step) in started.keys() {
+            warn!("{}: {} {} never ends", file, package, step);
+        }
+        Ok(result)
+    }
+
+    pub fn total(&self, name: &str) -> f64 {
+        self.packages
+            .get(name)
+            .map(|x| x.values().sum())
+            .unwrap_or_default()
+    }
+
+    pub fn total_time(&self) -> f64 {
+        self.packages.values().flat_map(|x| x.values()).sum()
+    }
+
+    /// Packages sorted by total time, slowest first.
+    pub fn slowest(&self, count: usize) -> Vec<(&String, f64)> {
+        let mut result: Vec<(&String, f64)> = self
+            .packages
+            .keys()
+            .map(|name| (name, self.total(name)))
+            .collect();
+        result.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+        result.truncate(count);
+        result
+    }
+
+    /// Package steps sorted by time, slowest first.
+    pub fn slowest_steps(&self, count: usize) -> Vec<(&String, &String, f64)> {
+        let mut result: Vec<(&String, &String, f64)> = self
+            .packages
+            .iter()
+            .flat_map(|(name, steps)| steps.iter().map(move |(step, time)| (name, step, *time)))
+            .collect();
+        result.sort_by(|a, b| b.2.total_cmp(&a.2).then(a.0.cmp(b.0)));
+        result.truncate(count);
+        result
+    }
+
+    /// Time of each step, all packages together.
+    pub fn step_totals(&self) -> BTreeMap<String, f64> {
+        let mut result = BTreeMap::new();
+        for (step, time) in self.packages.values().flatten() {
+            *result.entry(step.clone()).or_default() += time;
+        }
+        result
+    }
+}
+
+/// Build time of a package in two logs; `None` if it's not built.
+#[derive(Debug, Clone)]
+pub struct TimeChange {
+    pub name: String,
+    pub first: Option<f64>,
+    pub second: Option<f64>,
+    /// Versions of the package sets, if they differ.
+    pub versions: Option<(String, String)>,
+}
+
+impl TimeChange {
+    pub fn delta(&self) -> f64 {
+        self.second.unwrap_or_default() - self.first.unwrap_or_default()
+    }
+
+    /// Relative change, `None` for packages built only once.
+    pub fn percent(&self) -> Option<f64> {
+        match (self.first, self.second) {
+            (Some(first), Some(_)) if first > 0.0 => Some(self.delta() * 100.0 / first),
+            _ => None,
+        }
+    }
+}
+
+fn format_time(time: Option<f64>) -> String {
+    match time {
+        Some(time) => format!("{:.1}s", time),
+        None => "-".to_owned(),
+    }
+}
+
+impl Display for TimeChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = if self.delta() > 0.0 { "+" } else { "-" };
+        write!(f, "[{}] {}", sign, self.name)?;
+        if let Some((first, second)) = &self.versions {
+            write!(f, " {} -> {}", first, second)?;
+        }
+        write!(
+            f,
+            ": {} -> {} ({:+.1}s",
+            format_time(self.first),
+            format_time(self.second),
+            self.delta()
+        )?;
+        if let Some(percent) = self.percent() {
+            write!(f, ", {:+.0}%", percent)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Significant build time changes between two logs, biggest slowdowns first.
+#[derive(Debug, Default)]
+pub struct BuildTimeDiff {
+    pub first_total: f64,
+    pub second_total: f64,
+    pub changes: Vec<TimeChange>,
+}
+
+impl BuildTimeDiff {
+    /// Changes are kept if they are at least `min_seconds` and, for packages
+    /// of both logs, `min_percent`.
+    pub fn build(
+        first: &BuildTimes,
+        second: &BuildTimes,
+        min_seconds: f64,
+        min_percent: f64,
+    ) -> BuildTimeDiff {
+        let time = |times: &BuildTimes, name: &str| {
+            times.packages.contains_key(name).then(|| times.total(name))
+        };
+        let mut names: Vec<&String> = first.packages.keys().collect();
+        names.extend(second.packages.keys());
+        names.sort();
+        names.dedup();
+
+        let mut changes: Vec<TimeChange> = names
+            .into_iter()
+            .map(|name| TimeChange {
+                name: name.clone(),
+                first: time(first, name),
+                second: time(second, name),
+                versions: None,
+            })
+            .filter(|x| {
+                x.delta().abs() >= min_seconds && x.percent().is_none_or(|x| x.abs() >= min_percent)
+            })
+            .collect();
+        changes.sort_by(|a, b| b.delta().total_cmp(&a.delta()).then(a.name.cmp(&b.name)));
+        BuildTimeDiff {
+            first_total: first.total_time(),
+            second_total: second.total_time(),
+            changes,
+        }
+    }
+
+    /// Show version changes of the package sets next to time changes.
+    pub fn with_versions(mut self, first: &Packages, second: &Packages) -> BuildTimeDiff {
+        for change in &mut self.changes {
+            let version = |packages: &Packages| {
+                packages
+                    .get(&change.name)
+                    .and_then(|x| x.version.clone())
+                    .unwrap_or_else(|| "-".to_owned())
+            };
+            let versions = (version(first), version(second));
+            if versions.0 != versions.1 {
+                change.versions = Some(versions);
+            }
+        }
+        self
+    }
+
+    /// Slower packages, and packages built by the second log only.
+    pub fn regressions(&self) -> impl Iterator<Item = &TimeChange> {
+        self.changes.iter().filter(|x| x.delta() > 0.0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl Display for BuildTimeDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "[*] build time: {} -> {}",
+            format_time(Some(self.first_total)),
+            format_time(Some(self.second_total))
+        )?;
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::package::Package;
+    use crate::testutils::TempDir;
+
+    const LOG: &str = "\
+1618416236.00:start:download            :zlib
+1618416238.50:end  :download            :zlib
+1618416238.50:start:build               :zlib
+1618416248.50:end  :build               :zlib
+1618416248.50:start:build               :openssl
+1618416308.50:end  :build               :openssl
+1618416310.00:start:build               :zlib
+1618416312.00:end  :build               :zlib
+";
+
+    #[test]
+    fn parse() {
+        let times = BuildTimes::parse("build-time.log", LOG).unwrap();
+        assert_eq!(times.packages["zlib"]["download"], 2.5);
+        assert_eq!(times.packages["zlib"]["build"], 12.0);
+        assert_eq!(times.total("zlib"), 14.5);
+        assert_eq!(times.total_time(), 74.5);
+        assert_eq!(times.slowest(1), vec![(&"openssl".to_owned(), 60.0)]);
+        let steps = times.slowest_steps(2);
+        assert_eq!(
+            (steps[1].0.as_str(), steps[1].1.as_str()),
+            ("zlib", "build")
+        );
+        assert_eq!(times.step_totals()["build"], 72.0);
+
+        match BuildTimes::parse("build-time.log", "1.0:start:build\n") {
+            Err(Error::Parse { line, .. }) => assert_eq!(line, Some(1)),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn find_logs() {
+        let dir = TempDir::new("buildtime");
+        let output = dir.join("output");
+        std::fs::create_dir_all(output.join("build")).unwrap();
+        std::fs::write(output.join("build").join(LOG_NAME), LOG).unwrap();
+        std::fs::write(output.join("show-info.json"), "{}").unwrap();
+        std::fs::write(output.join("zlib.mk"), "").unwrap();
+        let log = output.join("build").join(LOG_NAME);
+
+        let find = |name: &str| find_log(output.join(name).to_str().unwrap());
+        assert_eq!(
+            find("show-info.json"),
+            Some(log.to_string_lossy().to_string())
+        );
+        assert_eq!(find("zlib.mk"), None);
+        assert_eq!(find("missing.json"), None);
+        assert_eq!(find_log(output.to_str().unwrap()), None);
+    }
+
+    #[test]
+    fn diff() {
+        let first = BuildTimes::parse("first", LOG).unwrap();
+        let second = BuildTimes::parse(
+            "second",
+            "0:start:build:zlib\n30:end:build:zlib\n30:start:build:openssl\n95:end:build:openssl\n95:start:build:nghttp2\n100:end:build:nghttp2\n",
+        )
+        .unwrap();
+
+        let packages = |version: &str| -> Packages {
+            let package = Package {
+                name: "zlib".to_owned(),
+                version: Some(version.to_owned()),
+                ..Default::default()
+            };
+            [("zlib".to_owned(), package)].into_iter().collect()
+        };
+        let diff = BuildTimeDiff::build(&first, &second, MIN_SECONDS, MIN_PERCENT)
+            .with_versions(&packages("1.2.13"), &packages("1.3"));
+        // openssl is 8% slower only
+        assert_eq!(
+            diff.to_string(),
+            "[*] build time: 74.5s -> 100.0s\n\
+             [+] zlib 1.2.13 -> 1.3: 14.5s -> 30.0s (+15.5s, +107%)\n\
+             [+] nghttp2: - -> 5.0s (+5.0s)\n"
+        );
+        assert_eq!(diff.regressions().count(), 2);
+    }
+}
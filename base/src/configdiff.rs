@@ -0,0 +1,316 @@
+use crate::defconfig;
+use crate::error::Result;
+use crate::kconfig::{self, Kconfig};
+use crate::sbom;
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::Path;
+
+const PACKAGE_PREFIX: &str = "BR2_PACKAGE_";
+
+/// Group for symbols that don't belong to a package.
+pub const GLOBAL_GROUP: &str = "config";
+
+/// Kconfig symbol table of a `.config` or a defconfig. `# BR2_FOO is not set`
+/// is kept as `n`; a missing symbol compares equal to it.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub symbols: BTreeMap<String, String>,
+    /// Full `.config` (or an expanded defconfig) rather than a defconfig with
+    /// default values omitted.
+    pub full: bool,
+}
+
+impl Config {
+    /// A defconfig inside a Buildroot tree gets default values of the
+    /// symbols it doesn't set from the `Config.in` files of that tree.
+    pub fn read(path: &str) -> Result<Config> {
+        let symbols: BTreeMap<String, String> =
+            defconfig::read_symbols(path)?.into_iter().collect();
+        if !defconfig::is_defconfig(path) {
+            return Ok(Config {
+                symbols,
+                full: true,
+            });
+        }
+
+        let root = match kconfig::find_root(path) {
+            Some(root) => root,
+            None => {
+                return Ok(Config {
+                    symbols,
+                    full: false,
+                })
+            }
+        };
+        match Kconfig::read(&root) {
+            Ok(tree) => {
+                info!("expand {} with defaults of {}", path, root.display());
+                Ok(Config {
+                    symbols: tree.expand(&symbols),
+                    full: true,
+                })
+            }
+            Err(err) => {
+                warn!("{}: defaults are not expanded: {}", path, err);
+                Ok(Config {
+                    symbols,
+                    full: false,
+                })
+            }
+        }
+    }
+}
+
+/// Config files are recognized by name: `.config` or a defconfig.
+pub fn is_config(path: &str) -> bool {
+    defconfig::is_defconfig(path) || Path::new(path).file_name().is_some_and(|x| x == ".config")
+}
+
+/// `.config` for an input: the file itself, or a `.config` next to a
+/// show-info JSON. SBOMs and other inputs have none.
+pub fn find_config(path: &str) -> Option<String> {
+    if is_config(path) {
+        return Some(path.to_owned());
+    }
+    if !path.ends_with(".json") || !Path::new(path).is_file() || sbom::detect(path).is_some() {
+        return None;
+    }
+    let config = Path::new(path).parent()?.join(".config");
+    if config.is_file() {
+        config.to_str().map(|x| x.to_owned())
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SymbolDiff {
+    Added {
+        name: String,
+        value: String,
+    },
+    Removed {
+        name: String,
+        value: String,
+    },
+    Changed {
+        name: String,
+        first: String,
+        second: String,
+    },
+}
+
+/// Changed symbols grouped by package name (or `GLOBAL_GROUP`).
+pub type ConfigDiffs = BTreeMap<String, Vec<SymbolDiff>>;
+
+/// Package a symbol belongs to: the shortest `BR2_PACKAGE_<NAME>` prefix that
+/// is enabled in one of the configs, so `BR2_PACKAGE_LIBOPENSSL_BIN` belongs
+/// to `libopenssl`. Package options are bool symbols too, so there's no way to
+/// tell `BR2_PACKAGE_FOO_BAR` option from `foo-bar` package if both are on.
+fn group(name: &str, first: &Config, second: &Config) -> String {
+    let rest = match name.strip_prefix(PACKAGE_PREFIX) {
+        Some(rest) => rest,
+        None => return GLOBAL_GROUP.to_owned(),
+    };
+
+    let is_package = |symbol: &str| {
+        first.symbols.get(symbol).is_some_and(|x| x == "y")
+            || second.symbols.get(symbol).is_some_and(|x| x == "y")
+    };
+    let package = rest
+        .match_indices('_')
+        .map(|(idx, _)| &rest[..idx])
+        .chain(std::iter::once(rest))
+        .find(|x| is_package(&format!("{}{}", PACKAGE_PREFIX, x)))
+        .unwrap_or(rest);
+    package.to_lowercase().replace('_', "-")
+}
+
+/// Compare two symbol tables. If only one side is a defconfig, the other one
+/// is a full `.config` with every default expanded, so only the symbols the
+/// defconfig sets are compared; otherwise symbols missing from a defconfig
+/// would show up as noise.
+pub fn build(first: &Config, second: &Config) -> ConfigDiffs {
+    let mut result = ConfigDiffs::new();
+    let mut push = |diff: SymbolDiff, name: &str| {
+        result
+            .entry(group(name, first, second))
+            .or_default()
+            .push(diff);
+    };
+
+    let skip_added = second.full && !first.full;
+    let skip_removed = first.full && !second.full;
+
+    // a missing symbol is the same as `n`
+    for (name, value) in &first.symbols {
+        match second.symbols.get(name) {
+            Some(other) if other != value => push(
+                SymbolDiff::Changed {
+                    name: name.clone(),
+                    first: value.clone(),
+                    second: other.clone(),
+                },
+                name,
+            ),
+            Some(_) => {}
+            None if skip_removed || value == "n" => {}
+            None => push(
+                SymbolDiff::Removed {
+                    name: name.clone(),
+                    value: value.clone(),
+                },
+                name,
+            ),
+        }
+    }
+
+    for (name, value) in &second.symbols {
+        if !skip_added && value != "n" && !first.symbols.contains_key(name) {
+            push(
+                SymbolDiff::Added {
+                    name: name.clone(),
+                    value: value.clone(),
+                },
+                name,
+            );
+        }
+    }
+    result
+}
+
+impl Display for SymbolDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SymbolDiff::Added { name, value } => write!(f, "      + {}={}", name, value),
+            SymbolDiff::Removed { name, value } => write!(f, "      - {}={}", name, value),
+            SymbolDiff::Changed {
+                name,
+                first,
+                second,
+            } => write!(f, "      * {}: {} -> {}", name, first, second),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutils::TempDir;
+
+    fn config(symbols: &[(&str, &str)], full: bool) -> Config {
+        Config {
+            symbols: symbols
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            full,
+        }
+    }
+
+    #[test]
+    fn groups() {
+        let first = config(
+            &[
+                ("BR2_PACKAGE_OPENSSL", "y"),
+                ("BR2_PACKAGE_LIBOPENSSL", "y"),
+                ("BR2_PACKAGE_LIBOPENSSL_BIN", "y"),
+            ],
+            true,
+        );
+        let second = config(&[("BR2_PACKAGE_PYTHON3", "y")], true);
+        assert_eq!(
+            group("BR2_PACKAGE_LIBOPENSSL_BIN", &first, &second),
+            "libopenssl"
+        );
+        assert_eq!(group("BR2_PACKAGE_OPENSSL", &first, &second), "openssl");
+        assert_eq!(group("BR2_PACKAGE_PYTHON3_SSL", &first, &second), "python3");
+        assert_eq!(group("BR2_PACKAGE_FOO_BAR", &first, &second), "foo-bar");
+        assert_eq!(
+            group("BR2_TARGET_GENERIC_HOSTNAME", &first, &second),
+            "config"
+        );
+    }
+
+    #[test]
+    fn full_configs() {
+        let first = config(
+            &[
+                ("BR2_PACKAGE_OPENSSL", "y"),
+                ("BR2_PACKAGE_OPENSSL_BIN", "y"),
+                ("BR2_PACKAGE_OPENSSL_ENGINES", "y"),
+                ("BR2_PACKAGE_XZ", "n"),
+                ("BR2_TARGET_GENERIC_HOSTNAME", "buildroot"),
+            ],
+            true,
+        );
+        let second = config(
+            &[
+                ("BR2_PACKAGE_OPENSSL", "y"),
+                ("BR2_PACKAGE_OPENSSL_ENGINES", "n"),
+                ("BR2_PACKAGE_ZLIB", "y"),
+                ("BR2_PACKAGE_LZ4", "n"),
+                ("BR2_TARGET_GENERIC_HOSTNAME", "board"),
+            ],
+            true,
+        );
+        let diffs = build(&first, &second);
+        assert_eq!(diffs.len(), 3);
+        assert_eq!(
+            diffs["openssl"],
+            vec![
+                SymbolDiff::Removed {
+                    name: "BR2_PACKAGE_OPENSSL_BIN".to_owned(),
+                    value: "y".to_owned()
+                },
+                SymbolDiff::Changed {
+                    name: "BR2_PACKAGE_OPENSSL_ENGINES".to_owned(),
+                    first: "y".to_owned(),
+                    second: "n".to_owned()
+                }
+            ]
+        );
+        assert_eq!(
+            diffs["config"][0].to_string(),
+            "      * BR2_TARGET_GENERIC_HOSTNAME: buildroot -> board"
+        );
+        assert_eq!(diffs["zlib"][0].to_string(), "      + BR2_PACKAGE_ZLIB=y");
+    }
+
+    #[test]
+    fn defconfig_against_config() {
+        let first = config(&[("BR2_PACKAGE_ZLIB", "y")], false);
+        let second = config(
+            &[("BR2_PACKAGE_ZLIB", "y"), ("BR2_TOOLCHAIN_BUILDROOT", "y")],
+            true,
+        );
+        assert!(build(&first, &second).is_empty());
+        assert!(build(&second, &first).is_empty());
+
+        let first = config(&[("BR2_PACKAGE_ZLIB", "y"), ("BR2_PACKAGE_XZ", "y")], false);
+        let diffs = build(&first, &second);
+        assert_eq!(diffs.len(), 1);
+        assert!(matches!(diffs["xz"][0], SymbolDiff::Removed { .. }));
+    }
+
+    #[test]
+    fn find_configs() {
+        let dir = TempDir::new("configdiff-find");
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        std::fs::write(dir.join(".config"), "BR2_PACKAGE_ZLIB=y\n").unwrap();
+        std::fs::write(dir.join("show-info.json"), "{}").unwrap();
+        std::fs::write(dir.join("sbom.json"), "{\"spdxVersion\": \"SPDX-2.3\"}").unwrap();
+        std::fs::write(dir.join("zlib.mk"), "").unwrap();
+
+        assert_eq!(find_config(&path("show-info.json")), Some(path(".config")));
+        assert_eq!(find_config(&path(".config")), Some(path(".config")));
+        assert_eq!(find_config(&path("sbom.json")), None);
+        assert_eq!(find_config(&path("zlib.mk")), None);
+        assert_eq!(find_config(&path("missing.json")), None);
+
+        let config = Config::read(&path(".config")).unwrap();
+        assert!(config.full);
+    }
+}
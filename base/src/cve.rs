@@ -0,0 +1,559 @@
+use crate::error::{Error, Result};
+use crate::json::{items, text};
+use crate::package::{Package, Packages};
+use flate2::read::GzDecoder;
+use log::{debug, info, warn};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Display;
+use std::io::Read;
+use std::path::Path;
+use walkdir::WalkDir;
+
+#[derive(Debug, PartialEq)]
+enum Chunk<'a> {
+    Number(u64),
+    Text(&'a str),
+}
+
+fn chunks(version: &str) -> Vec<Chunk<'_>> {
+    let mut result = Vec::new();
+    let mut rest = version.trim().trim_start_matches(['v', 'V']);
+    while let Some(from) = rest.find(|x: char| x.is_ascii_alphanumeric()) {
+        rest = &rest[from..];
+        let digits = rest.starts_with(|x: char| x.is_ascii_digit());
+        let end = rest
+            .find(|x: char| !x.is_ascii_alphanumeric() || x.is_ascii_digit() != digits)
+            .unwrap_or(rest.len());
+        let (chunk, tail) = rest.split_at(end);
+        result.push(match chunk.parse::<u64>() {
+            Ok(number) if digits => Chunk::Number(number),
+            _ => Chunk::Text(chunk),
+        });
+        rest = tail;
+    }
+    result
+}
+
+/// Compare versions the way upstream projects usually number them: numbers
+/// numerically, letters alphabetically, separators ignored. A version with
+/// a letter suffix is older than the bare one (`1.0rc1` < `1.0`), any other
+/// extra part makes it newer (`1.0` < `1.0.1`).
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (a, b) = (chunks(a), chunks(b));
+    for idx in 0..a.len().max(b.len()) {
+        let order = match (a.get(idx), b.get(idx)) {
+            (Some(Chunk::Number(x)), Some(Chunk::Number(y))) => x.cmp(y),
+            (Some(Chunk::Text(x)), Some(Chunk::Text(y))) => x.cmp(y),
+            (Some(Chunk::Number(_)), Some(Chunk::Text(_))) => Ordering::Greater,
+            (Some(Chunk::Text(_)), Some(Chunk::Number(_))) => Ordering::Less,
+            (Some(Chunk::Text(_)), None) => Ordering::Less,
+            (None, Some(Chunk::Text(_))) => Ordering::Greater,
+            (Some(_), None) => Ordering::Greater,
+            (None, Some(_)) => Ordering::Less,
+            (None, None) => Ordering::Equal,
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+    Ordering::Equal
+}
+
+/// Fields of a CPE 2.3 formatted string, `\:` escapes kept as is.
+fn cpe_fields(cpe: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut from = 0;
+    let mut escaped = false;
+    for (idx, symb) in cpe.char_indices() {
+        match symb {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            ':' => {
+                result.push(&cpe[from..idx]);
+                from = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    result.push(&cpe[from..]);
+    result
+}
+
+/// Vendor, product and version of a CPE 2.3 id.
+fn parse_cpe(cpe: &str) -> Option<(&str, &str, &str)> {
+    match cpe_fields(cpe).as_slice() {
+        ["cpe", "2.3", _, vendor, product, version, ..] => Some((vendor, product, version)),
+        _ => None,
+    }
+}
+
+/// A vulnerable product (and versions) of a CVE.
+#[derive(Debug, Clone, Default)]
+struct CpeMatch {
+    cve: String,
+    version: String,
+    start_including: Option<String>,
+    start_excluding: Option<String>,
+    end_including: Option<String>,
+    end_excluding: Option<String>,
+}
+
+impl CpeMatch {
+    /// `*` is any version within the range bounds, `-` (not applicable)
+    /// matches no version.
+    fn is_match(&self, version: &str) -> bool {
+        match self.version.as_str() {
+            "*" | "" => {}
+            "-" => return false,
+            exact => return compare_versions(version, exact) == Ordering::Equal,
+        }
+        let order = |bound: &Option<String>| bound.as_deref().map(|x| compare_versions(version, x));
+        !matches!(order(&self.start_including), Some(Ordering::Less))
+            && !matches!(
+                order(&self.start_excluding),
+                Some(Ordering::Less) | Some(Ordering::Equal)
+            )
+            && !matches!(order(&self.end_including), Some(Ordering::Greater))
+            && !matches!(
+                order(&self.end_excluding),
+                Some(Ordering::Greater) | Some(Ordering::Equal)
+            )
+    }
+}
+
+/// Vulnerable CPE matches of configuration nodes, children included. Nodes
+/// are flattened: platform conditions of `AND` nodes are not checked, a
+/// package is reported if its own CPE matches.
+fn collect_matches(cve: &str, node: &Value, result: &mut Vec<(String, String, CpeMatch)>) {
+    for child in items(node, "children") {
+        collect_matches(cve, child, result);
+    }
+    // 1.1 feeds use `cpe_match`/`cpe23Uri`, 2.0 ones `cpeMatch`/`criteria`
+    for item in items(node, "cpe_match").chain(items(node, "cpeMatch")) {
+        if item.get("vulnerable").and_then(|x| x.as_bool()) == Some(false) {
+            continue;
+        }
+        let cpe = match text(item, "cpe23Uri").or_else(|| text(item, "criteria")) {
+            Some(cpe) => cpe,
+            None => continue,
+        };
+        if let Some((vendor, product, version)) = parse_cpe(&cpe) {
+            let entry = CpeMatch {
+                cve: cve.to_owned(),
+                version: version.to_owned(),
+                start_including: text(item, "versionStartIncluding"),
+                start_excluding: text(item, "versionStartExcluding"),
+                end_including: text(item, "versionEndIncluding"),
+                end_excluding: text(item, "versionEndExcluding"),
+            };
+            result.push((vendor.to_owned(), product.to_owned(), entry));
+        }
+    }
+}
+
+/// CVE id and configuration nodes of every vulnerability of an NVD feed,
+/// either 1.1 (`CVE_Items`) or 2.0 (`vulnerabilities`).
+fn feed_items(data: &Value) -> Vec<(String, Vec<&Value>)> {
+    let mut result = Vec::new();
+    for item in items(data, "CVE_Items") {
+        let id = item
+            .get("cve")
+            .and_then(|x| x.get("CVE_data_meta"))
+            .and_then(|x| text(x, "ID"));
+        let nodes = item
+            .get("configurations")
+            .map(|x| items(x, "nodes").collect())
+            .unwrap_or_default();
+        if let Some(id) = id {
+            result.push((id, nodes));
+        }
+    }
+    for item in items(data, "vulnerabilities") {
+        let cve = item.get("cve").unwrap_or(item);
+        let nodes = items(cve, "configurations")
+            .flat_map(|x| items(x, "nodes"))
+            .collect();
+        if let Some(id) = text(cve, "id") {
+            result.push((id, nodes));
+        }
+    }
+    result
+}
+
+/// Known vulnerabilities by package, ignored CVEs excluded.
+pub type Vulnerabilities = BTreeMap<String, BTreeSet<String>>;
+
+/// CVEs of local NVD JSON feeds, indexed by CPE vendor and product.
+#[derive(Debug, Default)]
+pub struct CveDb {
+    matches: HashMap<(String, String), Vec<CpeMatch>>,
+    cves: usize,
+}
+
+impl CveDb {
+    pub fn new() -> CveDb {
+        CveDb::default()
+    }
+
+    /// Load a feed file (`.json` or `.json.gz`) or all feeds of a mirror
+    /// directory.
+    pub fn load(path: &str) -> Result<CveDb> {
+        let mut db = CveDb::new();
+        if Path::new(path).is_dir() {
+            let feeds = WalkDir::new(path)
+                .sort_by_file_name()
+                .into_iter()
+                .filter_map(|x| x.ok())
+                .filter(|x| x.file_type().is_file())
+                .map(|x| x.path().to_string_lossy().to_string())
+                .filter(|x| x.ends_with(".json") || x.ends_with(".json.gz"));
+            for feed in feeds {
+                db.add_feed(&feed)?;
+            }
+        } else {
+            db.add_feed(path)?;
+        }
+        info!("{} CVE(s) loaded from {}", db.cves, path);
+        Ok(db)
+    }
+
+    pub fn add_feed(&mut self, path: &str) -> Result<()> {
+        debug!("load {}", path);
+        let mut data = String::new();
+        let file = std::fs::File::open(path).map_err(|err| Error::io(path, err))?;
+        if path.ends_with(".gz") {
+            GzDecoder::new(file).read_to_string(&mut data)
+        } else {
+            std::io::BufReader::new(file).read_to_string(&mut data)
+        }
+        .map_err(|err| Error::io(path, err))?;
+        let data: Value = serde_json::from_str(&data)
+            .map_err(|err| Error::parse(path, Some(err.line()), &err.to_string()))?;
+
+        let items = feed_items(&data);
+        if items.is_empty() {
+            warn!("{}: no vulnerabilities found", path);
+        }
+        for (id, nodes) in items {
+            let mut matches = Vec::new();
+            for node in nodes {
+                collect_matches(&id, node, &mut matches);
+            }
+            for (vendor, product, entry) in matches {
+                self.matches
+                    .entry((vendor, product))
+                    .or_default()
+                    .push(entry);
+            }
+            self.cves += 1;
+        }
+        Ok(())
+    }
+
+    /// Number of loaded CVEs.
+    pub fn len(&self) -> usize {
+        self.cves
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cves == 0
+    }
+
+    /// CVEs affecting the package, those it ignores excluded. Packages
+    /// without a CPE id or a version can't be checked.
+    pub fn check_package(&self, package: &Package) -> BTreeSet<String> {
+        let cpe = match package.cpe_id.as_deref().and_then(parse_cpe) {
+            Some(cpe) => cpe,
+            None => return BTreeSet::new(),
+        };
+        let (vendor, product, version) = cpe;
+        let version = match version {
+            "-" => return BTreeSet::new(),
+            "*" | "" => match package.version.as_deref() {
+                Some(version) => version,
+                None => return BTreeSet::new(),
+            },
+            version => version,
+        };
+
+        self.matches
+            .get(&(vendor.to_owned(), product.to_owned()))
+            .into_iter()
+            .flatten()
+            .filter(|x| x.is_match(version))
+            .filter(|x| !package.ignore_cves.contains(&x.cve))
+            .map(|x| x.cve.clone())
+            .collect()
+    }
+
+    pub fn check(&self, packages: &Packages) -> Vulnerabilities {
+        packages
+            .values()
+            .map(|x| (x.name.clone(), self.check_package(x)))
+            .filter(|(_, cves)| !cves.is_empty())
+            .collect()
+    }
+}
+
+/// Take `_IGNORE_CVES` lists from another package set (mk files) for
+/// packages that have none, like packages of a show-info JSON of an older
+/// Buildroot.
+pub fn merge_ignored(packages: &mut Packages, other: &Packages) {
+    for (name, package) in packages.iter_mut() {
+        if let Some(other) = other.get(name) {
+            if package.ignore_cves.is_empty() {
+                package.ignore_cves = other.ignore_cves.clone();
+            }
+        }
+    }
+}
+
+/// Vulnerabilities that differ between two package sets.
+#[derive(Debug, Default)]
+pub struct CveDiff {
+    /// CVEs of the first set gone in the second one.
+    pub fixed: Vulnerabilities,
+    /// CVEs of the second set the first one doesn't have.
+    pub introduced: Vulnerabilities,
+}
+
+fn difference(first: &Vulnerabilities, second: &Vulnerabilities) -> Vulnerabilities {
+    let empty = BTreeSet::new();
+    first
+        .iter()
+        .map(|(name, cves)| {
+            let other = second.get(name).unwrap_or(&empty);
+            (name.clone(), cves.difference(other).cloned().collect())
+        })
+        .filter(|(_, cves): &(String, BTreeSet<String>)| !cves.is_empty())
+        .collect()
+}
+
+impl CveDiff {
+    pub fn build(first: &Vulnerabilities, second: &Vulnerabilities) -> CveDiff {
+        CveDiff {
+            fixed: difference(first, second),
+            introduced: difference(second, first),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fixed.is_empty() && self.introduced.is_empty()
+    }
+}
+
+fn write_vulnerabilities(
+    f: &mut std::fmt::Formatter<'_>,
+    vulnerabilities: &Vulnerabilities,
+) -> std::fmt::Result {
+    for (name, cves) in vulnerabilities {
+        let cves: Vec<&str> = cves.iter().map(|x| x.as_str()).collect();
+        writeln!(f, "      {}: {}", name, cves.join(" "))?;
+    }
+    Ok(())
+}
+
+impl Display for CveDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.fixed.is_empty() {
+            writeln!(f, "[-] fixed CVEs")?;
+            write_vulnerabilities(f, &self.fixed)?;
+        }
+        if !self.introduced.is_empty() {
+            writeln!(f, "[+] introduced CVEs")?;
+            write_vulnerabilities(f, &self.introduced)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutils::TempDir;
+
+    const FEED_V11: &str = r#"{
+  "CVE_data_type": "CVE",
+  "CVE_Items": [
+    {
+      "cve": { "CVE_data_meta": { "ID": "CVE-2022-37434" } },
+      "configurations": {
+        "nodes": [
+          {
+            "operator": "OR",
+            "children": [],
+            "cpe_match": [
+              {
+                "vulnerable": true,
+                "cpe23Uri": "cpe:2.3:a:zlib:zlib:*:*:*:*:*:*:*:*",
+                "versionEndIncluding": "1.2.12"
+              }
+            ]
+          }
+        ]
+      }
+    },
+    {
+      "cve": { "CVE_data_meta": { "ID": "CVE-2018-25032" } },
+      "configurations": {
+        "nodes": [
+          {
+            "operator": "OR",
+            "cpe_match": [
+              {
+                "vulnerable": true,
+                "cpe23Uri": "cpe:2.3:a:zlib:zlib:*:*:*:*:*:*:*:*",
+                "versionEndExcluding": "1.2.12"
+              }
+            ]
+          }
+        ]
+      }
+    }
+  ]
+}"#;
+
+    const FEED_V20: &str = r#"{
+  "format": "NVD_CVE",
+  "version": "2.0",
+  "vulnerabilities": [
+    {
+      "cve": {
+        "id": "CVE-2023-38545",
+        "configurations": [
+          {
+            "nodes": [
+              {
+                "operator": "OR",
+                "negate": false,
+                "cpeMatch": [
+                  {
+                    "vulnerable": true,
+                    "criteria": "cpe:2.3:a:haxx:libcurl:*:*:*:*:*:*:*:*",
+                    "versionStartIncluding": "7.69.0",
+                    "versionEndExcluding": "8.4.0"
+                  },
+                  {
+                    "vulnerable": false,
+                    "criteria": "cpe:2.3:a:haxx:libcurl:8.4.0:*:*:*:*:*:*:*"
+                  }
+                ]
+              }
+            ]
+          }
+        ]
+      }
+    }
+  ]
+}"#;
+
+    fn package(name: &str, version: &str, cpe: &str) -> Package {
+        Package {
+            name: name.to_owned(),
+            version: Some(version.to_owned()),
+            cpe_id: Some(cpe.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    fn db() -> CveDb {
+        let dir = TempDir::new("nvd");
+        std::fs::write(dir.join("nvdcve-1.1-2022.json"), FEED_V11).unwrap();
+        std::fs::write(dir.join("nvdcve-2.0-2023.json"), FEED_V20).unwrap();
+        let db = CveDb::load(dir.to_str().unwrap()).unwrap();
+        db
+    }
+
+    #[test]
+    fn versions() {
+        assert_eq!(compare_versions("1.2.11", "1.2.12"), Ordering::Less);
+        assert_eq!(compare_versions("1.10", "1.9"), Ordering::Greater);
+        assert_eq!(compare_versions("v1.2", "1.2"), Ordering::Equal);
+        assert_eq!(compare_versions("1.2", "1.2.1"), Ordering::Less);
+        assert_eq!(compare_versions("1.2rc1", "1.2"), Ordering::Less);
+        assert_eq!(compare_versions("8.4.0", "8_4_0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.1.1w", "1.1.1k"), Ordering::Greater);
+    }
+
+    #[test]
+    fn cpe() {
+        assert_eq!(
+            parse_cpe("cpe:2.3:a:haxx:libcurl:8.4.0:*:*:*:*:*:*:*"),
+            Some(("haxx", "libcurl", "8.4.0"))
+        );
+        assert_eq!(
+            parse_cpe(r"cpe:2.3:a:foo\:bar:baz:1.0:*:*:*:*:*:*:*"),
+            Some((r"foo\:bar", "baz", "1.0"))
+        );
+        assert_eq!(parse_cpe("zlib"), None);
+    }
+
+    #[test]
+    fn check() {
+        let db = db();
+        assert_eq!(db.len(), 3);
+
+        let zlib = package("zlib", "1.2.11", "cpe:2.3:a:zlib:zlib:1.2.11:*:*:*:*:*:*:*");
+        assert_eq!(
+            db.check_package(&zlib).into_iter().collect::<Vec<String>>(),
+            vec!["CVE-2018-25032", "CVE-2022-37434"]
+        );
+        let zlib = package("zlib", "1.2.12", "cpe:2.3:a:zlib:zlib:*:*:*:*:*:*:*:*");
+        assert_eq!(db.check_package(&zlib).len(), 1);
+        let zlib = package("zlib", "1.3", "cpe:2.3:a:zlib:zlib:1.3:*:*:*:*:*:*:*");
+        assert!(db.check_package(&zlib).is_empty());
+
+        let mut curl = package(
+            "libcurl",
+            "8.3.0",
+            "cpe:2.3:a:haxx:libcurl:8.3.0:*:*:*:*:*:*:*",
+        );
+        assert_eq!(db.check_package(&curl).len(), 1);
+        curl.ignore_cves = vec!["CVE-2023-38545".to_owned()];
+        assert!(db.check_package(&curl).is_empty());
+
+        let mut unknown = package("app", "1.0", "");
+        unknown.cpe_id = None;
+        assert!(db.check_package(&unknown).is_empty());
+
+        let zlib = package("zlib", "1.2.11", "cpe:2.3:a:zlib:zlib:-:*:*:*:*:*:*:*");
+        assert!(db.check_package(&zlib).is_empty());
+        let na = CpeMatch {
+            cve: "CVE-2020-0001".to_owned(),
+            version: "-".to_owned(),
+            start_including: None,
+            start_excluding: None,
+            end_including: None,
+            end_excluding: None,
+        };
+        assert!(!na.is_match("1.0"));
+    }
+
+    #[test]
+    fn diff() {
+        let db = db();
+        let mut first = Packages::new();
+        let mut second = Packages::new();
+        for (zlib, curl, packages) in [
+            ("1.2.11", "8.4.0", &mut first),
+            ("1.3", "8.3.0", &mut second),
+        ] {
+            packages.insert(
+                "zlib".to_owned(),
+                package("zlib", zlib, "cpe:2.3:a:zlib:zlib:*:*:*:*:*:*:*:*"),
+            );
+            packages.insert(
+                "libcurl".to_owned(),
+                package("libcurl", curl, "cpe:2.3:a:haxx:libcurl:*:*:*:*:*:*:*:*"),
+            );
+        }
+
+        let diff = CveDiff::build(&db.check(&first), &db.check(&second));
+        assert_eq!(
+            diff.to_string(),
+            "[-] fixed CVEs\n      zlib: CVE-2018-25032 CVE-2022-37434\n[+] introduced CVEs\n      libcurl: CVE-2023-38545\n"
+        );
+    }
+}
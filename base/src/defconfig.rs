@@ -0,0 +1,309 @@
+use crate::error::{Error, Result};
+use crate::package::{FetchMethod, Package, PackageReader, PackageSource, Packages};
+use log::{debug, warn};
+use std::fs::OpenOptions;
+use std::io::Write;
+
+/// Package names of defconfig components that can't be derived from the
+/// Kconfig symbol.
+const COMPONENT_NAMES: [(&str, &str); 1] = [("LINUX_KERNEL", "linux")];
+
+const REPO_URL_SUFFIX: &str = "_CUSTOM_REPO_URL";
+const REPO_VERSION_SUFFIX: &str = "_CUSTOM_REPO_VERSION";
+
+/// Symbols selecting the fetch method of a custom repository.
+const REPO_METHODS: [(&str, FetchMethod); 3] = [
+    ("_CUSTOM_GIT", FetchMethod::Git),
+    ("_CUSTOM_HG", FetchMethod::Hg),
+    ("_CUSTOM_SVN", FetchMethod::Svn),
+];
+
+/// Symbol and its value. Strings are unquoted, `# BR2_FOO is not set` is
+/// read as `n`.
+pub type Symbol = (String, String);
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    let inner = match value.strip_prefix('"').and_then(|x| x.strip_suffix('"')) {
+        Some(inner) => inner,
+        None => return value.to_owned(),
+    };
+
+    let mut result = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(symb) = chars.next() {
+        if symb == '\\' {
+            if let Some(next) = chars.next() {
+                result.push(next);
+            }
+        } else {
+            result.push(symb);
+        }
+    }
+    result
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn parse_line(line: &str) -> Option<Symbol> {
+    let line = line.trim();
+    if let Some(comment) = line.strip_prefix('#') {
+        let name = comment.trim().strip_suffix(" is not set")?.trim();
+        if name.starts_with("BR2_") {
+            return Some((name.to_owned(), "n".to_owned()));
+        }
+        return None;
+    }
+
+    let (key, value) = line.split_once('=')?;
+    let key = key.trim();
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return None;
+    }
+    Some((key.to_owned(), unquote(value)))
+}
+
+/// Read all symbols of a defconfig (or a full `.config`) in file order.
+pub fn read_symbols(path: &str) -> Result<Vec<Symbol>> {
+    let data = std::fs::read_to_string(path).map_err(|err| Error::io(path, err))?;
+    Ok(data.lines().filter_map(parse_line).collect())
+}
+
+/// Package name for a component prefix: `BR2_TARGET_UBOOT` is `uboot`,
+/// `BR2_TARGET_ARM_TRUSTED_FIRMWARE` is `arm-trusted-firmware`.
+fn component_name(prefix: &str) -> String {
+    let prefix = prefix.trim_start_matches("BR2_");
+    let prefix = prefix.strip_prefix("TARGET_").unwrap_or(prefix);
+    COMPONENT_NAMES
+        .iter()
+        .find(|(symbol, _)| *symbol == prefix)
+        .map(|(_, name)| (*name).to_owned())
+        .unwrap_or_else(|| prefix.to_lowercase().replace('_', "-"))
+}
+
+fn find_value<'a>(symbols: &'a [Symbol], key: &str) -> Option<&'a str> {
+    symbols
+        .iter()
+        .rev()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.as_str())
+        .filter(|v| !v.is_empty())
+}
+
+fn make_source(symbols: &[Symbol], prefix: &str, url: &str) -> Option<PackageSource> {
+    let method = REPO_METHODS
+        .iter()
+        .find(|(suffix, _)| find_value(symbols, &format!("{}{}", prefix, suffix)) == Some("y"))
+        .map(|(_, method)| *method)
+        .or_else(|| FetchMethod::guess(url))?;
+    Some(PackageSource::new(method, url))
+}
+
+/// Components with a custom repository (`<PREFIX>_CUSTOM_REPO_URL` and
+/// `<PREFIX>_CUSTOM_REPO_VERSION`), like the kernel, U-Boot or TF-A.
+fn read_components(path: &str, symbols: &[Symbol]) -> Packages {
+    let mut result = Packages::new();
+    for (key, url) in symbols {
+        let prefix = match key.strip_suffix(REPO_URL_SUFFIX) {
+            Some(prefix) if !url.is_empty() => prefix,
+            _ => continue,
+        };
+
+        let version = find_value(symbols, &format!("{}{}", prefix, REPO_VERSION_SUFFIX));
+        let name = component_name(prefix);
+        debug!("{}: {} from {}", path, name, url);
+        let sources = match make_source(symbols, prefix, url) {
+            Some(source) => vec![source],
+            None => {
+                warn!("{}: unknown fetch method for {}", path, url);
+                Vec::new()
+            }
+        };
+
+        result.insert(
+            name.clone(),
+            Package {
+                name,
+                version: version.map(|x| x.to_owned()),
+                sources,
+                location: Some(path.to_owned()),
+                ..Default::default()
+            },
+        );
+    }
+    result
+}
+
+/// Defconfigs are recognized by name: `configs/*_defconfig` or `defconfig`.
+pub fn is_defconfig(path: &str) -> bool {
+    std::path::Path::new(path)
+        .file_name()
+        .and_then(|x| x.to_str())
+        .is_some_and(|x| x == "defconfig" || x.ends_with("_defconfig"))
+}
+
+pub struct DefconfigReader {
+    path: String,
+}
+
+impl DefconfigReader {
+    pub fn new(path: &str) -> DefconfigReader {
+        DefconfigReader {
+            path: path.to_owned(),
+        }
+    }
+}
+
+impl PackageReader for DefconfigReader {
+    type Error = Error;
+    fn read(&mut self) -> Result<Packages> {
+        let symbols = read_symbols(&self.path)?;
+        Ok(read_components(&self.path, &symbols))
+    }
+}
+
+/// Set the custom repository version of the component `name` in a
+/// defconfig. Only the version line is rewritten, the rest of the file is
+/// kept as is.
+pub fn write_version(path: &str, name: &str, version: &str) -> Result<()> {
+    let input = std::fs::read_to_string(path).map_err(|err| Error::io(path, err))?;
+    let symbols: Vec<Symbol> = input.lines().filter_map(parse_line).collect();
+    let prefix = symbols
+        .iter()
+        .filter_map(|(key, _)| key.strip_suffix(REPO_URL_SUFFIX))
+        .find(|prefix| component_name(prefix) == name)
+        .ok_or_else(|| Error::parse(path, None, &format!("no custom repository for {}", name)))?;
+    let key = format!("{}{}", prefix, REPO_VERSION_SUFFIX);
+    let line = format!("{}={}", key, quote(version));
+
+    let mut found = false;
+    let mut output: Vec<String> = input
+        .lines()
+        .map(|x| match parse_line(x) {
+            Some((k, _)) if k == key => {
+                found = true;
+                line.clone()
+            }
+            _ => x.to_owned(),
+        })
+        .collect();
+    if !found {
+        output.push(line);
+    }
+
+    let mut data = output.join("\n");
+    if input.ends_with('\n') || !found {
+        data.push('\n');
+    }
+
+    let tmpfile = format!("{}.tmp", path);
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&tmpfile)
+        .and_then(|mut out| {
+            out.write_all(data.as_bytes())?;
+            out.sync_all()
+        })
+        .map_err(|err| Error::io(&tmpfile, err))?;
+
+    std::fs::rename(&tmpfile, path).map_err(|err| Error::io(path, err))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutils::TempDir;
+
+    const DEFCONFIG: &str = r#"BR2_aarch64=y
+BR2_LINUX_KERNEL=y
+BR2_LINUX_KERNEL_CUSTOM_GIT=y
+BR2_LINUX_KERNEL_CUSTOM_REPO_URL="https://github.com/raspberrypi/linux.git"
+BR2_LINUX_KERNEL_CUSTOM_REPO_VERSION="576cc10e1ed5"
+BR2_TARGET_UBOOT=y
+BR2_TARGET_UBOOT_CUSTOM_REPO_URL="https://source.denx.de/u-boot/u-boot.git"
+BR2_TARGET_UBOOT_CUSTOM_REPO_VERSION="v2023.01"
+# BR2_TARGET_ROOTFS_TAR is not set
+BR2_TARGET_ARM_TRUSTED_FIRMWARE_CUSTOM_REPO_URL="https://host/tf-a"
+"#;
+
+    #[test]
+    fn symbols() {
+        assert_eq!(
+            parse_line("BR2_TARGET_GENERIC_ISSUE=\"Welcome \\\"home\\\"\""),
+            Some((
+                "BR2_TARGET_GENERIC_ISSUE".to_owned(),
+                "Welcome \"home\"".to_owned()
+            ))
+        );
+        assert_eq!(
+            parse_line("# BR2_TARGET_ROOTFS_TAR is not set"),
+            Some(("BR2_TARGET_ROOTFS_TAR".to_owned(), "n".to_owned()))
+        );
+        assert_eq!(parse_line("# comment"), None);
+        assert_eq!(parse_line(""), None);
+        assert_eq!(quote("a\"b"), "\"a\\\"b\"");
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(component_name("BR2_LINUX_KERNEL"), "linux");
+        assert_eq!(component_name("BR2_TARGET_UBOOT"), "uboot");
+        assert_eq!(
+            component_name("BR2_TARGET_ARM_TRUSTED_FIRMWARE"),
+            "arm-trusted-firmware"
+        );
+        assert!(is_defconfig("configs/raspberrypi4_64_defconfig"));
+        assert!(!is_defconfig("package/linux/linux.mk"));
+    }
+
+    #[test]
+    fn components() {
+        let symbols: Vec<Symbol> = DEFCONFIG.lines().filter_map(parse_line).collect();
+        let packages = read_components("rpi_defconfig", &symbols);
+        assert_eq!(packages.len(), 3);
+
+        let linux = &packages["linux"];
+        assert_eq!(linux.version.as_deref(), Some("576cc10e1ed5"));
+        assert_eq!(
+            linux.get_git_source().as_deref(),
+            Some("https://github.com/raspberrypi/linux.git")
+        );
+
+        let uboot = &packages["uboot"];
+        assert_eq!(uboot.version.as_deref(), Some("v2023.01"));
+        assert!(uboot.get_source(FetchMethod::Git).is_some());
+
+        let tfa = &packages["arm-trusted-firmware"];
+        assert_eq!(tfa.version, None);
+        assert!(tfa.get_source(FetchMethod::Https).is_some());
+    }
+
+    #[test]
+    fn write() {
+        let dir = TempDir::new("defconfig");
+        let path = dir.join("rpi_defconfig");
+        let path = path.to_str().unwrap();
+        std::fs::write(path, DEFCONFIG).unwrap();
+
+        write_version(path, "uboot", "v2024.01").unwrap();
+        write_version(path, "arm-trusted-firmware", "v2.10").unwrap();
+        assert!(write_version(path, "busybox", "1.36").is_err());
+
+        let packages = DefconfigReader::new(path).read().unwrap();
+        assert_eq!(packages["uboot"].version.as_deref(), Some("v2024.01"));
+        assert_eq!(
+            packages["arm-trusted-firmware"].version.as_deref(),
+            Some("v2.10")
+        );
+        assert_eq!(packages["linux"].version.as_deref(), Some("576cc10e1ed5"));
+
+        let data = std::fs::read_to_string(path).unwrap();
+        assert!(data.contains("\nBR2_TARGET_UBOOT_CUSTOM_REPO_VERSION=\"v2024.01\"\n"));
+        assert!(data.ends_with("BR2_TARGET_ARM_TRUSTED_FIRMWARE_CUSTOM_REPO_VERSION=\"v2.10\"\n"));
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -15,8 +15,8 @@ pub enum PackageDiff {
         package: Package,
     },
     Changed {
-        first: Package,
-        second: Package,
+        first: Box<Package>,
+        second: Box<Package>,
         history: Option<Vec<PackageChange>>,
     },
     Removed {
@@ -46,8 +46,8 @@ pub fn build(first: &Packages, second: &Packages) -> PackagesDiff {
                 result.insert(
                     name.clone(),
                     PackageDiff::Changed {
-                        first: package.clone(),
-                        second: info.clone(),
+                        first: Box::new(package.clone()),
+                        second: Box::new(info.clone()),
                         history: None,
                     },
                 );
@@ -114,14 +114,10 @@ impl Display for PackageDiff {
                 history,
             } => {
                 writeln!(f, "[*] {} [modified]", first.name)?;
-                let got_versions = first.version.is_some() && second.version.is_some();
-                if got_versions && first.version != second.version {
-                    writeln!(
-                        f,
-                        "      version: {} -> {}",
-                        first.version.as_ref().unwrap(),
-                        second.version.as_ref().unwrap()
-                    )?;
+                if let (Some(v1), Some(v2)) = (&first.version, &second.version) {
+                    if v1 != v2 {
+                        writeln!(f, "      version: {} -> {}", v1, v2)?;
+                    }
                 }
 
                 if first.sources != second.sources {
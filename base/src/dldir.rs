@@ -0,0 +1,614 @@
+use crate::error::{Error, Result};
+use crate::external::PACKAGE_DIRS;
+use crate::package::{FetchMethod, Package, Packages};
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha224, Sha256, Sha384, Sha512};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Display;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+const ARCHIVE_SUFFIXES: [&str; 14] = [
+    ".tar", ".tar.gz", ".tgz", ".tar.bz2", ".tbz2", ".tar.xz", ".txz", ".tar.lz", ".tar.zst",
+    ".tar.Z", ".zip", ".gz", ".xz", ".bz2",
+];
+
+/// Line of a Buildroot `.hash` file: `<kind>  <hash>  <file>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashEntry {
+    pub kind: String,
+    pub hash: String,
+    pub file: String,
+}
+
+pub fn read_hashes(path: &Path) -> Result<Vec<HashEntry>> {
+    let name = path.to_string_lossy();
+    let file = std::fs::File::open(path).map_err(|err| Error::io(&name, err))?;
+    let mut result = Vec::new();
+    for (idx, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|err| Error::io(&name, err))?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_whitespace().collect::<Vec<&str>>()[..] {
+            [kind, hash, file] => result.push(HashEntry {
+                kind: kind.to_owned(),
+                hash: hash.to_lowercase(),
+                file: file.to_owned(),
+            }),
+            _ => {
+                return Err(Error::parse(
+                    &name,
+                    Some(idx + 1),
+                    "expected '<kind> <hash> <file>'",
+                ))
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Downloaded archives, as opposed to license files that hash files list too.
+pub fn is_archive(name: &str) -> bool {
+    ARCHIVE_SUFFIXES.iter().any(|x| name.ends_with(x))
+}
+
+fn hash_with<D: Digest + std::io::Write>(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut digest = D::new();
+    std::io::copy(&mut file, &mut digest)?;
+    Ok(digest
+        .finalize()
+        .iter()
+        .map(|x| format!("{:02x}", x))
+        .collect())
+}
+
+/// Hash of the file, `None` for kinds Buildroot doesn't know (and `none`).
+pub fn hash_file(kind: &str, path: &Path) -> Result<Option<String>> {
+    let result = match kind {
+        "md5" => hash_with::<Md5>(path),
+        "sha1" => hash_with::<Sha1>(path),
+        "sha224" => hash_with::<Sha224>(path),
+        "sha256" => hash_with::<Sha256>(path),
+        "sha384" => hash_with::<Sha384>(path),
+        "sha512" => hash_with::<Sha512>(path),
+        _ => return Ok(None),
+    };
+    result
+        .map(Some)
+        .map_err(|err| Error::io(&path.to_string_lossy(), err))
+}
+
+/// Content of `dl/<subdir>/`.
+#[derive(Debug, Clone, Default)]
+pub struct DlPackage {
+    pub dir: PathBuf,
+    pub files: BTreeSet<String>,
+    /// `git/` clone cache is present.
+    pub git: bool,
+}
+
+/// Subdirectories of a download directory. Hidden files (locks) are skipped.
+pub fn inventory(dl: &Path) -> Result<BTreeMap<String, DlPackage>> {
+    let name = dl.to_string_lossy();
+    let entries = std::fs::read_dir(dl).map_err(|err| Error::io(&name, err))?;
+    let mut result = BTreeMap::new();
+    for entry in entries.filter_map(|x| x.ok()) {
+        let dir = entry.path();
+        if !dir.is_dir() {
+            continue;
+        }
+        let mut package = DlPackage {
+            dir: dir.clone(),
+            ..Default::default()
+        };
+        for file in std::fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let file_name = file.file_name().to_string_lossy().to_string();
+            if file_name == "git" && file.path().is_dir() {
+                package.git = true;
+            } else if !file_name.starts_with('.') && file.path().is_file() {
+                package.files.insert(file_name);
+            }
+        }
+        result.insert(entry.file_name().to_string_lossy().to_string(), package);
+    }
+    Ok(result)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DlStatus {
+    Ok,
+    Missing,
+    Corrupt {
+        kind: String,
+        expected: String,
+        actual: String,
+    },
+    /// Not referenced by the package hash file, or no package uses the
+    /// directory.
+    Extra,
+    /// No hash to check the file against.
+    Unchecked,
+}
+
+#[derive(Debug, Clone)]
+pub struct DlCheck {
+    /// dl subdirectory.
+    pub package: String,
+    /// `None` stands for the whole subdirectory.
+    pub file: Option<String>,
+    pub status: DlStatus,
+}
+
+impl DlCheck {
+    pub fn is_ok(&self) -> bool {
+        self.status == DlStatus::Ok
+    }
+}
+
+impl Display for DlCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let path = match &self.file {
+            Some(file) => format!("{}/{}", self.package, file),
+            None => format!("{}/", self.package),
+        };
+        match &self.status {
+            DlStatus::Ok => write!(f, "[ ] {}: ok", path),
+            DlStatus::Missing => write!(f, "[-] {}: missing", path),
+            DlStatus::Corrupt {
+                kind,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "[!] {}: {} mismatch, expected {}, got {}",
+                path, kind, expected, actual
+            ),
+            DlStatus::Extra => write!(f, "[+] {}: extra", path),
+            DlStatus::Unchecked => write!(f, "[?] {}: no hash", path),
+        }
+    }
+}
+
+/// dl subdirectory of the package: `<PKG>_DL_SUBDIR` or the package name,
+/// host packages sharing it with the target one.
+pub fn dl_subdir(package: &Package) -> String {
+    package
+        .sources
+        .iter()
+        .find_map(|x| x.subdir.clone())
+        .unwrap_or_else(|| {
+            package
+                .name
+                .strip_prefix("host-")
+                .unwrap_or(&package.name)
+                .to_owned()
+        })
+}
+
+/// Directories with `<name>.hash` files of packages, by package name, for
+/// inputs without mk file locations (show-info, legal-info, SBOMs).
+pub fn index_hash_dirs(trees: &[&str]) -> HashMap<String, PathBuf> {
+    let mut result = HashMap::new();
+    let roots = trees
+        .iter()
+        .flat_map(|tree| PACKAGE_DIRS.iter().map(move |x| Path::new(tree).join(x)));
+    for entry in roots
+        .flat_map(|root| WalkDir::new(root).sort_by_file_name())
+        .flatten()
+    {
+        let path = entry.path();
+        let stem = path.file_stem();
+        let dir = path.parent().and_then(|x| x.file_name());
+        if path.extension().is_some_and(|x| x == "hash") && stem.is_some() && stem == dir {
+            let name = stem.unwrap_or_default().to_string_lossy().to_string();
+            // a later tree overrides the package, as for mk files
+            result.insert(name, path.parent().unwrap_or(path).to_path_buf());
+        }
+    }
+    result
+}
+
+/// Package name without the `host-` prefix, as in mk and hash file names.
+fn raw_name(package: &Package) -> &str {
+    package.name.strip_prefix("host-").unwrap_or(&package.name)
+}
+
+/// Hash files of the package: `<name>.hash` in the package directory (next to
+/// the mk file, or found in `hash_dirs`) and in its `<version>` subdirectory.
+fn hash_files(package: &Package, hash_dirs: &HashMap<String, PathBuf>) -> Vec<PathBuf> {
+    let name = raw_name(package);
+    let dir = match &package.location {
+        Some(location) => Path::new(location)
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .to_path_buf(),
+        None => match hash_dirs.get(name) {
+            Some(dir) => dir.clone(),
+            None => return Vec::new(),
+        },
+    };
+    let file_name = format!("{}.hash", name);
+    let mut result = vec![dir.join(&file_name)];
+    if let Some(version) = &package.version {
+        result.push(dir.join(version).join(&file_name));
+    }
+    result.into_iter().filter(|x| x.is_file()).collect()
+}
+
+fn is_downloaded(package: &Package) -> bool {
+    package
+        .sources
+        .iter()
+        .any(|x| !matches!(x.method, FetchMethod::Local | FetchMethod::File))
+}
+
+/// `file` is `<prefix>` followed by an archive suffix, maybe after the tag of
+/// a generated tarball (`-git4`, `-br1`, `-cargo2`).
+fn is_archive_of(file: &str, prefix: &str) -> bool {
+    let rest = match file.strip_prefix(prefix) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let rest = match rest.strip_prefix('-') {
+        Some(tagged) => match tagged.find('.') {
+            Some(idx)
+                if tagged.starts_with(|x: char| x.is_ascii_alphabetic())
+                    && tagged[..idx].chars().all(|x| x.is_ascii_alphanumeric()) =>
+            {
+                &tagged[idx..]
+            }
+            _ => return false,
+        },
+        None => rest,
+    };
+    ARCHIVE_SUFFIXES.contains(&rest)
+}
+
+/// Archive of the package version: the file name of a source URL if it's an
+/// archive (legal-info, SBOMs), otherwise `<name>-<version>` with an archive
+/// suffix, looked up in the hash files, then in the download directory.
+fn expected_archive<'a, I>(package: &Package, known: I) -> Option<String>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    let from_sources = package
+        .sources
+        .iter()
+        .filter(|x| !x.method.is_vcs())
+        .filter_map(|x| x.url.rsplit('/').next())
+        .find(|x| is_archive(x));
+    if let Some(archive) = from_sources {
+        return Some(archive.to_owned());
+    }
+    let prefix = format!("{}-{}", raw_name(package), package.version.as_ref()?);
+    known
+        .into_iter()
+        .find(|x| is_archive_of(x, &prefix))
+        .cloned()
+}
+
+fn check_file(dir: &Path, file: &str, hashes: &[HashEntry]) -> Result<DlStatus> {
+    let path = dir.join(file);
+    if !path.is_file() {
+        return Ok(DlStatus::Missing);
+    }
+    let mut checked = false;
+    for entry in hashes {
+        if let Some(actual) = hash_file(&entry.kind, &path)? {
+            if actual != entry.hash {
+                return Ok(DlStatus::Corrupt {
+                    kind: entry.kind.clone(),
+                    expected: entry.hash.clone(),
+                    actual,
+                });
+            }
+            checked = true;
+        }
+    }
+    Ok(if checked {
+        DlStatus::Ok
+    } else {
+        DlStatus::Unchecked
+    })
+}
+
+/// Check the download directory against the package set. Only the archive of
+/// the package version is checked: it must be present with the hash of the
+/// hash file, or is unchecked without one. Packages sharing a subdirectory
+/// (`host-foo` and `foo`) check their archives each. Other archives of the hash file
+/// (older versions, extra downloads) are left alone, unknown files are extra.
+/// Hash files are looked up next to mk files, then in `hash_dirs` (see
+/// [`index_hash_dirs`]).
+pub fn verify(
+    dl: &Path,
+    packages: &Packages,
+    hash_dirs: &HashMap<String, PathBuf>,
+) -> Result<Vec<DlCheck>> {
+    let mut inventory = inventory(dl)?;
+    let mut names: Vec<&String> = packages.keys().collect();
+    names.sort();
+
+    // host and target variants (and packages with a common `DL_SUBDIR`)
+    // share a subdirectory
+    let mut groups: Vec<(String, Vec<&Package>)> = Vec::new();
+    for name in names {
+        let package = &packages[name];
+        let subdir = dl_subdir(package);
+        match groups.iter_mut().find(|(x, _)| *x == subdir) {
+            Some((_, group)) => group.push(package),
+            None => groups.push((subdir, vec![package])),
+        }
+    }
+
+    let mut result = Vec::new();
+    for (subdir, group) in groups {
+        let content = match inventory.remove(&subdir) {
+            Some(content) => content,
+            None => {
+                if group.iter().any(|x| is_downloaded(x)) {
+                    result.push(DlCheck {
+                        package: subdir,
+                        file: None,
+                        status: DlStatus::Missing,
+                    });
+                }
+                continue;
+            }
+        };
+
+        // every package checks its own archive, other files are checked once
+        let mut known = BTreeSet::new();
+        let mut expected = BTreeSet::new();
+        for package in group {
+            let mut hashes: BTreeMap<String, Vec<HashEntry>> = BTreeMap::new();
+            for file in hash_files(package, hash_dirs) {
+                for entry in read_hashes(&file)? {
+                    hashes.entry(entry.file.clone()).or_default().push(entry);
+                }
+            }
+            known.extend(hashes.keys().cloned());
+
+            match expected_archive(package, hashes.keys().chain(content.files.iter())) {
+                Some(file) if expected.insert(file.clone()) => {
+                    let entries = hashes.get(&file).map(|x| x.as_slice()).unwrap_or_default();
+                    result.push(DlCheck {
+                        package: subdir.clone(),
+                        status: check_file(&content.dir, &file, entries)?,
+                        file: Some(file),
+                    });
+                }
+                Some(_) => {}
+                None if is_downloaded(package) && package.version.is_some() => {
+                    let version = package.version.as_deref().unwrap_or_default();
+                    let file = format!("{}-{}", raw_name(package), version);
+                    if expected.insert(file.clone()) {
+                        result.push(DlCheck {
+                            package: subdir.clone(),
+                            file: Some(file),
+                            status: DlStatus::Missing,
+                        });
+                    }
+                }
+                None => {}
+            }
+        }
+        for file in &content.files {
+            if expected.contains(file) || known.contains(file) {
+                continue;
+            }
+            result.push(DlCheck {
+                package: subdir.clone(),
+                file: Some(file.clone()),
+                status: if known.is_empty() {
+                    DlStatus::Unchecked
+                } else {
+                    DlStatus::Extra
+                },
+            });
+        }
+    }
+
+    for (subdir, _) in inventory {
+        result.push(DlCheck {
+            package: subdir,
+            file: None,
+            status: DlStatus::Extra,
+        });
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::package::PackageSource;
+    use crate::testutils::TempDir;
+
+    #[test]
+    fn hashes() {
+        let dir = TempDir::new("dldir-hash");
+        let file = dir.join("abc");
+        std::fs::write(&file, "abc").unwrap();
+        assert_eq!(
+            hash_file("sha256", &file).unwrap().unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hash_file("md5", &file).unwrap().unwrap(),
+            "900150983cd24fb0d6963f7d28e17f72"
+        );
+        assert_eq!(hash_file("none", &file).unwrap(), None);
+
+        let hash = dir.join("foo.hash");
+        std::fs::write(
+            &hash,
+            "# from upstream\nsha256  ABC  foo-1.0.tar.gz\n\nbroken line\n",
+        )
+        .unwrap();
+        match read_hashes(&hash) {
+            Err(Error::Parse { line, .. }) => assert_eq!(line, Some(4)),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn verify_dl() {
+        let dir = TempDir::new("dldir-verify");
+        let dl = dir.join("dl");
+        let pkg = dir.join("package");
+        for x in ["foo", "bar", "old"] {
+            std::fs::create_dir_all(dl.join(x)).unwrap();
+        }
+        std::fs::create_dir_all(pkg.join("foo")).unwrap();
+        std::fs::create_dir_all(dl.join("bar").join("git")).unwrap();
+        std::fs::write(dl.join("foo").join("foo-1.0.tar.gz"), "abc").unwrap();
+        std::fs::write(dl.join("foo").join("foo-0.9.tar.gz"), "old").unwrap();
+        std::fs::write(dl.join("bar").join("bar-2.0.tar.xz"), "bar").unwrap();
+        std::fs::write(
+            pkg.join("foo").join("foo.hash"),
+            "sha256  ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  foo-1.0.tar.gz\n\
+             sha256  0000  foo-1.1.tar.gz\n\
+             sha256  1111  COPYING\n",
+        )
+        .unwrap();
+
+        let package = |name: &str, version: &str, location: Option<String>| Package {
+            name: name.to_owned(),
+            version: Some(version.to_owned()),
+            location,
+            sources: vec![PackageSource::new(FetchMethod::Https, "https://host")],
+            ..Default::default()
+        };
+        let packages: Packages = [
+            package(
+                "foo",
+                "1.0",
+                Some(pkg.join("foo").join("foo.mk").to_string_lossy().to_string()),
+            ),
+            package("host-bar", "2.0", None),
+            package("baz", "1.0", None),
+        ]
+        .into_iter()
+        .map(|x| (x.name.clone(), x))
+        .collect();
+
+        let inventory = inventory(&dl).unwrap();
+        assert!(inventory["bar"].git);
+        assert_eq!(inventory["foo"].files.len(), 2);
+
+        // foo-1.1.tar.gz of the hash file is another version, not missing
+        let no_dirs = HashMap::new();
+        let lines: Vec<String> = verify(&dl, &packages, &no_dirs)
+            .unwrap()
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "[-] baz/: missing",
+                "[ ] foo/foo-1.0.tar.gz: ok",
+                "[+] foo/foo-0.9.tar.gz: extra",
+                "[?] bar/bar-2.0.tar.xz: no hash",
+                "[+] old/: extra",
+            ]
+        );
+
+        // hash files of packages without mk files are found in trees
+        std::fs::create_dir_all(pkg.join("bar")).unwrap();
+        std::fs::write(
+            pkg.join("bar").join("bar.hash"),
+            "sha256  0000  bar-2.0.tar.xz
+",
+        )
+        .unwrap();
+        let tree = dir.to_string_lossy().to_string();
+        let hash_dirs = index_hash_dirs(&[&tree]);
+        assert_eq!(hash_dirs.len(), 2);
+        let checks = verify(&dl, &packages, &hash_dirs).unwrap();
+        assert!(matches!(checks[3].status, DlStatus::Corrupt { .. }));
+
+        // the archive of a legal-info or SBOM source is the expected one
+        let mut packages = packages;
+        packages.get_mut("foo").unwrap().sources = vec![PackageSource::new(
+            FetchMethod::Https,
+            "https://host/foo-0.9.tar.gz",
+        )];
+        let lines: Vec<String> = verify(&dl, &packages, &no_dirs)
+            .unwrap()
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        assert_eq!(lines[1], "[?] foo/foo-0.9.tar.gz: no hash");
+        assert_eq!(lines.len(), 4);
+
+        packages.get_mut("foo").unwrap().sources =
+            vec![PackageSource::new(FetchMethod::Https, "https://host")];
+        packages.get_mut("foo").unwrap().version = Some("1.2".to_owned());
+        packages.get_mut("baz").unwrap().sources = Vec::new();
+        std::fs::remove_dir_all(dl.join("bar")).unwrap();
+        std::fs::write(dl.join("foo").join("foo-1.0.tar.gz"), "abd").unwrap();
+        let lines: Vec<String> = verify(&dl, &packages, &no_dirs)
+            .unwrap()
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "[-] foo/foo-1.2: missing",
+                "[+] foo/foo-0.9.tar.gz: extra",
+                "[-] bar/: missing",
+                "[+] old/: extra",
+            ]
+        );
+
+        // host-foo shares foo/ and checks its own version
+        let foo = packages["foo"].clone();
+        packages.insert(
+            "host-foo".to_owned(),
+            Package {
+                name: "host-foo".to_owned(),
+                version: Some("0.9".to_owned()),
+                ..foo.clone()
+            },
+        );
+        packages.insert(
+            "foo".to_owned(),
+            Package {
+                version: Some("1.0".to_owned()),
+                ..foo
+            },
+        );
+        std::fs::write(dl.join("foo").join("foo-1.0.tar.gz"), "abc").unwrap();
+        let lines: Vec<String> = verify(&dl, &packages, &no_dirs)
+            .unwrap()
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "[ ] foo/foo-1.0.tar.gz: ok",
+                "[?] foo/foo-0.9.tar.gz: no hash",
+                "[-] bar/: missing",
+                "[+] old/: extra",
+            ]
+        );
+    }
+
+    #[test]
+    fn archive_names() {
+        assert!(is_archive_of("foo-1.0.tar.gz", "foo-1.0"));
+        assert!(is_archive_of("foo-1.0-git4.tar.gz", "foo-1.0"));
+        assert!(is_archive_of("foo-1.0-br1.tar.gz", "foo-1.0"));
+        assert!(!is_archive_of("foo-1.0.1.tar.gz", "foo-1.0"));
+        assert!(!is_archive_of("foo-1.0-1.tar.gz", "foo-1.0"));
+        assert!(!is_archive_of("COPYING", "foo-1.0"));
+    }
+}
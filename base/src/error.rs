@@ -0,0 +1,154 @@
+use std::fmt::Display;
+
+/// Errors produced by the base crate. Every variant keeps enough context
+/// (file, repository, object name) to build a meaningful message, and the
+/// underlying error, if any, is available via `source()`.
+#[derive(Debug)]
+pub enum Error {
+    /// Input could not be parsed. `line` is 1-based, if known.
+    Parse {
+        file: String,
+        line: Option<usize>,
+        message: String,
+    },
+    /// Git operation failed for the repository at `url`.
+    Git { url: String, source: git2::Error },
+    /// Object (commit, tag, branch) `name` is absent in the repository at `url`.
+    /// `candidates` are existing names similar to the requested one.
+    MissingObject {
+        url: String,
+        name: String,
+        candidates: Vec<String>,
+    },
+    /// I/O failed for `path`.
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    /// Package source (URI, fetch method) that can't be handled.
+    UnsupportedSource(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn parse(file: &str, line: Option<usize>, message: &str) -> Error {
+        Error::Parse {
+            file: file.to_owned(),
+            line,
+            message: message.to_owned(),
+        }
+    }
+
+    pub fn git(url: &str, source: git2::Error) -> Error {
+        Error::Git {
+            url: url.to_owned(),
+            source,
+        }
+    }
+
+    pub fn missing_object(url: &str, name: &str) -> Error {
+        Error::MissingObject {
+            url: url.to_owned(),
+            name: name.to_owned(),
+            candidates: Vec::new(),
+        }
+    }
+
+    pub fn missing_object_like(url: &str, name: &str, candidates: Vec<String>) -> Error {
+        Error::MissingObject {
+            url: url.to_owned(),
+            name: name.to_owned(),
+            candidates,
+        }
+    }
+
+    pub fn io(path: &str, source: std::io::Error) -> Error {
+        Error::Io {
+            path: path.to_owned(),
+            source,
+        }
+    }
+
+    pub fn unsupported_source(source: &str) -> Error {
+        Error::UnsupportedSource(source.to_owned())
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Parse {
+                file,
+                line: Some(line),
+                message,
+            } => write!(f, "{}:{}: {}", file, line, message),
+            Error::Parse {
+                file,
+                line: None,
+                message,
+            } => write!(f, "{}: {}", file, message),
+            Error::Git { url, source } => write!(f, "{}: {}", url, source.message()),
+            Error::MissingObject {
+                url,
+                name,
+                candidates,
+            } => {
+                write!(f, "{}: can't find {}", url, name)?;
+                if !candidates.is_empty() {
+                    write!(f, " (similar: {})", candidates.join(", "))?;
+                }
+                Ok(())
+            }
+            Error::Io { path, source } => write!(f, "{}: {}", path, source),
+            Error::UnsupportedSource(source) => write!(f, "unsupported source: {}", source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Git { source, .. } => Some(source),
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn display() {
+        let err = Error::parse("foo.mk", Some(3), "invalid line");
+        assert_eq!(err.to_string(), "foo.mk:3: invalid line");
+
+        let err = Error::parse("foo.mk", None, "invalid name");
+        assert_eq!(err.to_string(), "foo.mk: invalid name");
+
+        let err = Error::missing_object("https://host/foo.git", "v1.0");
+        assert_eq!(err.to_string(), "https://host/foo.git: can't find v1.0");
+
+        let candidates = vec!["v1.0.0".to_owned(), "v1.1".to_owned()];
+        let err = Error::missing_object_like("https://host/foo.git", "v1.0", candidates);
+        assert_eq!(
+            err.to_string(),
+            "https://host/foo.git: can't find v1.0 (similar: v1.0.0, v1.1)"
+        );
+    }
+
+    #[test]
+    fn keep_cause() {
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "no such file");
+        let err = Error::io("/tmp/foo.mk", io);
+        assert_eq!(err.to_string(), "/tmp/foo.mk: no such file");
+        assert!(err.source().is_some());
+
+        let err = Error::git("https://host/foo.git", git2::Error::from_str("auth failed"));
+        assert_eq!(err.to_string(), "https://host/foo.git: auth failed");
+        assert!(err.source().is_some());
+    }
+}
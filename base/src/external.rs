@@ -0,0 +1,260 @@
+use crate::error::{Error, Result};
+use crate::mkfile::{self, Duplicate, MkFile, Precedence};
+use crate::package::{Package, PackageReader, Packages};
+use log::info;
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// Description of a br2-external tree (`external.desc`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalDesc {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+impl ExternalDesc {
+    pub fn parse(file: &str, data: &str) -> Result<ExternalDesc> {
+        let mut name = None;
+        let mut description = None;
+        for (idx, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once(':') {
+                Some((key, value)) if key.trim() == "name" => name = Some(value.trim().to_owned()),
+                Some((key, value)) if key.trim() == "desc" => {
+                    description = Some(value.trim().to_owned())
+                }
+                _ => return Err(Error::parse(file, Some(idx + 1), "invalid line")),
+            }
+        }
+
+        let name = name.ok_or_else(|| Error::parse(file, None, "name not found"))?;
+        if name.is_empty() || !name.chars().all(|x| x.is_ascii_alphanumeric() || x == '_') {
+            return Err(Error::parse(file, None, &format!("invalid name {}", name)));
+        }
+        Ok(ExternalDesc { name, description })
+    }
+
+    /// Read `external.desc` of a tree, if the tree is a br2-external.
+    pub fn read(tree: &str) -> Result<Option<ExternalDesc>> {
+        let file = Path::new(tree).join("external.desc");
+        if !file.is_file() {
+            return Ok(None);
+        }
+        let file = file.to_string_lossy();
+        let data = std::fs::read_to_string(file.as_ref()).map_err(|err| Error::io(&file, err))?;
+        ExternalDesc::parse(&file, &data).map(Some)
+    }
+}
+
+/// Package mk files follow the `<dir>/<name>/<name>.mk` layout (possibly
+/// nested in a group directory); others, like `external.mk` or helper
+/// includes, are not packages.
+pub(crate) fn is_package_mk(path: &Path) -> bool {
+    let stem = path.file_stem();
+    let dir = path.parent().and_then(|x| x.file_name());
+    path.extension().is_some_and(|x| x == "mk") && stem.is_some() && stem == dir
+}
+
+/// Reads packages of Buildroot and br2-external trees. Trees are read in
+/// the given order, the same way Buildroot includes external mk files after
+/// its own ones. A package defined twice, within one tree or in several
+/// trees, is a duplicate, resolved by `precedence`.
+pub struct ExternalTreeReader {
+    trees: Vec<String>,
+    precedence: Precedence,
+    duplicates: Vec<Duplicate>,
+}
+
+impl ExternalTreeReader {
+    pub fn new(trees: &[&str]) -> ExternalTreeReader {
+        ExternalTreeReader {
+            trees: trees.iter().map(|x| (*x).to_owned()).collect(),
+            precedence: Precedence::default(),
+            duplicates: Vec::new(),
+        }
+    }
+
+    pub fn precedence(mut self, precedence: Precedence) -> ExternalTreeReader {
+        self.precedence = precedence;
+        self
+    }
+
+    /// Trees separated by colons, like `BR2_EXTERNAL`.
+    pub fn from_list(trees: &str) -> ExternalTreeReader {
+        let trees: Vec<&str> = trees.split(':').filter(|x| !x.is_empty()).collect();
+        ExternalTreeReader::new(&trees)
+    }
+}
+
+/// Tree directories with mk files: packages, the kernel, bootloaders and
+/// toolchains.
+pub(crate) const PACKAGE_DIRS: [&str; 4] = ["package", "linux", "boot", "toolchain"];
+
+/// Buildroot source tree: has the top `Config.in` and `package/Config.in`.
+pub fn is_buildroot_tree(path: &Path) -> bool {
+    path.join("Config.in").is_file() && path.join("package/Config.in").is_file()
+}
+
+fn is_br2_external(path: &str) -> bool {
+    Path::new(path).join("external.desc").is_file()
+}
+
+/// A path is handled by `ExternalTreeReader` if it's a br2-external root or a
+/// colon-separated list of them, optionally starting with the Buildroot tree.
+/// Other paths with colons are plain file names.
+pub fn is_external_tree(path: &str) -> bool {
+    let parts: Vec<&str> = path.split(':').collect();
+    parts.iter().enumerate().all(|(idx, part)| {
+        is_br2_external(part) || (idx == 0 && parts.len() > 1 && is_buildroot_tree(Path::new(part)))
+    })
+}
+
+fn read_tree(tree: &str) -> Result<Vec<Result<Package>>> {
+    let external = ExternalDesc::read(tree)?.map(|x| x.name);
+    let roots: Vec<_> = PACKAGE_DIRS
+        .iter()
+        .map(|x| Path::new(tree).join(x))
+        .filter(|x| x.is_dir())
+        .collect();
+    if roots.is_empty() {
+        return Err(Error::io(
+            &Path::new(tree).join("package").to_string_lossy(),
+            std::io::Error::from(std::io::ErrorKind::NotFound),
+        ));
+    }
+
+    let packages = roots
+        .iter()
+        .flat_map(|root| WalkDir::new(root).sort_by_file_name())
+        .flatten()
+        .filter(|e| is_package_mk(e.path()))
+        .filter_map(|e| e.path().to_str().map(|x| x.to_owned()))
+        .map(|file| {
+            info!("process {}", file);
+            MkFile::new(&file).read_info().map(|mut package| {
+                package.external = external.clone();
+                package
+            })
+        })
+        .collect();
+    Ok(packages)
+}
+
+impl PackageReader for ExternalTreeReader {
+    type Error = Error;
+    fn read(&mut self) -> Result<Packages> {
+        let mut packages = Vec::new();
+        for tree in &self.trees {
+            packages.extend(read_tree(tree)?);
+        }
+        let (packages, duplicates) = mkfile::collect_packages(packages, self.precedence)?;
+        self.duplicates = duplicates;
+        Ok(packages)
+    }
+
+    fn duplicates(&self) -> &[Duplicate] {
+        &self.duplicates
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutils::TempDir;
+
+    #[test]
+    fn desc() {
+        let desc =
+            ExternalDesc::parse("external.desc", "name: BOARD\ndesc: Board support\n").unwrap();
+        assert_eq!(desc.name, "BOARD");
+        assert_eq!(desc.description.as_deref(), Some("Board support"));
+
+        assert!(ExternalDesc::parse("external.desc", "desc: no name").is_err());
+        assert!(ExternalDesc::parse("external.desc", "name: bad-name").is_err());
+        match ExternalDesc::parse("external.desc", "name: FOO\nfoo bar") {
+            Err(Error::Parse { line, .. }) => assert_eq!(line, Some(2)),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn package_layout() {
+        assert!(is_package_mk(Path::new("package/zlib/zlib.mk")));
+        assert!(is_package_mk(Path::new("package/qt5/qt5base/qt5base.mk")));
+        assert!(!is_package_mk(Path::new("external.mk")));
+        assert!(!is_package_mk(Path::new("package/qt5/qt5.mk.in")));
+        assert!(!is_package_mk(Path::new("package/board/helpers.mk")));
+    }
+
+    #[test]
+    fn merge_trees() {
+        let root = TempDir::new("external");
+        let write = |path: &str, data: &str| {
+            let file = root.join(path);
+            std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+            std::fs::write(file, data).unwrap();
+        };
+        write("buildroot/Config.in", "");
+        write("buildroot/package/Config.in", "");
+        write("buildroot/package/zlib/zlib.mk", "ZLIB_VERSION = 1.2\n");
+        write("buildroot/linux/linux.mk", "LINUX_VERSION = 6.6\n");
+        write(
+            "buildroot/linux/linux-ext-rtai.mk",
+            "LINUX_EXTENSIONS += rtai\n",
+        );
+        write("buildroot/boot/uboot/uboot.mk", "UBOOT_VERSION = 2024.01\n");
+        write("buildroot/package/xz/xz.mk", "XZ_VERSION = 5.4\n");
+        write("ext/external.desc", "name: BOARD\n");
+        write(
+            "ext/external.mk",
+            "include $(sort $(wildcard package/*/*.mk))\n",
+        );
+        write("ext/package/zlib/zlib.mk", "ZLIB_VERSION = 1.3\n");
+        write("ext/package/app/app.mk", "APP_VERSION = 0.1\n");
+        write("ext/package/app/app-helpers.mk", "APP_HELPER = 1\n");
+
+        let buildroot = root.join("buildroot");
+        let ext = root.join("ext");
+        let list = format!("{}:{}", buildroot.display(), ext.display());
+        assert!(is_external_tree(&list));
+        assert!(is_external_tree(ext.to_str().unwrap()));
+        assert!(!is_external_tree(buildroot.to_str().unwrap()));
+        let reversed = format!("{}:{}", ext.display(), buildroot.display());
+        assert!(!is_external_tree(&reversed));
+        assert!(!is_external_tree(&format!(
+            "{}:/nonexistent",
+            ext.display()
+        )));
+        assert!(!is_external_tree("/tmp/show-info:2024.json"));
+
+        let mut reader = ExternalTreeReader::from_list(&list);
+        let packages = reader.read().unwrap();
+        assert_eq!(packages.len(), 5);
+        assert_eq!(packages["linux"].version.as_deref(), Some("6.6"));
+        assert_eq!(packages["uboot"].version.as_deref(), Some("2024.01"));
+        assert_eq!(packages["zlib"].version.as_deref(), Some("1.2"));
+        assert_eq!(packages["zlib"].external, None);
+        assert_eq!(packages["xz"].external, None);
+        assert_eq!(packages["app"].version.as_deref(), Some("0.1"));
+        assert_eq!(packages["app"].external.as_deref(), Some("BOARD"));
+        assert_eq!(reader.duplicates().len(), 1);
+        assert_eq!(
+            reader.duplicates()[0].dropped[0].external.as_deref(),
+            Some("BOARD")
+        );
+
+        let mut reader = ExternalTreeReader::from_list(&list).precedence(Precedence::Fail);
+        assert!(matches!(reader.read(), Err(Error::Parse { .. })));
+
+        write("ext/package/board/zlib/zlib.mk", "ZLIB_VERSION = 1.4\n");
+        let mut reader = ExternalTreeReader::from_list(&list).precedence(Precedence::Last);
+        let packages = reader.read().unwrap();
+        assert_eq!(packages["zlib"].version.as_deref(), Some("1.3"));
+        assert_eq!(packages["zlib"].external.as_deref(), Some("BOARD"));
+        assert_eq!(reader.duplicates().len(), 1);
+        assert_eq!(reader.duplicates()[0].dropped.len(), 2);
+    }
+}
@@ -0,0 +1,454 @@
+use crate::error::{Error, Result};
+use crate::package::Packages;
+use crate::pattern::Pattern;
+use log::debug;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+pub const FILE_LIST_NAME: &str = "packages-file-list.txt";
+
+/// `packages-file-list.txt` for an input: the file itself, or
+/// `build/packages-file-list.txt` in an output directory or next to a
+/// show-info JSON in it.
+pub fn find_file_list(path: &str) -> Option<String> {
+    let path = Path::new(path);
+    if path.file_name().is_some_and(|x| x == FILE_LIST_NAME) {
+        return Some(path.to_string_lossy().to_string());
+    }
+    let dir = if path.is_dir() { path } else { path.parent()? };
+    let list = dir.join("build").join(FILE_LIST_NAME);
+    if list.is_file() {
+        Some(list.to_string_lossy().to_string())
+    } else {
+        None
+    }
+}
+
+/// `output/target` of `output/build/packages-file-list.txt`.
+pub fn target_dir(file_list: &str) -> Option<PathBuf> {
+    Path::new(file_list)
+        .parent()?
+        .parent()
+        .map(|x| x.join("target"))
+}
+
+/// Owners of installed files. A file installed by several packages belongs
+/// to the last one, as it's the one that overwrote it.
+#[derive(Debug, Default)]
+pub struct FileList {
+    /// Paths relative to the target directory, without `./`.
+    pub files: BTreeMap<String, String>,
+}
+
+impl FileList {
+    pub fn read(path: &str) -> Result<FileList> {
+        let content = std::fs::read_to_string(path).map_err(|err| Error::io(path, err))?;
+        FileList::parse(path, &content)
+    }
+
+    /// Lines are `<package>,<path>`.
+    pub fn parse(file: &str, content: &str) -> Result<FileList> {
+        let mut result = FileList::default();
+        for (idx, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match line.split_once(',') {
+                Some((package, path)) if !package.is_empty() && !path.is_empty() => {
+                    let path = path.trim_start_matches("./").to_owned();
+                    result.files.insert(path, package.to_owned());
+                }
+                _ => {
+                    return Err(Error::parse(
+                        file,
+                        Some(idx + 1),
+                        "expected '<package>,<path>'",
+                    ))
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Files of each package.
+    pub fn packages(&self) -> BTreeMap<&String, Vec<&String>> {
+        let mut result: BTreeMap<&String, Vec<&String>> = BTreeMap::new();
+        for (path, package) in &self.files {
+            result.entry(package).or_default().push(path);
+        }
+        result
+    }
+
+    /// Installed size of each package. Files removed after installation
+    /// (post-build scripts) are skipped, symlinks count for their own size.
+    pub fn sizes(&self, target: &Path) -> PackageSizes {
+        let mut result = PackageSizes::new();
+        for (path, package) in &self.files {
+            let size = match std::fs::symlink_metadata(target.join(path)) {
+                Ok(metadata) if !metadata.is_dir() => metadata.len(),
+                Ok(_) => continue,
+                Err(_) => {
+                    debug!("{}: {} is not in the target", package, path);
+                    continue;
+                }
+            };
+            let entry = result.entry(package.clone()).or_default();
+            entry.size += size;
+            entry.files += 1;
+        }
+        result
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PackageSize {
+    pub size: u64,
+    pub files: usize,
+}
+
+pub type PackageSizes = BTreeMap<String, PackageSize>;
+
+/// Per-package sizes of a file list and its target directory.
+pub fn read_sizes(file_list: &str, target: Option<&Path>) -> Result<PackageSizes> {
+    let list = FileList::read(file_list)?;
+    let target = match target {
+        Some(target) => target.to_path_buf(),
+        None => target_dir(file_list)
+            .ok_or_else(|| Error::missing_object(file_list, "target directory"))?,
+    };
+    if !target.is_dir() {
+        return Err(Error::missing_object(file_list, &target.to_string_lossy()));
+    }
+    Ok(list.sizes(&target))
+}
+
+/// Human readable size: `12.3 MiB`.
+pub fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if size < 1024 {
+        return format!("{} B", size);
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+fn format_delta(delta: i64) -> String {
+    let sign = if delta < 0 { "-" } else { "+" };
+    format!("{}{}", sign, format_size(delta.unsigned_abs()))
+}
+
+/// Packages by size, biggest first.
+pub struct SizeReport<'a> {
+    pub sizes: &'a PackageSizes,
+    /// At most that many packages, all of them if 0.
+    pub count: usize,
+}
+
+impl Display for SizeReport<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let total: u64 = self.sizes.values().map(|x| x.size).sum();
+        writeln!(
+            f,
+            "[*] target size: {}, {} package(s)",
+            format_size(total),
+            self.sizes.len()
+        )?;
+        let mut sizes: Vec<(&String, &PackageSize)> = self.sizes.iter().collect();
+        sizes.sort_by(|a, b| b.1.size.cmp(&a.1.size).then(a.0.cmp(b.0)));
+        if self.count > 0 {
+            sizes.truncate(self.count);
+        }
+        for (name, size) in sizes {
+            writeln!(
+                f,
+                "      {}: {} ({} file(s))",
+                name,
+                format_size(size.size),
+                size.files
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Installed size of a package in two outputs; `None` if it's not installed.
+#[derive(Debug, Clone)]
+pub struct SizeChange {
+    pub name: String,
+    pub first: Option<u64>,
+    pub second: Option<u64>,
+    /// Versions of the package sets, if they differ.
+    pub versions: Option<(String, String)>,
+}
+
+impl SizeChange {
+    pub fn delta(&self) -> i64 {
+        self.second.unwrap_or_default() as i64 - self.first.unwrap_or_default() as i64
+    }
+}
+
+impl Display for SizeChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let size = |x: Option<u64>| x.map(format_size).unwrap_or_else(|| "-".to_owned());
+        let sign = if self.delta() > 0 { "+" } else { "-" };
+        write!(f, "[{}] {}", sign, self.name)?;
+        if let Some((first, second)) = &self.versions {
+            write!(f, " {} -> {}", first, second)?;
+        }
+        write!(
+            f,
+            ": {} -> {} ({})",
+            size(self.first),
+            size(self.second),
+            format_delta(self.delta())
+        )
+    }
+}
+
+/// Size changes between two outputs, biggest growth first.
+#[derive(Debug, Default)]
+pub struct SizeDiff {
+    pub first_total: u64,
+    pub second_total: u64,
+    pub changes: Vec<SizeChange>,
+}
+
+impl SizeDiff {
+    /// Changes below `min_size` bytes are dropped.
+    pub fn build(first: &PackageSizes, second: &PackageSizes, min_size: u64) -> SizeDiff {
+        let mut names: Vec<&String> = first.keys().chain(second.keys()).collect();
+        names.sort();
+        names.dedup();
+
+        let mut changes: Vec<SizeChange> = names
+            .into_iter()
+            .map(|name| SizeChange {
+                name: name.clone(),
+                first: first.get(name).map(|x| x.size),
+                second: second.get(name).map(|x| x.size),
+                versions: None,
+            })
+            .filter(|x| x.delta() != 0 && x.delta().unsigned_abs() >= min_size)
+            .collect();
+        changes.sort_by(|a, b| b.delta().cmp(&a.delta()).then(a.name.cmp(&b.name)));
+        SizeDiff {
+            first_total: first.values().map(|x| x.size).sum(),
+            second_total: second.values().map(|x| x.size).sum(),
+            changes,
+        }
+    }
+
+    /// Show version changes of the package sets next to size changes.
+    pub fn with_versions(mut self, first: &Packages, second: &Packages) -> SizeDiff {
+        for change in &mut self.changes {
+            let version = |packages: &Packages| {
+                packages
+                    .get(&change.name)
+                    .and_then(|x| x.version.clone())
+                    .unwrap_or_else(|| "-".to_owned())
+            };
+            let versions = (version(first), version(second));
+            if versions.0 != versions.1 {
+                change.versions = Some(versions);
+            }
+        }
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl Display for SizeDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "[*] target size: {} -> {} ({})",
+            format_size(self.first_total),
+            format_size(self.second_total),
+            format_delta(self.second_total as i64 - self.first_total as i64)
+        )?;
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+/// Installed files a package gained and lost between two outputs.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct FileDiff {
+    pub added: BTreeSet<String>,
+    pub removed: BTreeSet<String>,
+}
+
+/// File diffs by package name.
+pub type FileDiffs = BTreeMap<String, FileDiff>;
+
+/// Patterns are matched against paths with and without the leading `/`,
+/// any path is kept without patterns.
+fn is_selected(path: &str, patterns: &[Pattern]) -> bool {
+    patterns.is_empty()
+        || patterns
+            .iter()
+            .any(|x| x.is_match(path) || x.is_match(&format!("/{}", path)))
+}
+
+/// Files added and removed per package. A file moved to another package is
+/// removed from the first one and added to the second one.
+pub fn diff_files(first: &FileList, second: &FileList, patterns: &[Pattern]) -> FileDiffs {
+    let mut result = FileDiffs::new();
+    for (path, package) in &second.files {
+        if first.files.get(path) != Some(package) && is_selected(path, patterns) {
+            let diff: &mut FileDiff = result.entry(package.clone()).or_default();
+            diff.added.insert(format!("/{}", path));
+        }
+    }
+    for (path, package) in &first.files {
+        if second.files.get(path) != Some(package) && is_selected(path, patterns) {
+            let diff: &mut FileDiff = result.entry(package.clone()).or_default();
+            diff.removed.insert(format!("/{}", path));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::package::Package;
+    use crate::testutils::TempDir;
+
+    #[test]
+    fn sizes() {
+        let dir = TempDir::new("filelist");
+        let target = dir.join("target");
+        std::fs::create_dir_all(target.join("usr").join("lib")).unwrap();
+        std::fs::write(target.join("usr/lib/libz.so.1.3"), vec![0u8; 2048]).unwrap();
+        std::fs::write(target.join("usr/lib/libssl.so"), vec![0u8; 100]).unwrap();
+        std::os::unix::fs::symlink("libz.so.1.3", target.join("usr/lib/libz.so")).unwrap();
+        std::fs::create_dir_all(dir.join("build")).unwrap();
+        let list = dir.join("build").join(FILE_LIST_NAME);
+        std::fs::write(
+            &list,
+            "zlib,./usr/lib/libz.so.1.3\nzlib,./usr/lib/libz.so\nzlib,./usr/lib/libz.a\nopenssl,./usr/lib/libssl.so\n",
+        )
+        .unwrap();
+
+        let list = list.to_string_lossy();
+        assert_eq!(
+            find_file_list(&dir.to_string_lossy()).as_deref(),
+            Some(list.as_ref())
+        );
+        let sizes = read_sizes(&list, None).unwrap();
+        assert_eq!(
+            sizes["zlib"],
+            PackageSize {
+                size: 2048 + 11,
+                files: 2
+            }
+        );
+        assert_eq!(sizes["openssl"].size, 100);
+        assert_eq!(
+            SizeReport {
+                sizes: &sizes,
+                count: 1
+            }
+            .to_string(),
+            "[*] target size: 2.1 KiB, 2 package(s)\n      zlib: 2.0 KiB (2 file(s))\n"
+        );
+
+        match FileList::parse("list", "zlib,./a\nbroken\n") {
+            Err(Error::Parse { line, .. }) => assert_eq!(line, Some(2)),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn diff() {
+        let sizes = |items: &[(&str, u64)]| -> PackageSizes {
+            items
+                .iter()
+                .map(|(name, size)| {
+                    let size = PackageSize {
+                        size: *size,
+                        files: 1,
+                    };
+                    (name.to_string(), size)
+                })
+                .collect()
+        };
+        let first = sizes(&[
+            ("zlib", 100 * 1024),
+            ("openssl", 3 << 20),
+            ("dropbear", 500),
+        ]);
+        let second = sizes(&[
+            ("zlib", 120 * 1024),
+            ("openssl", 3 << 20),
+            ("nghttp2", 4096),
+        ]);
+        let packages = |version: &str| -> Packages {
+            let package = Package {
+                name: "zlib".to_owned(),
+                version: Some(version.to_owned()),
+                ..Default::default()
+            };
+            [("zlib".to_owned(), package)].into_iter().collect()
+        };
+
+        let diff = SizeDiff::build(&first, &second, 1024)
+            .with_versions(&packages("1.2.13"), &packages("1.3"));
+        assert_eq!(
+            diff.to_string(),
+            "[*] target size: 3.1 MiB -> 3.1 MiB (+23.5 KiB)\n\
+             [+] zlib 1.2.13 -> 1.3: 100.0 KiB -> 120.0 KiB (+20.0 KiB)\n\
+             [+] nghttp2: - -> 4.0 KiB (+4.0 KiB)\n"
+        );
+        assert_eq!(SizeDiff::build(&first, &second, 0).changes.len(), 3);
+    }
+
+    #[test]
+    fn files() {
+        let first = FileList::parse(
+            "first",
+            "zlib,./usr/lib/libz.so.1.2.13\nzlib,./usr/lib/libz.so\nbusybox,./etc/inittab\nbusybox,./bin/ls\n",
+        )
+        .unwrap();
+        let second = FileList::parse(
+            "second",
+            "zlib,./usr/lib/libz.so.1.3\nzlib,./usr/lib/libz.so\ncoreutils,./bin/ls\nbusybox,./bin/sh\n",
+        )
+        .unwrap();
+
+        let diffs = diff_files(&first, &second, &[]);
+        assert_eq!(diffs.len(), 3);
+        assert_eq!(
+            diffs["zlib"].added.iter().collect::<Vec<&String>>(),
+            vec!["/usr/lib/libz.so.1.3"]
+        );
+        assert_eq!(
+            diffs["busybox"].removed.iter().collect::<Vec<&String>>(),
+            vec!["/bin/ls", "/etc/inittab"]
+        );
+        assert!(diffs["coreutils"].added.contains("/bin/ls"));
+
+        let patterns = [
+            Pattern::new("/bin/*").unwrap(),
+            Pattern::new("etc/*").unwrap(),
+        ];
+        let diffs = diff_files(&first, &second, &patterns);
+        assert_eq!(
+            diffs.keys().collect::<Vec<&String>>(),
+            vec!["busybox", "coreutils"]
+        );
+        assert_eq!(diffs["busybox"].removed.len(), 2);
+    }
+}
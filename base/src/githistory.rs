@@ -1,21 +1,17 @@
 use crate::diffs::{PackageChange, PackageDiff, PackagesDiff};
+use crate::error::{Error, Result};
 use crate::gitworkspace::{GitWorkspace, Options};
 
 use git2::*;
 use log::{debug, error};
-use std::io::{Error, ErrorKind};
 
 struct GitHistoryBuilder<'a> {
     repo: &'a Repository,
+    url: &'a str,
 }
 
 impl<'a> GitHistoryBuilder<'a> {
-    fn history(
-        &self,
-        commit1: &str,
-        commit2: &str,
-        short: bool,
-    ) -> Result<Vec<PackageChange>, Error> {
+    fn history(&self, commit1: &str, commit2: &str, short: bool) -> Result<Vec<PackageChange>> {
         debug!("building history: {}...{}", commit1, commit2);
 
         let result = self.history_short(commit1, commit2, short)?;
@@ -30,11 +26,11 @@ impl<'a> GitHistoryBuilder<'a> {
         }
     }
 
-    fn search_oid(&self, commit: &str) -> Result<Oid, Error> {
-        let parsed = self.repo.revparse_single(commit).map_err(|_| {
-            error!("failed to find object: {}", commit);
-            Error::new(ErrorKind::Other, "failed to find object")
-        })?;
+    fn search_oid(&self, commit: &str) -> Result<Oid> {
+        let parsed = self
+            .repo
+            .revparse_single(commit)
+            .map_err(|_| Error::missing_object(self.url, commit))?;
 
         Ok(parsed.id())
     }
@@ -44,21 +40,24 @@ impl<'a> GitHistoryBuilder<'a> {
         commit1: &str,
         commit2: &str,
         short: bool,
-    ) -> Result<Vec<PackageChange>, Error> {
+    ) -> Result<Vec<PackageChange>> {
         let mut result = Vec::new();
         let c1 = self.search_oid(commit1)?;
         let c2 = self.search_oid(commit2)?;
 
-        let mut walk = self.repo.revwalk().unwrap();
-        walk.push_range(&format!("{}..{}", c1, c2)).unwrap();
+        let git_error = |err| Error::git(self.url, err);
+
+        let mut walk = self.repo.revwalk().map_err(git_error)?;
+        walk.push_range(&format!("{}..{}", c1, c2))
+            .map_err(git_error)?;
 
         if short {
-            walk.simplify_first_parent().unwrap();
+            walk.simplify_first_parent().map_err(git_error)?;
         }
 
         for commit in walk {
-            let id = commit.unwrap();
-            let info = self.repo.find_commit(id).unwrap();
+            let id = commit.map_err(git_error)?;
+            let info = self.repo.find_commit(id).map_err(git_error)?;
             let history = PackageChange {
                 summary: info.summary().map(|s| s.to_owned()),
                 author: Some(info.author().to_string()),
@@ -71,49 +70,58 @@ impl<'a> GitHistoryBuilder<'a> {
     }
 }
 
-fn append_one(workspace: &mut GitWorkspace, package: &mut PackageDiff, short: bool) -> Option<()> {
+/// Changes between two revisions of the repository cloned from `url`.
+pub fn build(
+    repo: &Repository,
+    url: &str,
+    from: &str,
+    to: &str,
+    short: bool,
+) -> Result<Vec<PackageChange>> {
+    GitHistoryBuilder { repo, url }.history(from, to, short)
+}
+
+fn append_one(workspace: &mut GitWorkspace, package: &mut PackageDiff, short: bool) -> Result<()> {
     if let PackageDiff::Changed {
         first,
         second,
         history,
     } = package
     {
-        let v1 = &first.version.as_ref()?;
-        let v2 = &second.version.as_ref()?;
-        let uri = second.get_git_source().or_else(|| {
-            error!("unsupported uri");
-            None
-        })?;
-
-        let repo = workspace
-            .create_repo(&uri)
-            .map_err(|_| {
-                error!("can't get repo from {}", uri);
-            })
-            .ok()?;
-
-        let commits = GitHistoryBuilder { repo: &repo }
-            .history(v1, v2, short)
-            .map_err(|_| {
-                error!("can't build detailed history for {}", uri);
-            })
-            .ok()?;
+        let (v1, v2) = match (&first.version, &second.version) {
+            (Some(v1), Some(v2)) => (v1, v2),
+            _ => return Ok(()),
+        };
+        let uri = match second.get_git_source() {
+            Some(uri) => uri,
+            None => {
+                debug!("{} is not a git package", second.name);
+                return Ok(());
+            }
+        };
+
+        let repo = workspace.create_repo(&uri)?;
+        let commits = build(&repo, &uri, v1, v2, short)?;
 
         debug!("add {} commits to {}", commits.len(), second.name);
 
         let history = history.get_or_insert(Vec::new());
         commits.iter().for_each(|r| history.push(r.clone()));
-        Some(())
-    } else {
-        None
     }
+    Ok(())
 }
 
-pub fn append(diffs: &mut PackagesDiff, options: &Options) -> Result<(), Error> {
+/// Append git history to every changed package. Packages that failed are
+/// skipped and returned along with the reason.
+pub fn append(diffs: &mut PackagesDiff, options: &Options) -> Result<Vec<(String, Error)>> {
     let mut workspace = GitWorkspace::new(options);
     workspace.init()?;
-    for (_, c) in diffs.iter_mut() {
-        append_one(&mut workspace, c, options.short_history);
+    let mut failures = Vec::new();
+    for (name, c) in diffs.iter_mut() {
+        if let Err(err) = append_one(&mut workspace, c, options.short_history) {
+            error!("can't build detailed history for {}: {}", name, err);
+            failures.push((name.clone(), err));
+        }
     }
-    Ok(())
+    Ok(failures)
 }
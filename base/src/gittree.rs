@@ -0,0 +1,167 @@
+use crate::error::{Error, Result};
+use crate::external;
+use crate::gitworkspace::{self, RefKind};
+use crate::mkfile::{self, Duplicate, MkFile, Precedence};
+use crate::package::{PackageReader, Packages};
+use git2::{ObjectType, Repository, TreeWalkMode, TreeWalkResult};
+use log::info;
+use std::path::Path;
+
+/// Reads mk files of a revision straight from the object database of a
+/// Buildroot (or br2-external) repository, the working tree is not touched.
+/// Package locations are `<rev>:<path>`.
+pub struct GitTreeReader {
+    repo: String,
+    rev: String,
+    subdir: String,
+    precedence: Precedence,
+    duplicates: Vec<Duplicate>,
+}
+
+impl GitTreeReader {
+    pub fn new(repo: &str, rev: &str) -> GitTreeReader {
+        GitTreeReader {
+            repo: repo.to_owned(),
+            rev: rev.to_owned(),
+            subdir: "package".to_owned(),
+            precedence: Precedence::default(),
+            duplicates: Vec::new(),
+        }
+    }
+
+    pub fn precedence(mut self, precedence: Precedence) -> GitTreeReader {
+        self.precedence = precedence;
+        self
+    }
+
+    /// Directory with mk files inside the repository, `package` by default.
+    pub fn subdir(mut self, subdir: &str) -> GitTreeReader {
+        self.subdir = subdir.trim_matches('/').to_owned();
+        self
+    }
+}
+
+impl PackageReader for GitTreeReader {
+    type Error = Error;
+    fn read(&mut self) -> Result<Packages> {
+        let repo = Repository::discover(&self.repo).map_err(|err| Error::git(&self.repo, err))?;
+        let git_error = |err| Error::git(&self.repo, err);
+
+        let commit = gitworkspace::resolve(&repo, RefKind::Any, &self.rev)?;
+        let tree = commit.peel_to_tree().map_err(git_error)?;
+        let tree = if self.subdir.is_empty() {
+            tree
+        } else {
+            let entry = tree.get_path(Path::new(&self.subdir)).map_err(|_| {
+                Error::missing_object(&self.repo, &format!("{}:{}", self.rev, self.subdir))
+            })?;
+            repo.find_tree(entry.id()).map_err(git_error)?
+        };
+
+        let mut files = Vec::new();
+        tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+            let path = format!("{}{}", root, entry.name().unwrap_or_default());
+            if entry.kind() == Some(ObjectType::Blob) && external::is_package_mk(Path::new(&path)) {
+                files.push((path, entry.id()));
+            }
+            TreeWalkResult::Ok
+        })
+        .map_err(git_error)?;
+
+        let prefix = if self.subdir.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", self.subdir)
+        };
+        let packages = files.into_iter().map(|(path, id)| {
+            let location = format!("{}:{}{}", self.rev, prefix, path);
+            info!("process {}", location);
+            let blob = repo.find_blob(id).map_err(git_error)?;
+            MkFile::new(&location).parse(blob.content())
+        });
+        let (packages, duplicates) = mkfile::collect_packages(packages, self.precedence)?;
+        self.duplicates = duplicates;
+        Ok(packages)
+    }
+
+    fn duplicates(&self) -> &[Duplicate] {
+        &self.duplicates
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutils::TempDir;
+    use git2::Signature;
+
+    fn commit_files(repo: &Repository, files: &[(&str, &str)], message: &str) -> git2::Oid {
+        let workdir = repo.workdir().unwrap();
+        let mut index = repo.index().unwrap();
+        for (path, data) in files {
+            let file = workdir.join(path);
+            std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+            std::fs::write(&file, data).unwrap();
+            index.add_path(std::path::Path::new(path)).unwrap();
+        }
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = Signature::now("test", "test@example.com").unwrap();
+        let parent = repo.head().ok().and_then(|x| x.peel_to_commit().ok());
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &parents)
+            .unwrap()
+    }
+
+    #[test]
+    fn revisions() {
+        let path = TempDir::new("gittree");
+        let repo = Repository::init(&path).unwrap();
+        let first = commit_files(
+            &repo,
+            &[
+                ("package/zlib/zlib.mk", "ZLIB_VERSION = 1.2.13\n"),
+                ("Makefile", "all:\n"),
+            ],
+            "first",
+        );
+        commit_files(
+            &repo,
+            &[
+                ("package/zlib/zlib.mk", "ZLIB_VERSION = 1.3\n"),
+                ("package/xz/xz.mk", "XZ_VERSION = 5.4.5\n"),
+                ("package/compat/xz/xz.mk", "XZ_VERSION = 5.2\n"),
+                ("package/pkg-utils.mk", "PKG_VERSION = 1.0\n"),
+                ("package/xz/xz-helpers.mk", "XZ_HELPERS_VERSION = 1.0\n"),
+            ],
+            "second",
+        );
+        std::fs::write(path.join("package/zlib/zlib.mk"), "ZLIB_VERSION = dirty\n").unwrap();
+
+        let repo_path = path.to_str().unwrap();
+        let packages = GitTreeReader::new(repo_path, &first.to_string())
+            .read()
+            .unwrap();
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages["zlib"].version.as_deref(), Some("1.2.13"));
+        assert_eq!(
+            packages["zlib"].location,
+            Some(format!("{}:package/zlib/zlib.mk", first))
+        );
+
+        let mut reader = GitTreeReader::new(repo_path, "HEAD");
+        let packages = reader.read().unwrap();
+        assert_eq!(packages.len(), 2);
+        assert_eq!(packages["zlib"].version.as_deref(), Some("1.3"));
+        assert_eq!(packages["xz"].version.as_deref(), Some("5.2"));
+        assert_eq!(reader.duplicates().len(), 1);
+
+        let mut reader = GitTreeReader::new(repo_path, "HEAD").precedence(Precedence::Fail);
+        assert!(matches!(reader.read(), Err(Error::Parse { .. })));
+
+        assert!(GitTreeReader::new(repo_path, "HEAD")
+            .subdir("boot")
+            .read()
+            .is_err());
+    }
+}
@@ -1,15 +1,15 @@
+use crate::error::{Error, Result};
 use git2::*;
 use log::{debug, info, warn};
-use std::io::{Error, ErrorKind};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 fn get_repo_name(uri: &str) -> Option<&str> {
-    let parts = uri.split('/');
+    let mut parts = uri.split('/');
     let len = uri.len();
     let git = len > 4 && &uri[len - 4..len] == ".git";
 
     if git && parts.clone().count() > 1 {
-        parts.last().map(|s| {
+        parts.next_back().map(|s| {
             let len = s.len();
             &s[..len - 4]
         })
@@ -18,6 +18,31 @@ fn get_repo_name(uri: &str) -> Option<&str> {
     }
 }
 
+/// Default branch of the remote, as it reported after a fetch, or the one
+/// the seed cache points to.
+fn remote_head(origin: &mut Remote, seed: &Path) -> Option<String> {
+    if let Some(branch) = origin
+        .default_branch()
+        .ok()
+        .and_then(|x| x.as_str().map(|x| x.to_owned()))
+        .and_then(|x| x.strip_prefix("refs/heads/").map(|x| x.to_owned()))
+    {
+        return Some(branch);
+    }
+
+    let seed = Repository::open(seed).ok()?;
+    let target = |name: &str| {
+        seed.find_reference(name)
+            .ok()
+            .and_then(|x| x.symbolic_target().map(|x| x.to_owned()))
+    };
+    target("refs/remotes/origin/HEAD")
+        .and_then(|x| x.strip_prefix("refs/remotes/origin/").map(|x| x.to_owned()))
+        .or_else(|| {
+            target("HEAD").and_then(|x| x.strip_prefix("refs/heads/").map(|x| x.to_owned()))
+        })
+}
+
 fn is_dir_exist(path: &str) -> bool {
     std::fs::read_dir(path).is_ok()
 }
@@ -28,6 +53,8 @@ pub struct Options {
     pub key: String,
     pub clean_workspace: bool,
     pub short_history: bool,
+    /// Buildroot download directory: its `<pkg>/git` caches seed new clones.
+    pub dl_dir: Option<String>,
 }
 
 impl Options {
@@ -37,6 +64,7 @@ impl Options {
             key: "".to_owned(),
             clean_workspace: false,
             short_history: true,
+            dl_dir: None,
         }
     }
 }
@@ -59,40 +87,186 @@ pub fn get_object_hash(object: &Object) -> Option<String> {
     Some(object.id().to_string())
 }
 
-pub fn get_tag<'a>(repo: &'a Repository, name: &str) -> Option<Object<'a>> {
-    let mut result = None;
-    let _ = repo.tag_foreach(|oid, tagname| {
-        let tagname = std::str::from_utf8(tagname).unwrap();
-        let shortname = name;
-        let longname = format!("refs/tags/{}", name);
+fn get_repo_url(repo: &Repository) -> String {
+    repo.find_remote("origin")
+        .ok()
+        .and_then(|remote| remote.url().map(|url| url.to_owned()))
+        .unwrap_or_else(|| repo.path().to_string_lossy().into_owned())
+}
 
-        debug!("visit tag {}", tagname);
+/// What kind of ref a short name refers to first. Full refs
+/// (`refs/...`) and revparse expressions are resolved the same way for all
+/// kinds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefKind {
+    /// Remote branches of a workspace clone, then local ones and tags.
+    Branch,
+    Tag,
+    /// Tags, then local branches of a checkout, then remote ones.
+    Any,
+}
 
-        if tagname == shortname || tagname == longname {
-            result = repo.find_tag(oid).map(|tag| tag.target().unwrap()).ok();
-            false
-        } else {
-            true
+fn ref_candidates(kind: RefKind, name: &str) -> Vec<String> {
+    if name.starts_with("refs/") {
+        return vec![name.to_owned()];
+    }
+
+    let local = vec![format!("refs/heads/{}", name)];
+    let remote = vec![
+        format!("refs/remotes/origin/{}", name),
+        format!("refs/remotes/{}", name),
+    ];
+    let tags = vec![format!("refs/tags/{}", name)];
+    match kind {
+        RefKind::Branch => [remote, local, tags].concat(),
+        RefKind::Tag => [tags, remote, local].concat(),
+        // a local checkout (like the tree brdiff reads): local branches win
+        RefKind::Any => [tags, local, remote].concat(),
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { prev } else { prev + 1 };
+            prev = row[j + 1];
+            row[j + 1] = cost.min(row[j] + 1).min(prev + 1);
         }
-    });
-    result
+    }
+    row[b.len()]
 }
 
-pub fn get_latest_commit<'a>(repo: &'a Repository, head: &str) -> Option<Object<'a>> {
-    let remote_head = format!("origin/{}", head);
+/// Existing refs that look like `name`: contain it, or differ by a couple of
+/// characters.
+fn near_matches(repo: &Repository, name: &str) -> Vec<String> {
+    let short = name
+        .trim_start_matches("refs/")
+        .trim_start_matches("tags/")
+        .trim_start_matches("remotes/")
+        .trim_start_matches("origin/");
+    let mut result: Vec<(usize, String)> = repo
+        .references()
+        .map(|refs| {
+            refs.flatten()
+                .filter_map(|r| r.shorthand().map(|x| x.to_owned()))
+                .collect()
+        })
+        .unwrap_or_else(|_| Vec::new())
+        .into_iter()
+        .filter_map(|candidate: String| {
+            let last = candidate.trim_start_matches("origin/");
+            let distance = edit_distance(short, last);
+            if distance <= 2 || (short.len() > 2 && last.contains(short)) {
+                Some((distance, candidate))
+            } else {
+                None
+            }
+        })
+        .collect();
+    result.sort();
+    result.dedup_by(|a, b| a.1 == b.1);
+    result.into_iter().take(5).map(|(_, x)| x).collect()
+}
 
-    for branch in repo.branches(None).unwrap() {
-        let info = branch.unwrap().0;
-        let name = info.name().unwrap().unwrap();
-        debug!("visit branch {}", name);
+/// Resolve a ref name or revspec into a commit. Tries full refs, remote
+/// branches (`origin/<name>`), tags (both annotated and lightweight) and local
+/// branches directly, then falls back to revparse expressions (`HEAD~2`,
+/// hashes, `origin/HEAD`). `HEAD` means the default branch of the remote, if
+/// there is one, and the local `HEAD` otherwise.
+/// Also returns the full name of the ref `name` resolved as, if it isn't a
+/// revparse expression.
+pub fn resolve_ref<'a>(
+    repo: &'a Repository,
+    kind: RefKind,
+    name: &str,
+) -> Result<(Object<'a>, Option<String>)> {
+    let url = get_repo_url(repo);
+    let name = name.trim();
+    let remote_head = "refs/remotes/origin/HEAD";
+    let spec = if name == "HEAD" && repo.find_reference(remote_head).is_ok() {
+        remote_head
+    } else {
+        name
+    };
 
-        if name == head || name == remote_head {
-            if let Ok(comm) = info.get().peel_to_commit() {
-                return Some(comm.as_object().clone());
-            }
+    for candidate in ref_candidates(kind, spec) {
+        if let Ok(reference) = repo.find_reference(&candidate) {
+            debug!("{} resolved as {}", name, candidate);
+            return reference
+                .peel_to_commit()
+                .map(|commit| (commit.into_object(), Some(candidate)))
+                .map_err(|err| Error::git(&url, err));
         }
     }
-    None
+
+    if let Ok(object) = repo.revparse_single(spec) {
+        debug!("{} resolved as revspec", name);
+        return object
+            .peel_to_commit()
+            .map(|commit| (commit.into_object(), None))
+            .map_err(|err| Error::git(&url, err));
+    }
+
+    Err(Error::missing_object_like(
+        &url,
+        name,
+        near_matches(repo, spec),
+    ))
+}
+
+pub fn resolve<'a>(repo: &'a Repository, kind: RefKind, name: &str) -> Result<Object<'a>> {
+    resolve_ref(repo, kind, name).map(|(object, _)| object)
+}
+
+pub fn get_tag<'a>(repo: &'a Repository, name: &str) -> Result<Object<'a>> {
+    resolve(repo, RefKind::Tag, name)
+}
+
+/// Tag with the commit it points to. `time` is the tagger time for annotated
+/// tags and the commit time for lightweight ones (seconds since epoch).
+#[derive(Debug, Clone)]
+pub struct TagInfo {
+    pub name: String,
+    pub commit: Oid,
+    pub time: i64,
+}
+
+pub fn get_tags(repo: &Repository) -> Result<Vec<TagInfo>> {
+    let url = get_repo_url(repo);
+    let names = repo.tag_names(None).map_err(|err| Error::git(&url, err))?;
+    let mut result = Vec::new();
+    for name in names.iter().flatten() {
+        let object = match repo.revparse_single(&format!("refs/tags/{}", name)) {
+            Ok(object) => object,
+            Err(err) => {
+                debug!("skip tag {}: {}", name, err);
+                continue;
+            }
+        };
+        let commit = match object.peel_to_commit() {
+            Ok(commit) => commit,
+            Err(_) => continue,
+        };
+        let time = object
+            .as_tag()
+            .and_then(|tag| tag.tagger())
+            .map(|tagger| tagger.when().seconds())
+            .unwrap_or_else(|| commit.time().seconds());
+        result.push(TagInfo {
+            name: name.to_owned(),
+            commit: commit.id(),
+            time,
+        });
+    }
+    Ok(result)
+}
+
+pub fn get_latest_commit<'a>(repo: &'a Repository, head: &str) -> Result<Object<'a>> {
+    resolve(repo, RefKind::Branch, head)
 }
 
 impl GitWorkspace {
@@ -102,64 +276,320 @@ impl GitWorkspace {
         }
     }
 
-    pub fn init(&self) -> Result<(), Error> {
+    pub fn init(&self) -> Result<()> {
         let path = &self.options.workdir;
 
         if self.options.clean_workspace && is_dir_exist(path) {
             debug!("removing directory: {}", path);
-            std::fs::remove_dir_all(path)?;
+            std::fs::remove_dir_all(path).map_err(|err| Error::io(path, err))?;
         }
 
         if !is_dir_exist(path) {
             debug!("creating directory: {}", path);
-            std::fs::create_dir_all(path)
+            std::fs::create_dir_all(path).map_err(|err| Error::io(path, err))
         } else {
             Ok(())
         }
     }
 
-    pub fn create_repo(&self, uri: &str) -> Result<Repository, Error> {
+    pub fn create_repo(&self, uri: &str) -> Result<Repository> {
         debug!("creating repo for:{}", uri);
 
-        let repo = get_repo_name(uri)
-            .ok_or_else(|| Error::new(ErrorKind::Other, "can't find repo name"))?;
+        let repo = get_repo_name(uri).ok_or_else(|| Error::unsupported_source(uri))?;
 
         let path = format!("{}/{}", self.options.workdir, repo);
 
         if !is_dir_exist(&path) {
-            self.clone_repo(uri, &path)?;
+            match self.find_seed(uri, repo) {
+                Some(seed) => {
+                    if let Err(err) = self.seed_repo(&seed, uri, &path) {
+                        warn!(
+                            "{}: seeding from {} failed, cloning: {}",
+                            uri,
+                            seed.display(),
+                            err
+                        );
+                        std::fs::remove_dir_all(&path).map_err(|err| Error::io(&path, err))?;
+                        self.clone_repo(uri, &path)?;
+                    }
+                }
+                None => self.clone_repo(uri, &path)?,
+            }
         }
 
         self.open_repo(&path)
     }
 
-    fn open_repo(&self, path: &str) -> Result<Repository, Error> {
-        info!("opening repo {}", path);
-        Repository::open(path).map_err(|_| Error::new(ErrorKind::Other, "repo open error"))
+    /// A git cache of the download directory with `uri` as origin:
+    /// `<dl>/<name>/git` is tried first, then caches of other packages.
+    fn find_seed(&self, uri: &str, name: &str) -> Option<PathBuf> {
+        let dl = Path::new(self.options.dl_dir.as_deref()?);
+        let is_seed = |path: &Path| {
+            Repository::open(path).is_ok_and(|repo| {
+                repo.find_remote("origin")
+                    .is_ok_and(|remote| remote.url() == Some(uri))
+            })
+        };
+
+        let guess = dl.join(name).join("git");
+        if is_seed(&guess) {
+            return Some(guess);
+        }
+        std::fs::read_dir(dl)
+            .ok()?
+            .filter_map(|x| x.ok())
+            .map(|x| x.path().join("git"))
+            .find(|x| x != &guess && x.is_dir() && is_seed(x))
     }
 
-    fn clone_repo(&self, uri: &str, path: &str) -> Result<(), Error> {
-        info!("cloning {} into {}", uri, path);
-        let mut callbacks = RemoteCallbacks::new();
-        let mut builder = git2::build::RepoBuilder::new();
-        let mut options = git2::FetchOptions::new();
+    /// Create the clone from a download cache: take its branches and tags
+    /// locally, then fetch what's new from `uri`. If the fetch fails, the
+    /// cached state is used.
+    fn seed_repo(&self, seed: &Path, uri: &str, path: &str) -> Result<()> {
+        info!("seeding {} from {}", path, seed.display());
+        let seed_url = seed.to_string_lossy();
+        let repo = Repository::init(path).map_err(|err| Error::git(path, err))?;
+        let mut local = repo
+            .remote_anonymous(&seed_url)
+            .map_err(|err| Error::git(&seed_url, err))?;
+        for refspec in [
+            "+refs/heads/*:refs/remotes/origin/*",
+            "+refs/remotes/origin/*:refs/remotes/origin/*",
+            "+refs/tags/*:refs/tags/*",
+        ] {
+            local
+                .fetch(&[refspec], None, None)
+                .map_err(|err| Error::git(&seed_url, err))?;
+        }
 
+        let mut origin = repo
+            .remote("origin", uri)
+            .map_err(|err| Error::git(uri, err))?;
+        let mut options = self.fetch_options();
+        options.download_tags(AutotagOption::All);
+        if let Err(err) = origin.fetch::<&str>(&[], Some(&mut options), None) {
+            warn!("{}: fetch failed, using the cached state: {}", uri, err);
+        }
+
+        // clones have it, and HEAD is resolved through it
+        let branches = remote_head(&mut origin, seed)
+            .into_iter()
+            .chain(["master".to_owned(), "main".to_owned()]);
+        for branch in branches {
+            let target = format!("refs/remotes/origin/{}", branch);
+            if repo.find_reference(&target).is_ok() {
+                repo.reference_symbolic("refs/remotes/origin/HEAD", &target, true, "seed")
+                    .map_err(|err| Error::git(path, err))?;
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn fetch_options(&self) -> FetchOptions<'_> {
+        let mut callbacks = RemoteCallbacks::new();
         if !self.options.key.is_empty() {
             callbacks.credentials(|_url, username_from_url, _allowed_types| {
                 Cred::ssh_key(
-                    username_from_url.unwrap(),
+                    username_from_url.unwrap_or("git"),
                     None,
                     std::path::Path::new(&self.options.key),
                     None,
                 )
             });
         }
+        let mut options = FetchOptions::new();
         options.remote_callbacks(callbacks);
-        builder.fetch_options(options);
-        builder.clone(uri, Path::new(&path)).map_err(|err| {
-            warn!("clone error:{}", err);
-            Error::new(ErrorKind::Other, "repo clone error")
-        })?;
+        options
+    }
+
+    fn open_repo(&self, path: &str) -> Result<Repository> {
+        info!("opening repo {}", path);
+        Repository::open(path).map_err(|err| Error::git(path, err))
+    }
+
+    fn clone_repo(&self, uri: &str, path: &str) -> Result<()> {
+        info!("cloning {} into {}", uri, path);
+        let mut builder = git2::build::RepoBuilder::new();
+        builder.fetch_options(self.fetch_options());
+        builder
+            .clone(uri, Path::new(&path))
+            .map_err(|err| Error::git(uri, err))?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutils::TempDir;
+
+    #[test]
+    fn missing_objects() {
+        let path = TempDir::new("gitworkspace");
+        let repo = Repository::init(&path).unwrap();
+
+        match get_tag(&repo, "v1.0") {
+            Err(Error::MissingObject { name, .. }) => assert_eq!(name, "v1.0"),
+            _ => unreachable!(),
+        }
+
+        match get_latest_commit(&repo, "master") {
+            Err(Error::MissingObject { name, .. }) => assert_eq!(name, "master"),
+            _ => unreachable!(),
+        };
+    }
+
+    #[test]
+    fn resolve_refs() {
+        let path = TempDir::new("gitworkspace-refs");
+        let repo = Repository::init(&path).unwrap();
+        let sig = git2::Signature::now("test", "test@example.com").unwrap();
+        let tree = repo
+            .find_tree(repo.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        let first = repo.commit(None, &sig, &sig, "first", &tree, &[]).unwrap();
+        let first = repo.find_commit(first).unwrap();
+        let second = repo
+            .commit(None, &sig, &sig, "second", &tree, &[&first])
+            .unwrap();
+
+        repo.reference("refs/remotes/origin/master", second, false, "")
+            .unwrap();
+        repo.reference("refs/remotes/origin/HEAD", second, false, "")
+            .unwrap();
+        repo.tag_lightweight("v1.0", first.as_object(), false)
+            .unwrap();
+        repo.tag("v1.1", first.as_object(), &sig, "v1.1", false)
+            .unwrap();
+
+        let id = |object: Object| object.id();
+        assert_eq!(id(get_latest_commit(&repo, "master").unwrap()), second);
+        assert_eq!(
+            id(get_latest_commit(&repo, "origin/master").unwrap()),
+            second
+        );
+        assert_eq!(id(get_latest_commit(&repo, "HEAD").unwrap()), second);
+        assert_eq!(
+            id(get_latest_commit(&repo, "origin/master~1").unwrap()),
+            first.id()
+        );
+        assert_eq!(id(get_tag(&repo, "v1.0").unwrap()), first.id());
+        assert_eq!(id(get_tag(&repo, "refs/tags/v1.1").unwrap()), first.id());
+
+        let name = |name| resolve_ref(&repo, RefKind::Tag, name).unwrap().1;
+        assert_eq!(name("v1.0").as_deref(), Some("refs/tags/v1.0"));
+        assert_eq!(
+            name("master").as_deref(),
+            Some("refs/remotes/origin/master")
+        );
+        assert_eq!(name("v1.0~0"), None);
+
+        repo.reference("refs/heads/master", first.id(), false, "")
+            .unwrap();
+        assert_eq!(
+            id(resolve(&repo, RefKind::Any, "master").unwrap()),
+            first.id()
+        );
+        assert_eq!(id(get_latest_commit(&repo, "master").unwrap()), second);
+
+        match get_tag(&repo, "v1.2") {
+            Err(Error::MissingObject { candidates, .. }) => {
+                assert!(candidates.contains(&"v1.0".to_owned()));
+                assert!(candidates.contains(&"v1.1".to_owned()));
+            }
+            _ => unreachable!(),
+        };
+    }
+
+    #[test]
+    fn seed_from_dl() {
+        let dir = TempDir::new("gitworkspace-seed");
+        let uri = "file:///nonexistent/foo.git";
+
+        let cache = Repository::init(dir.join("dl").join("foo").join("git")).unwrap();
+        cache.remote("origin", uri).unwrap();
+        let sig = git2::Signature::now("test", "test@example.com").unwrap();
+        let tree = cache
+            .find_tree(cache.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        let commit = cache.commit(None, &sig, &sig, "first", &tree, &[]).unwrap();
+        cache
+            .reference("refs/remotes/origin/master", commit, false, "")
+            .unwrap();
+        cache
+            .tag_lightweight("v1.0", &cache.find_object(commit, None).unwrap(), false)
+            .unwrap();
+
+        let mut options = Options::new(dir.join("ws").to_str().unwrap());
+        options.dl_dir = Some(dir.join("dl").to_string_lossy().to_string());
+        let ws = GitWorkspace::new(&options);
+        ws.init().unwrap();
+        let repo = ws.create_repo(uri).unwrap();
+        assert_eq!(get_tag(&repo, "v1.0").unwrap().id(), commit);
+        assert_eq!(get_latest_commit(&repo, "HEAD").unwrap().id(), commit);
+    }
+
+    fn commit(repo: &Repository, message: &str) -> Oid {
+        let sig = git2::Signature::now("test", "test@example.com").unwrap();
+        let tree = repo
+            .find_tree(repo.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        repo.commit(None, &sig, &sig, message, &tree, &[]).unwrap()
+    }
+
+    #[test]
+    fn seed_remote_head() {
+        let dir = TempDir::new("gitworkspace-seed-head");
+        let uri = "file:///nonexistent/foo.git";
+
+        let cache = Repository::init(dir.join("dl").join("foo").join("git")).unwrap();
+        cache.remote("origin", uri).unwrap();
+        let first = commit(&cache, "first");
+        let second = commit(&cache, "second");
+        cache
+            .reference("refs/heads/next", first, false, "")
+            .unwrap();
+        cache
+            .reference("refs/heads/develop", second, false, "")
+            .unwrap();
+        cache.set_head("refs/heads/develop").unwrap();
+
+        let mut options = Options::new(dir.join("ws").to_str().unwrap());
+        options.dl_dir = Some(dir.join("dl").to_string_lossy().to_string());
+        let ws = GitWorkspace::new(&options);
+        ws.init().unwrap();
+        let repo = ws.create_repo(uri).unwrap();
+        assert_eq!(get_latest_commit(&repo, "HEAD").unwrap().id(), second);
+    }
+
+    #[test]
+    fn seed_failure() {
+        let dir = TempDir::new("gitworkspace-seed-failure");
+        let upstream = Repository::init(dir.join("upstream").join("foo.git")).unwrap();
+        let head = commit(&upstream, "first");
+        upstream
+            .reference("refs/heads/master", head, false, "")
+            .unwrap();
+        upstream.set_head("refs/heads/master").unwrap();
+        let uri = format!("file://{}", dir.join("upstream").join("foo.git").display());
+
+        let cache_path = dir.join("dl").join("foo").join("git");
+        let cache = Repository::init(&cache_path).unwrap();
+        cache.remote("origin", &uri).unwrap();
+        // a branch without its objects: the fetch from the cache fails
+        std::fs::write(
+            cache_path.join(".git/refs/heads/broken"),
+            "0123456789012345678901234567890123456789\n",
+        )
+        .unwrap();
+
+        let mut options = Options::new(dir.join("ws").to_str().unwrap());
+        options.dl_dir = Some(dir.join("dl").to_string_lossy().to_string());
+        let ws = GitWorkspace::new(&options);
+        ws.init().unwrap();
+        let repo = ws.create_repo(&uri).unwrap();
+        assert_eq!(get_latest_commit(&repo, "HEAD").unwrap().id(), head);
+        assert!(repo.find_reference("refs/remotes/origin/broken").is_err());
+    }
+}
@@ -0,0 +1,401 @@
+use crate::error::{Error, Result};
+use crate::package::Packages;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// At most that many paths are returned by [`Graph::why`].
+const MAX_PATHS: usize = 32;
+
+/// Result of [`Graph::why`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WhyPaths {
+    pub paths: Vec<Vec<String>>,
+    /// More paths exist than [`MAX_PATHS`].
+    pub truncated: bool,
+}
+
+impl Display for WhyPaths {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for path in &self.paths {
+            writeln!(f, "{}", path.join(" -> "))?;
+        }
+        if self.truncated {
+            writeln!(f, "... only the first {} paths are shown", MAX_PATHS)?;
+        }
+        Ok(())
+    }
+}
+
+/// Export formats of a graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    Dot,
+    Json,
+    Tree,
+}
+
+impl FromStr for GraphFormat {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "dot" => Ok(GraphFormat::Dot),
+            "json" => Ok(GraphFormat::Json),
+            "tree" => Ok(GraphFormat::Tree),
+            other => Err(Error::parse(
+                other,
+                None,
+                "unknown graph format, expected dot, json or tree",
+            )),
+        }
+    }
+}
+
+pub type Edge = (String, String);
+
+/// Package dependency graph. Dependencies that are not in the package set
+/// are nodes too.
+#[derive(Debug, Default)]
+pub struct Graph {
+    dependencies: BTreeMap<String, BTreeSet<String>>,
+    dependents: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl Graph {
+    pub fn build(packages: &Packages) -> Graph {
+        let mut graph = Graph::default();
+        for package in packages.values() {
+            graph.dependencies.entry(package.name.clone()).or_default();
+            graph.dependents.entry(package.name.clone()).or_default();
+            for dependency in &package.dependencies {
+                graph.add_edge(&package.name, dependency);
+            }
+        }
+        graph
+    }
+
+    fn add_edge(&mut self, from: &str, to: &str) {
+        self.dependencies
+            .entry(from.to_owned())
+            .or_default()
+            .insert(to.to_owned());
+        self.dependencies.entry(to.to_owned()).or_default();
+        self.dependents
+            .entry(to.to_owned())
+            .or_default()
+            .insert(from.to_owned());
+        self.dependents.entry(from.to_owned()).or_default();
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.dependencies.contains_key(name)
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &String> {
+        self.dependencies.keys()
+    }
+
+    pub fn edges(&self) -> BTreeSet<Edge> {
+        self.dependencies
+            .iter()
+            .flat_map(|(from, deps)| deps.iter().map(|to| (from.clone(), to.clone())))
+            .collect()
+    }
+
+    /// The root set: packages nothing depends on.
+    pub fn roots(&self) -> Vec<&String> {
+        self.dependents
+            .iter()
+            .filter(|(_, x)| x.is_empty())
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// Packages that depend on the package, directly or not.
+    pub fn rdeps(&self, name: &str) -> BTreeSet<String> {
+        let mut result = BTreeSet::new();
+        let mut queue = vec![name.to_owned()];
+        while let Some(current) = queue.pop() {
+            for dependent in self.dependents.get(&current).into_iter().flatten() {
+                if result.insert(dependent.clone()) {
+                    queue.push(dependent.clone());
+                }
+            }
+        }
+        result.remove(name);
+        result
+    }
+
+    /// Why the package is included: paths from the root set down to it.
+    pub fn why(&self, name: &str) -> WhyPaths {
+        let mut paths = Vec::new();
+        let mut path = vec![name.to_owned()];
+        self.collect_paths(&mut path, &mut paths);
+        let truncated = paths.len() > MAX_PATHS;
+        paths.truncate(MAX_PATHS);
+        WhyPaths { paths, truncated }
+    }
+
+    /// Collects one path over the limit to know that some are left out.
+    fn collect_paths(&self, path: &mut Vec<String>, result: &mut Vec<Vec<String>>) {
+        if result.len() > MAX_PATHS {
+            return;
+        }
+        let current = path.last().cloned().unwrap_or_default();
+        let dependents = match self.dependents.get(&current) {
+            Some(x) if !x.is_empty() => x,
+            _ => {
+                if path.len() > 1 {
+                    result.push(path.iter().rev().cloned().collect());
+                }
+                return;
+            }
+        };
+        for dependent in dependents {
+            // dependency loops are possible in broken trees
+            if path.contains(dependent) {
+                continue;
+            }
+            path.push(dependent.clone());
+            self.collect_paths(path, result);
+            path.pop();
+        }
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut result = String::from("digraph packages {\n    rankdir=LR;\n");
+        for node in self.nodes() {
+            result.push_str(&format!("    \"{}\";\n", node));
+        }
+        for (from, to) in self.edges() {
+            result.push_str(&format!("    \"{}\" -> \"{}\";\n", from, to));
+        }
+        result.push_str("}\n");
+        result
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "nodes": self.nodes().collect::<Vec<&String>>(),
+            "edges": self.edges()
+                .into_iter()
+                .map(|(from, to)| json!({ "from": from, "to": to }))
+                .collect::<Vec<Value>>(),
+        })
+    }
+
+    /// Text tree from the root set (or the given package). Subtrees already
+    /// shown are marked with `(*)` and not expanded again.
+    pub fn to_tree(&self, root: Option<&str>) -> String {
+        let roots: Vec<&str> = match root {
+            Some(root) => vec![root],
+            None => self.roots().into_iter().map(|x| x.as_str()).collect(),
+        };
+        let mut result = String::new();
+        let mut shown = BTreeSet::new();
+        for root in roots {
+            self.write_tree(root, 0, &mut shown, &mut result);
+        }
+        result
+    }
+
+    fn write_tree(&self, name: &str, depth: usize, shown: &mut BTreeSet<String>, out: &mut String) {
+        let deps = self.dependencies.get(name).filter(|x| !x.is_empty());
+        let repeated = deps.is_some() && !shown.insert(name.to_owned());
+        out.push_str(&format!(
+            "{}{}{}\n",
+            "    ".repeat(depth),
+            name,
+            if repeated { " (*)" } else { "" }
+        ));
+        if repeated {
+            return;
+        }
+        for dependency in deps.into_iter().flatten() {
+            self.write_tree(dependency, depth + 1, shown, out);
+        }
+    }
+
+    pub fn export(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.to_dot(),
+            GraphFormat::Json => serde_json::to_string_pretty(&self.to_json()).unwrap_or_default(),
+            GraphFormat::Tree => self.to_tree(None),
+        }
+    }
+}
+
+/// Edges added and removed between two graphs.
+#[derive(Debug, Default)]
+pub struct GraphDiff {
+    pub added: BTreeSet<Edge>,
+    pub removed: BTreeSet<Edge>,
+    /// Edges of both graphs.
+    pub kept: BTreeSet<Edge>,
+}
+
+impl GraphDiff {
+    pub fn build(first: &Graph, second: &Graph) -> GraphDiff {
+        let (first, second) = (first.edges(), second.edges());
+        GraphDiff {
+            added: second.difference(&first).cloned().collect(),
+            removed: first.difference(&second).cloned().collect(),
+            kept: first.intersection(&second).cloned().collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+
+    /// Both graphs in one: added edges are green, removed ones red and
+    /// dashed.
+    pub fn to_dot(&self) -> String {
+        let mut result = String::from("digraph packages {\n    rankdir=LR;\n");
+        for (from, to) in &self.kept {
+            result.push_str(&format!("    \"{}\" -> \"{}\";\n", from, to));
+        }
+        for (from, to) in &self.added {
+            result.push_str(&format!("    \"{}\" -> \"{}\" [color=green];\n", from, to));
+        }
+        for (from, to) in &self.removed {
+            result.push_str(&format!(
+                "    \"{}\" -> \"{}\" [color=red, style=dashed];\n",
+                from, to
+            ));
+        }
+        result.push_str("}\n");
+        result
+    }
+
+    pub fn to_json(&self) -> Value {
+        let edges = |edges: &BTreeSet<Edge>| -> Vec<Value> {
+            edges
+                .iter()
+                .map(|(from, to)| json!({ "from": from, "to": to }))
+                .collect()
+        };
+        json!({
+            "added": edges(&self.added),
+            "removed": edges(&self.removed),
+        })
+    }
+
+    pub fn export(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.to_dot(),
+            GraphFormat::Json => serde_json::to_string_pretty(&self.to_json()).unwrap_or_default(),
+            GraphFormat::Tree => self.to_string(),
+        }
+    }
+}
+
+impl Display for GraphDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (from, to) in &self.added {
+            writeln!(f, "[+] {} -> {}", from, to)?;
+        }
+        for (from, to) in &self.removed {
+            writeln!(f, "[-] {} -> {}", from, to)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::package::Package;
+
+    fn packages(edges: &[(&str, &[&str])]) -> Packages {
+        edges
+            .iter()
+            .map(|(name, deps)| {
+                let package = Package {
+                    name: name.to_string(),
+                    dependencies: deps.iter().map(|x| x.to_string()).collect(),
+                    ..Default::default()
+                };
+                (name.to_string(), package)
+            })
+            .collect()
+    }
+
+    fn graph() -> Graph {
+        Graph::build(&packages(&[
+            ("app", &["libcurl", "zlib"]),
+            ("libcurl", &["openssl", "zlib"]),
+            ("openssl", &["zlib"]),
+            ("dropbear", &["zlib"]),
+        ]))
+    }
+
+    #[test]
+    fn queries() {
+        let graph = graph();
+        assert!(graph.contains("zlib"));
+        assert_eq!(graph.roots(), vec!["app", "dropbear"]);
+        assert_eq!(
+            graph.rdeps("openssl").into_iter().collect::<Vec<String>>(),
+            vec!["app", "libcurl"]
+        );
+        let why = graph.why("openssl");
+        assert_eq!(why.paths, vec![vec!["app", "libcurl", "openssl"]]);
+        assert!(!why.truncated);
+        assert_eq!(why.to_string(), "app -> libcurl -> openssl\n");
+        assert_eq!(graph.why("zlib").paths.len(), 4);
+        assert!(graph.why("app").paths.is_empty());
+
+        let names: Vec<String> = (0..MAX_PATHS + 1).map(|x| format!("app{}", x)).collect();
+        let edges: Vec<(&str, &[&str])> =
+            names.iter().map(|x| (x.as_str(), &["zlib"][..])).collect();
+        let why = Graph::build(&packages(&edges)).why("zlib");
+        assert!(why.truncated);
+        assert_eq!(why.paths.len(), MAX_PATHS);
+        assert!(why
+            .to_string()
+            .ends_with("only the first 32 paths are shown\n"));
+    }
+
+    #[test]
+    fn export() {
+        let graph = graph();
+        assert_eq!(
+            graph.to_tree(None),
+            "app\n    libcurl\n        openssl\n            zlib\n        zlib\n    zlib\ndropbear\n    zlib\n"
+        );
+        assert!(graph.to_dot().contains("    \"libcurl\" -> \"openssl\";\n"));
+        assert_eq!(graph.to_json()["edges"].as_array().unwrap().len(), 6);
+    }
+
+    #[test]
+    fn tree_repeated() {
+        let graph = Graph::build(&packages(&[
+            ("app", &["libcurl", "tool"]),
+            ("tool", &["libcurl"]),
+            ("libcurl", &["zlib"]),
+        ]));
+        assert_eq!(
+            graph.to_tree(Some("app")),
+            "app\n    libcurl\n        zlib\n    tool\n        libcurl (*)\n"
+        );
+    }
+
+    #[test]
+    fn diff() {
+        let second = Graph::build(&packages(&[
+            ("app", &["libcurl"]),
+            ("libcurl", &["openssl", "zlib", "nghttp2"]),
+            ("openssl", &["zlib"]),
+        ]));
+        let diff = GraphDiff::build(&graph(), &second);
+        assert_eq!(
+            diff.to_string(),
+            "[+] libcurl -> nghttp2\n[-] app -> zlib\n[-] dropbear -> zlib\n"
+        );
+        assert!(diff
+            .to_dot()
+            .contains("\"libcurl\" -> \"nghttp2\" [color=green];"));
+    }
+}
@@ -0,0 +1,18 @@
+use serde_json::Value;
+
+/// String value of `key`, if any.
+pub(crate) fn text(value: &Value, key: &str) -> Option<String> {
+    value
+        .get(key)
+        .and_then(|x| x.as_str())
+        .map(|x| x.to_owned())
+}
+
+/// Elements of the `key` array; nothing if it's missing or not an array.
+pub(crate) fn items<'a>(value: &'a Value, key: &str) -> impl Iterator<Item = &'a Value> {
+    value
+        .get(key)
+        .and_then(|x| x.as_array())
+        .into_iter()
+        .flatten()
+}
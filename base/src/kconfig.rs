@@ -0,0 +1,679 @@
+use crate::error::{Error, Result};
+use crate::external;
+use log::{debug, warn};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Passes over the entries until the values stop changing: a default may
+/// depend on a symbol defined further in the tree.
+const MAX_PASSES: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Bool,
+    Value,
+}
+
+/// One `config` block of a symbol.
+#[derive(Debug, Default)]
+struct Definition {
+    /// `if` blocks, menu and entry dependencies.
+    conditions: Vec<String>,
+    defaults: Vec<(String, Option<String>)>,
+    selects: Vec<(String, Option<String>)>,
+}
+
+/// A symbol with all its definitions: Buildroot defines some symbols in
+/// several files (`BR2_PACKAGE_PROVIDES_*` in every provider), and only one
+/// of them has the type.
+#[derive(Debug)]
+struct Entry {
+    name: String,
+    kind: Option<Kind>,
+    definitions: Vec<Definition>,
+    choice: Option<usize>,
+}
+
+impl Entry {
+    fn kind(&self) -> Kind {
+        self.kind.unwrap_or(Kind::Bool)
+    }
+
+    /// Definitions whose dependencies hold.
+    fn active<'a>(
+        &'a self,
+        values: &'a BTreeMap<String, String>,
+    ) -> impl Iterator<Item = &'a Definition> {
+        self.definitions
+            .iter()
+            .filter(|x| x.conditions.iter().all(|cond| eval(cond, values)))
+    }
+
+    fn is_visible(&self, values: &BTreeMap<String, String>) -> bool {
+        self.active(values).next().is_some()
+    }
+
+    /// The definition being read.
+    fn definition(&mut self) -> &mut Definition {
+        if self.definitions.is_empty() {
+            self.definitions.push(Definition::default());
+        }
+        let last = self.definitions.len() - 1;
+        &mut self.definitions[last]
+    }
+}
+
+#[derive(Debug, Default)]
+struct Choice {
+    conditions: Vec<String>,
+    defaults: Vec<(String, Option<String>)>,
+    members: Vec<String>,
+}
+
+enum Block {
+    If(String),
+    Menu(Vec<String>),
+    Choice(usize),
+}
+
+/// What the following `depends on`/`default` lines belong to.
+enum Current {
+    None,
+    Entry(usize),
+    Menu,
+    Choice(usize),
+}
+
+/// Kconfig tree of a Buildroot source directory. Only what is needed to
+/// fill in default values is kept: types, dependencies, defaults, selects
+/// and choices.
+#[derive(Debug, Default)]
+pub struct Kconfig {
+    entries: Vec<Entry>,
+    index: HashMap<String, usize>,
+    choices: Vec<Choice>,
+}
+
+/// Buildroot source directory of a defconfig: the closest parent with
+/// `Config.in` and `package/Config.in`, so `configs/foo_defconfig` of a
+/// Buildroot tree has one and a standalone defconfig doesn't.
+pub fn find_root(defconfig: &str) -> Option<PathBuf> {
+    Path::new(defconfig)
+        .ancestors()
+        .skip(1)
+        .map(|x| {
+            if x.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                x
+            }
+        })
+        .find(|x| external::is_buildroot_tree(x))
+        .map(|x| x.to_path_buf())
+}
+
+/// `default VALUE if COND`: the value and the optional condition.
+fn split_if(text: &str) -> (String, Option<String>) {
+    let mut quoted = false;
+    let bytes = text.as_bytes();
+    for idx in 0..bytes.len() {
+        match bytes[idx] {
+            b'"' => quoted = !quoted,
+            b' ' | b'\t' if !quoted && text[idx..].trim_start().starts_with("if ") => {
+                let cond = text[idx..].trim_start()[3..].trim();
+                return (text[..idx].trim().to_owned(), Some(cond.to_owned()));
+            }
+            _ => {}
+        }
+    }
+    (text.trim().to_owned(), None)
+}
+
+fn unquote(text: &str) -> &str {
+    let text = text.trim();
+    text.strip_prefix('"')
+        .and_then(|x| x.strip_suffix('"'))
+        .unwrap_or(text)
+}
+
+fn indent(line: &str) -> usize {
+    line.chars()
+        .take_while(|x| x.is_whitespace())
+        .map(|x| if x == '\t' { 8 } else { 1 })
+        .sum()
+}
+
+impl Kconfig {
+    /// Read `Config.in` of a Buildroot source directory with all the files it
+    /// sources. Sources with variables (br2-external trees and so on) are
+    /// skipped.
+    pub fn read(root: &Path) -> Result<Kconfig> {
+        let mut result = Kconfig::default();
+        let mut blocks = Vec::new();
+        result.read_file(root, Path::new("Config.in"), &mut blocks)?;
+        Ok(result)
+    }
+
+    fn conditions(&self, blocks: &[Block]) -> Vec<String> {
+        let mut result = Vec::new();
+        for block in blocks {
+            match block {
+                Block::If(cond) => result.push(cond.clone()),
+                Block::Menu(deps) => result.extend(deps.iter().cloned()),
+                Block::Choice(idx) => result.extend(self.choices[*idx].conditions.iter().cloned()),
+            }
+        }
+        result
+    }
+
+    fn read_file(&mut self, root: &Path, file: &Path, blocks: &mut Vec<Block>) -> Result<()> {
+        let path = root.join(file);
+        let name = path.to_string_lossy().into_owned();
+        let data = std::fs::read_to_string(&path).map_err(|err| Error::io(&name, err))?;
+
+        let mut current = Current::None;
+        let mut help: Option<usize> = None;
+        for (number, line) in data.lines().enumerate() {
+            if let Some(level) = help {
+                if line.trim().is_empty() || indent(line) > level {
+                    continue;
+                }
+                help = None;
+            }
+            let text = line.trim();
+            if text.is_empty() || text.starts_with('#') {
+                continue;
+            }
+            let (keyword, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+            let rest = rest.trim();
+
+            match keyword {
+                "config" | "menuconfig" => {
+                    let choice = blocks.iter().rev().find_map(|x| match x {
+                        Block::Choice(idx) => Some(*idx),
+                        _ => None,
+                    });
+                    if let Some(idx) = choice {
+                        self.choices[idx].members.push(rest.to_owned());
+                    }
+                    let definition = Definition {
+                        conditions: self.conditions(blocks),
+                        ..Default::default()
+                    };
+                    let idx = match self.index.get(rest) {
+                        Some(idx) => *idx,
+                        None => {
+                            self.entries.push(Entry {
+                                name: rest.to_owned(),
+                                kind: None,
+                                definitions: Vec::new(),
+                                choice: None,
+                            });
+                            self.index.insert(rest.to_owned(), self.entries.len() - 1);
+                            self.entries.len() - 1
+                        }
+                    };
+                    let entry = &mut self.entries[idx];
+                    entry.definitions.push(definition);
+                    entry.choice = entry.choice.or(choice);
+                    current = Current::Entry(idx);
+                }
+                "choice" => {
+                    self.choices.push(Choice {
+                        conditions: self.conditions(blocks),
+                        ..Default::default()
+                    });
+                    let idx = self.choices.len() - 1;
+                    blocks.push(Block::Choice(idx));
+                    current = Current::Choice(idx);
+                }
+                "menu" => {
+                    blocks.push(Block::Menu(Vec::new()));
+                    current = Current::Menu;
+                }
+                "if" => {
+                    blocks.push(Block::If(rest.to_owned()));
+                    current = Current::None;
+                }
+                "endif" | "endmenu" | "endchoice" => {
+                    if blocks.pop().is_none() {
+                        return Err(Error::parse(&name, Some(number + 1), "unbalanced block"));
+                    }
+                    current = Current::None;
+                }
+                "comment" | "mainmenu" => current = Current::None,
+                "source" => {
+                    current = Current::None;
+                    let source = unquote(rest);
+                    if source.contains('$') {
+                        debug!("{}: skip source {}", name, source);
+                        continue;
+                    }
+                    self.read_file(root, Path::new(source), blocks)?;
+                }
+                "help" | "---help---" => help = Some(indent(line)),
+                "bool" | "tristate" | "string" | "int" | "hex" | "def_bool" | "def_tristate" => {
+                    if let Current::Entry(idx) = current {
+                        let entry = &mut self.entries[idx];
+                        entry.kind = Some(match keyword {
+                            "string" | "int" | "hex" => Kind::Value,
+                            _ => Kind::Bool,
+                        });
+                        if keyword.starts_with("def_") {
+                            entry.definition().defaults.push(split_if(rest));
+                        }
+                    }
+                }
+                "depends" => {
+                    let cond = rest.strip_prefix("on").unwrap_or(rest).trim().to_owned();
+                    match current {
+                        Current::Entry(idx) => self.entries[idx].definition().conditions.push(cond),
+                        Current::Choice(idx) => self.choices[idx].conditions.push(cond),
+                        Current::Menu => {
+                            if let Some(Block::Menu(deps)) = blocks.last_mut() {
+                                deps.push(cond);
+                            }
+                        }
+                        Current::None => {}
+                    }
+                }
+                "default" => match current {
+                    Current::Entry(idx) => {
+                        self.entries[idx].definition().defaults.push(split_if(rest))
+                    }
+                    Current::Choice(idx) => self.choices[idx].defaults.push(split_if(rest)),
+                    _ => {}
+                },
+                "select" => {
+                    if let Current::Entry(idx) = current {
+                        self.entries[idx].definition().selects.push(split_if(rest));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Fill in the symbols a defconfig doesn't set with their default
+    /// values, the way `make olddefconfig` does for plain cases. Unset bool
+    /// symbols are `n`.
+    pub fn expand(&self, symbols: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+        let mut values = symbols.clone();
+        for pass in 0..MAX_PASSES {
+            let before = values.clone();
+            let selected: BTreeSet<String> = self
+                .entries
+                .iter()
+                .filter(|entry| is_true(values.get(&entry.name).map(|x| x.as_str())))
+                .flat_map(|entry| entry.active(&values).flat_map(|x| x.selects.iter()))
+                .filter(|(_, cond)| holds(cond, &values))
+                .map(|(name, _)| name.clone())
+                .collect();
+
+            for entry in &self.entries {
+                if symbols.contains_key(&entry.name) || entry.choice.is_some() {
+                    continue;
+                }
+                let value = if selected.contains(&entry.name) {
+                    Some("y".to_owned())
+                } else if entry.is_visible(&values) {
+                    default_value(entry, &values)
+                } else if entry.kind() == Kind::Bool {
+                    Some("n".to_owned())
+                } else {
+                    None
+                };
+                match value {
+                    Some(value) => values.insert(entry.name.clone(), value),
+                    None => values.remove(&entry.name),
+                };
+            }
+
+            for choice in &self.choices {
+                self.expand_choice(choice, symbols, &mut values);
+            }
+            if values == before {
+                return values;
+            }
+            debug!("kconfig defaults pass {} changed values", pass);
+        }
+        warn!("kconfig defaults don't settle after {} passes", MAX_PASSES);
+        values
+    }
+
+    fn expand_choice(
+        &self,
+        choice: &Choice,
+        symbols: &BTreeMap<String, String>,
+        values: &mut BTreeMap<String, String>,
+    ) {
+        let visible = choice.conditions.iter().all(|x| eval(x, values));
+        let member_visible = |name: &str, values: &BTreeMap<String, String>| {
+            self.index
+                .get(name)
+                .is_some_and(|idx| self.entries[*idx].is_visible(values))
+        };
+        let set = choice
+            .members
+            .iter()
+            .find(|x| symbols.get(*x).is_some_and(|value| is_true(Some(value))));
+        let chosen = match set {
+            Some(name) => Some(name.clone()),
+            None if visible => choice
+                .defaults
+                .iter()
+                .filter(|(_, cond)| holds(cond, values))
+                .map(|(name, _)| name)
+                .chain(choice.members.iter())
+                .find(|name| member_visible(name, values))
+                .cloned(),
+            None => None,
+        };
+        for member in &choice.members {
+            if symbols.contains_key(member) {
+                continue;
+            }
+            let value = if Some(member) == chosen.as_ref() {
+                "y"
+            } else {
+                "n"
+            };
+            values.insert(member.clone(), value.to_owned());
+        }
+    }
+}
+
+/// Condition of a `default`/`select` line, if any.
+fn holds(cond: &Option<String>, values: &BTreeMap<String, String>) -> bool {
+    cond.as_deref().is_none_or(|x| eval(x, values))
+}
+
+fn is_true(value: Option<&str>) -> bool {
+    matches!(value, Some("y") | Some("m"))
+}
+
+/// The first default of the visible definitions, in the tree order.
+fn default_value(entry: &Entry, values: &BTreeMap<String, String>) -> Option<String> {
+    let value = entry
+        .active(values)
+        .flat_map(|x| x.defaults.iter())
+        .find(|(_, cond)| holds(cond, values))
+        .map(|(value, _)| value.as_str());
+    match (entry.kind(), value) {
+        (Kind::Bool, Some(value)) => Some(if eval(value, values) { "y" } else { "n" }.to_owned()),
+        (Kind::Bool, None) => Some("n".to_owned()),
+        (Kind::Value, Some(value)) if value.contains('$') => None,
+        (Kind::Value, Some(value)) if value.starts_with('"') => Some(unquote(value).to_owned()),
+        (Kind::Value, Some(value)) => Some(term(value, values)),
+        (Kind::Value, None) => None,
+    }
+}
+
+/// Value of a symbol name or a literal (`y`, numbers, lowercase words).
+fn term(name: &str, values: &BTreeMap<String, String>) -> String {
+    match values.get(name) {
+        Some(value) => value.clone(),
+        None if name.chars().any(|x| x.is_ascii_uppercase()) => "n".to_owned(),
+        None => name.to_owned(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Op(&'static str),
+}
+
+fn tokenize(expr: &str) -> Vec<Token> {
+    const OPS: [&str; 11] = ["&&", "||", "!=", "<=", ">=", "!", "=", "<", ">", "(", ")"];
+    let mut result = Vec::new();
+    let mut rest = expr.trim_start();
+    while !rest.is_empty() {
+        if let Some(op) = OPS.iter().find(|x| rest.starts_with(**x)) {
+            result.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            result.push(Token::Str(quoted[..end].to_owned()));
+            rest = quoted.get(end + 1..).unwrap_or_default();
+        } else {
+            let end = rest
+                .find(|x: char| x.is_whitespace() || "&|!=<>()\"".contains(x))
+                .unwrap_or(rest.len());
+            result.push(Token::Word(rest[..end].to_owned()));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    result
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    values: &'a BTreeMap<String, String>,
+}
+
+impl Parser<'_> {
+    fn accept(&mut self, op: &str) -> bool {
+        if matches!(self.tokens.get(self.pos), Some(Token::Op(x)) if *x == op) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or(&mut self) -> bool {
+        let mut result = self.and();
+        while self.accept("||") {
+            result |= self.and();
+        }
+        result
+    }
+
+    fn and(&mut self) -> bool {
+        let mut result = self.not();
+        while self.accept("&&") {
+            result &= self.not();
+        }
+        result
+    }
+
+    fn not(&mut self) -> bool {
+        if self.accept("!") {
+            return !self.not();
+        }
+        if self.accept("(") {
+            let result = self.or();
+            self.accept(")");
+            return result;
+        }
+        self.compare()
+    }
+
+    fn value(&mut self) -> String {
+        let value = match self.tokens.get(self.pos) {
+            Some(Token::Word(word)) => term(word, self.values),
+            Some(Token::Str(text)) => text.clone(),
+            _ => return String::new(),
+        };
+        self.pos += 1;
+        value
+    }
+
+    fn compare(&mut self) -> bool {
+        let left = self.value();
+        let op = match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) if ["=", "!=", "<", ">", "<=", ">="].contains(op) => *op,
+            _ => return is_true(Some(&left)),
+        };
+        self.pos += 1;
+        let right = self.value();
+        let ordering = match (left.parse::<i64>(), right.parse::<i64>()) {
+            (Ok(a), Ok(b)) => a.cmp(&b),
+            _ => left.cmp(&right),
+        };
+        match op {
+            "=" => ordering.is_eq(),
+            "!=" => ordering.is_ne(),
+            "<" => ordering.is_lt(),
+            ">" => ordering.is_gt(),
+            "<=" => ordering.is_le(),
+            _ => ordering.is_ge(),
+        }
+    }
+}
+
+/// Evaluate a Kconfig expression; unknown symbols are `n`.
+fn eval(expr: &str, values: &BTreeMap<String, String>) -> bool {
+    Parser {
+        tokens: tokenize(expr),
+        pos: 0,
+        values,
+    }
+    .or()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutils::TempDir;
+
+    fn values(symbols: &[(&str, &str)]) -> BTreeMap<String, String> {
+        symbols
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn expressions() {
+        let symbols = values(&[("BR2_ARM", "y"), ("BR2_ARCH", "arm"), ("BR2_JLEVEL", "4")]);
+        assert!(eval("BR2_ARM", &symbols));
+        assert!(!eval("BR2_X86", &symbols));
+        assert!(eval("BR2_ARM && !BR2_X86", &symbols));
+        assert!(eval("BR2_X86 || (BR2_ARM && BR2_ARCH = \"arm\")", &symbols));
+        assert!(eval("BR2_ARCH != \"mips\"", &symbols));
+        assert!(eval("BR2_JLEVEL >= 2", &symbols));
+        assert!(!eval("n", &symbols));
+        assert_eq!(
+            split_if("\"a if b\" if BR2_ARM"),
+            ("\"a if b\"".to_owned(), Some("BR2_ARM".to_owned()))
+        );
+    }
+
+    #[test]
+    fn defaults() {
+        let root = TempDir::new("kconfig");
+        std::fs::create_dir_all(root.join("package/foo")).unwrap();
+        std::fs::create_dir_all(root.join("configs")).unwrap();
+        std::fs::write(
+            root.join("Config.in"),
+            "mainmenu \"Buildroot\"\n\
+             config BR2_HOSTNAME\n\
+             \tstring \"hostname\"\n\
+             \tdefault \"buildroot\"\n\
+             \thelp\n\
+             \t  default y\n\
+             \n\
+             choice\n\
+             \tprompt \"init\"\n\
+             \tdefault BR2_INIT_BUSYBOX\n\
+             config BR2_INIT_BUSYBOX\n\
+             \tbool \"busybox\"\n\
+             config BR2_INIT_SYSTEMD\n\
+             \tbool \"systemd\"\n\
+             endchoice\n\
+             source \"package/Config.in\"\n\
+             source \"$BR2_BASE_DIR/.br2-external.in\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("package/Config.in"),
+            "menu \"Packages\"\n\
+             source \"package/foo/Config.in\"\n\
+             source \"package/jpeg/Config.in\"\n\
+             endmenu\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("package/foo/Config.in"),
+            "config BR2_PACKAGE_FOO\n\
+             \tbool \"foo\"\n\
+             \tselect BR2_PACKAGE_ZLIB\n\
+             if BR2_PACKAGE_FOO\n\
+             config BR2_PACKAGE_FOO_TOOLS\n\
+             \tbool \"tools\"\n\
+             \tdefault y if BR2_INIT_SYSTEMD\n\
+             config BR2_PACKAGE_FOO_LEVEL\n\
+             \tint \"level\"\n\
+             \tdefault 3\n\
+             endif\n\
+             config BR2_PACKAGE_ZLIB\n\
+             \tbool \"zlib\"\n",
+        )
+        .unwrap();
+        // a symbol defined by every provider, with the type in another file
+        std::fs::create_dir_all(root.join("package/jpeg")).unwrap();
+        std::fs::write(
+            root.join("package/jpeg/Config.in"),
+            "config BR2_PACKAGE_LIBJPEG\n\
+             \tbool \"libjpeg\"\n\
+             if BR2_PACKAGE_LIBJPEG\n\
+             config BR2_PACKAGE_PROVIDES_JPEG\n\
+             \tdefault \"libjpeg\"\n\
+             endif\n\
+             config BR2_PACKAGE_JPEG_TURBO\n\
+             \tbool \"jpeg-turbo\"\n\
+             if BR2_PACKAGE_JPEG_TURBO\n\
+             config BR2_PACKAGE_PROVIDES_JPEG\n\
+             \tdefault \"jpeg-turbo\"\n\
+             endif\n\
+             config BR2_PACKAGE_PROVIDES_JPEG\n\
+             \tstring\n",
+        )
+        .unwrap();
+        let defconfig = root.join("configs/board_defconfig");
+        std::fs::write(&defconfig, "BR2_PACKAGE_FOO=y\n").unwrap();
+        assert_eq!(
+            find_root(&defconfig.to_string_lossy()).as_deref(),
+            Some(root.as_ref())
+        );
+        assert_eq!(find_root("/nonexistent/board_defconfig"), None);
+
+        let kconfig = Kconfig::read(&root).unwrap();
+        let expanded = kconfig.expand(&values(&[("BR2_PACKAGE_FOO", "y")]));
+        assert_eq!(
+            expanded,
+            values(&[
+                ("BR2_HOSTNAME", "buildroot"),
+                ("BR2_INIT_BUSYBOX", "y"),
+                ("BR2_INIT_SYSTEMD", "n"),
+                ("BR2_PACKAGE_FOO", "y"),
+                ("BR2_PACKAGE_FOO_LEVEL", "3"),
+                ("BR2_PACKAGE_FOO_TOOLS", "n"),
+                ("BR2_PACKAGE_JPEG_TURBO", "n"),
+                ("BR2_PACKAGE_LIBJPEG", "n"),
+                ("BR2_PACKAGE_ZLIB", "y"),
+            ])
+        );
+
+        let expanded = kconfig.expand(&values(&[
+            ("BR2_INIT_SYSTEMD", "y"),
+            ("BR2_HOSTNAME", "board"),
+        ]));
+        assert_eq!(expanded["BR2_HOSTNAME"], "board");
+        assert_eq!(expanded["BR2_INIT_BUSYBOX"], "n");
+        assert_eq!(expanded["BR2_PACKAGE_FOO"], "n");
+        assert_eq!(expanded["BR2_PACKAGE_FOO_TOOLS"], "n");
+        assert!(!expanded.contains_key("BR2_PACKAGE_FOO_LEVEL"));
+        assert!(!expanded.contains_key("BR2_PACKAGE_PROVIDES_JPEG"));
+
+        let expanded = kconfig.expand(&values(&[("BR2_PACKAGE_LIBJPEG", "y")]));
+        assert_eq!(expanded["BR2_PACKAGE_PROVIDES_JPEG"], "libjpeg");
+        assert_eq!(expanded["BR2_PACKAGE_JPEG_TURBO"], "n");
+    }
+}
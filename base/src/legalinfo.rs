@@ -0,0 +1,351 @@
+use crate::error::{Error, Result};
+use crate::package::{FetchMethod, Package, PackageReader, PackageSource, Packages};
+use log::{info, warn};
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+const MANIFEST: &str = "manifest.csv";
+const HOST_MANIFEST: &str = "host-manifest.csv";
+
+/// A legal-info directory (`make legal-info` output) or one of its manifests.
+pub fn is_legal_info(path: &str) -> bool {
+    let path = Path::new(path);
+    match path.file_name().and_then(|x| x.to_str()) {
+        Some(MANIFEST) | Some(HOST_MANIFEST) => true,
+        _ => path.join(MANIFEST).is_file(),
+    }
+}
+
+fn field<'a>(
+    record: &'a csv::StringRecord,
+    headers: &csv::StringRecord,
+    name: &str,
+) -> Option<&'a str> {
+    headers
+        .iter()
+        .position(|x| x.trim() == name)
+        .and_then(|idx| record.get(idx))
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty())
+}
+
+fn make_source(site: Option<&str>, archive: Option<&str>) -> Option<PackageSource> {
+    let site = site?;
+    let method = FetchMethod::guess(site)?;
+    let url = match archive {
+        Some(archive) if !method.is_vcs() => format!("{}/{}", site.trim_end_matches('/'), archive),
+        _ => site.to_owned(),
+    };
+    Some(PackageSource::new(method, &url))
+}
+
+/// Read one manifest. Host packages get a `host-` prefix, if they don't have
+/// it already, so they don't clash with target ones.
+fn read_manifest(path: &Path, host: bool) -> Result<Packages> {
+    let file = path.to_string_lossy();
+    let csv_error = |err: csv::Error| {
+        let line = err.position().map(|x| x.line() as usize);
+        Error::parse(&file, line, &err.to_string())
+    };
+
+    let input = std::fs::File::open(path).map_err(|err| Error::io(&file, err))?;
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(input);
+    let headers = reader.headers().map_err(csv_error)?.clone();
+    if !headers.iter().any(|x| x.trim() == "PACKAGE") {
+        return Err(Error::parse(&file, Some(1), "PACKAGE column not found"));
+    }
+
+    let mut result = Packages::new();
+    for record in reader.records() {
+        let record = record.map_err(csv_error)?;
+        let name = match field(&record, &headers, "PACKAGE") {
+            Some(name) if host && !name.starts_with("host-") => format!("host-{}", name),
+            Some(name) => name.to_owned(),
+            None => {
+                warn!("{}: skip record without package name", file);
+                continue;
+            }
+        };
+
+        let source = make_source(
+            field(&record, &headers, "SOURCE SITE"),
+            field(&record, &headers, "SOURCE ARCHIVE"),
+        );
+        let package = Package {
+            name: name.clone(),
+            version: field(&record, &headers, "VERSION").map(|x| x.to_owned()),
+            sources: source.into_iter().collect(),
+            location: Some(file.to_string()),
+            license: field(&record, &headers, "LICENSE").map(|x| x.to_owned()),
+            license_files: field(&record, &headers, "LICENSE FILES")
+                .map(|x| x.split_whitespace().map(|x| x.to_owned()).collect())
+                .unwrap_or_default(),
+            ..Default::default()
+        };
+        result.insert(name, package);
+    }
+    Ok(result)
+}
+
+/// Reads `manifest.csv` and `host-manifest.csv` of a legal-info directory,
+/// or a single manifest file.
+pub struct ManifestReader {
+    path: String,
+}
+
+impl ManifestReader {
+    pub fn new(path: &str) -> ManifestReader {
+        ManifestReader {
+            path: path.to_owned(),
+        }
+    }
+
+    fn manifests(&self) -> Vec<(PathBuf, bool)> {
+        let path = Path::new(&self.path);
+        if path.is_file() {
+            let host = path.file_name().is_some_and(|x| x == HOST_MANIFEST);
+            return vec![(path.to_path_buf(), host)];
+        }
+
+        let mut result = vec![(path.join(MANIFEST), false)];
+        let host = path.join(HOST_MANIFEST);
+        if host.is_file() {
+            result.push((host, true));
+        }
+        result
+    }
+}
+
+impl PackageReader for ManifestReader {
+    type Error = Error;
+    fn read(&mut self) -> Result<Packages> {
+        let mut result = Packages::new();
+        for (manifest, host) in self.manifests() {
+            info!("process {}", manifest.display());
+            result.extend(read_manifest(&manifest, host)?);
+        }
+        Ok(result)
+    }
+}
+
+/// Drop comments in parentheses from a Buildroot license string.
+pub(crate) fn strip_comments(license: &str) -> String {
+    let mut depth: usize = 0;
+    let mut result = String::new();
+    for symb in license.chars() {
+        match symb {
+            '(' => depth += 1,
+            // an unmatched `)` must not hide the rest of the license
+            ')' => depth = depth.saturating_sub(1),
+            _ if depth == 0 => result.push(symb),
+            _ => {}
+        }
+    }
+    result
+}
+
+/// Licenses of a Buildroot license string, split on `,`, `;` and the `or`
+/// and `and` operators, with comments in parentheses dropped:
+/// `GPL-2.0+ (programs), LGPL-2.1+ or BSD-3-Clause` gives `GPL-2.0+`,
+/// `LGPL-2.1+` and `BSD-3-Clause`. Multi-word names like `Public Domain` or
+/// `GPL-2.0 with linking exception` are kept whole.
+pub fn license_ids(license: &str) -> BTreeSet<String> {
+    let current = strip_comments(license);
+    let mut result = BTreeSet::new();
+    for part in current.split([',', ';']) {
+        let mut words: Vec<&str> = Vec::new();
+        for word in part.split_whitespace() {
+            match word {
+                "or" | "OR" | "and" | "AND" => {
+                    if !words.is_empty() {
+                        result.insert(words.join(" "));
+                    }
+                    words.clear();
+                }
+                word => words.push(word),
+            }
+        }
+        if !words.is_empty() {
+            result.insert(words.join(" "));
+        }
+    }
+    result
+}
+
+/// License-focused comparison of two package sets.
+#[derive(Debug, Default)]
+pub struct LicenseReport {
+    /// License identifiers used in the second set only.
+    pub new_licenses: BTreeSet<String>,
+    /// Packages (present in both sets) with a different license: name, old
+    /// and new license.
+    pub changed: Vec<(String, String, String)>,
+    /// Packages of the second set with a license, but without license files.
+    pub missing_files: Vec<String>,
+}
+
+impl LicenseReport {
+    pub fn build(first: &Packages, second: &Packages) -> LicenseReport {
+        let ids = |packages: &Packages| -> BTreeSet<String> {
+            packages
+                .values()
+                .filter_map(|x| x.license.as_deref())
+                .flat_map(license_ids)
+                .collect()
+        };
+        let known = ids(first);
+        let new_licenses = ids(second).difference(&known).cloned().collect();
+
+        let mut names: Vec<&String> = second.keys().collect();
+        names.sort();
+
+        let mut changed = Vec::new();
+        let mut missing_files = Vec::new();
+        for name in names {
+            let package = &second[name];
+            if let Some(previous) = first.get(name) {
+                let old = previous.license.clone().unwrap_or_default();
+                let new = package.license.clone().unwrap_or_default();
+                if old != new {
+                    changed.push((name.clone(), old, new));
+                }
+            }
+
+            let has_license = package.license.as_deref().is_some_and(|x| !x.is_empty());
+            if has_license && package.license_files.is_empty() {
+                missing_files.push(name.clone());
+            }
+        }
+
+        LicenseReport {
+            new_licenses,
+            changed,
+            missing_files,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.new_licenses.is_empty() && self.changed.is_empty() && self.missing_files.is_empty()
+    }
+}
+
+impl Display for LicenseReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.new_licenses.is_empty() {
+            writeln!(f, "[+] new licenses")?;
+            for license in &self.new_licenses {
+                writeln!(f, "      {}", license)?;
+            }
+        }
+        if !self.changed.is_empty() {
+            writeln!(f, "[*] changed licenses")?;
+            for (name, old, new) in &self.changed {
+                writeln!(f, "      {}: {} -> {}", name, old, new)?;
+            }
+        }
+        if !self.missing_files.is_empty() {
+            writeln!(f, "[!] no license files")?;
+            for name in &self.missing_files {
+                writeln!(f, "      {}", name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutils::TempDir;
+
+    const MANIFEST_V1: &str = r#""PACKAGE","VERSION","LICENSE","LICENSE FILES","SOURCE ARCHIVE","SOURCE SITE","DEPENDENCIES WITH LICENSES"
+"busybox","1.36.1","GPL-2.0, bzip2-1.0.4","LICENSE archival/libarchive/bz/LICENSE","busybox-1.36.1.tar.bz2","https://www.busybox.net/downloads",""
+"zlib","1.2.13","Zlib","README","zlib-1.2.13.tar.xz","https://www.zlib.net",""
+"app","1a2b3c","PROPRIETARY","","app-1a2b3c-git4.tar.gz","https://git.example.com/app.git",""
+"#;
+
+    const MANIFEST_V2: &str = r#""PACKAGE","VERSION","LICENSE","LICENSE FILES","SOURCE ARCHIVE","SOURCE SITE","DEPENDENCIES WITH LICENSES"
+"busybox","1.36.1","GPL-2.0, bzip2-1.0.4","LICENSE archival/libarchive/bz/LICENSE","busybox-1.36.1.tar.bz2","https://www.busybox.net/downloads",""
+"zlib","1.3","Zlib or MIT","README","zlib-1.3.tar.xz","https://www.zlib.net",""
+"jq","1.7","MIT, CC-BY-3.0 (documentation)","COPYING","jq-1.7.tar.gz","https://github.com/jqlang/jq/releases/download/jq-1.7",""
+"#;
+
+    fn read(name: &str, data: &str) -> Packages {
+        let dir = TempDir::new(&format!("legal-info-{}", name));
+        std::fs::write(dir.join(MANIFEST), data).unwrap();
+        std::fs::write(
+            dir.join(HOST_MANIFEST),
+            "\"PACKAGE\",\"VERSION\",\"LICENSE\"\n\"host-gcc\",\"12.3.0\",\"GPL-3.0+\"\n\"make\",\"4.4\",\"GPL-3.0+\"\n",
+        )
+        .unwrap();
+        assert!(is_legal_info(dir.to_str().unwrap()));
+        let packages = ManifestReader::new(dir.to_str().unwrap()).read().unwrap();
+        packages
+    }
+
+    #[test]
+    fn manifest() {
+        let packages = read("v1", MANIFEST_V1);
+        assert_eq!(packages.len(), 5);
+
+        let busybox = &packages["busybox"];
+        assert_eq!(busybox.version.as_deref(), Some("1.36.1"));
+        assert_eq!(busybox.license.as_deref(), Some("GPL-2.0, bzip2-1.0.4"));
+        assert_eq!(busybox.license_files.len(), 2);
+        assert_eq!(
+            busybox.get_source(FetchMethod::Https).unwrap().url,
+            "https://www.busybox.net/downloads/busybox-1.36.1.tar.bz2"
+        );
+        assert_eq!(
+            packages["app"].get_git_source().as_deref(),
+            Some("https://git.example.com/app.git")
+        );
+        assert!(packages.contains_key("host-gcc"));
+        assert!(packages.contains_key("host-make"));
+    }
+
+    #[test]
+    fn ids() {
+        let ids: Vec<String> = license_ids("GPL-2.0+ (programs), LGPL-2.1+ or BSD-3-Clause")
+            .into_iter()
+            .collect();
+        assert_eq!(ids, vec!["BSD-3-Clause", "GPL-2.0+", "LGPL-2.1+"]);
+        assert!(license_ids("").is_empty());
+
+        let ids: Vec<String> = license_ids("MIT), GPL-2.0 (tools)").into_iter().collect();
+        assert_eq!(ids, ["GPL-2.0", "MIT"]);
+
+        let ids: Vec<String> =
+            license_ids("Public Domain; GPL-2.0 with linking exception and  MIT ,")
+                .into_iter()
+                .collect();
+        assert_eq!(
+            ids,
+            ["GPL-2.0 with linking exception", "MIT", "Public Domain"]
+        );
+    }
+
+    #[test]
+    fn report() {
+        let first = read("first", MANIFEST_V1);
+        let second = read("second", MANIFEST_V2);
+        let report = LicenseReport::build(&first, &second);
+        assert_eq!(
+            report.new_licenses.iter().collect::<Vec<&String>>(),
+            vec!["CC-BY-3.0", "MIT"]
+        );
+        assert_eq!(
+            report.changed,
+            vec![(
+                "zlib".to_owned(),
+                "Zlib".to_owned(),
+                "Zlib or MIT".to_owned()
+            )]
+        );
+        assert_eq!(report.missing_files, vec!["host-gcc", "host-make"]);
+        assert!(report
+            .to_string()
+            .starts_with("[+] new licenses\n      CC-BY-3.0\n      MIT\n"));
+    }
+}
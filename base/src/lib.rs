@@ -1,7 +1,31 @@
+pub mod buildtime;
+pub mod configdiff;
+pub mod cve;
+pub mod defconfig;
 pub mod diffs;
+pub mod dldir;
+pub mod error;
+pub mod external;
+pub mod filelist;
 pub mod githistory;
+pub mod gittree;
 pub mod gitworkspace;
+pub mod graph;
+mod json;
+pub mod kconfig;
+pub mod legalinfo;
+pub mod lint;
 pub mod mkfile;
 pub mod package;
+pub mod patches;
+pub mod pattern;
+pub mod reader;
 pub mod report;
+pub mod sbom;
+pub mod showinfo;
+pub mod tags;
+#[cfg(any(test, feature = "testutils"))]
+pub mod testutils;
 pub mod utils;
+
+pub use error::{Error, Result};
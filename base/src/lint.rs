@@ -0,0 +1,461 @@
+use crate::error::{Error, Result};
+use crate::external::is_package_mk;
+use crate::mkfile::{try_read_cmdline, try_read_key_value, var_prefix};
+use crate::package::FetchMethod;
+use log::info;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+use walkdir::WalkDir;
+
+/// Lint rules. Ids are stable: they are used to disable rules on the command
+/// line and in mk files (`# brlint: disable=<id>[,<id>]`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Rule {
+    /// No `<PKG>_VERSION`.
+    MissingVersion,
+    /// The variable prefix doesn't match the file name.
+    PrefixMismatch,
+    /// Git URL in `<PKG>_SITE` without `<PKG>_SITE_METHOD = git`.
+    GitSiteMethod,
+    /// No `<name>.hash` next to the mk file.
+    MissingHash,
+    /// No `<PKG>_LICENSE`.
+    MissingLicense,
+    TrailingWhitespace,
+    /// Header variables out of the coding style order.
+    VariableOrder,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 7] = [
+        Rule::MissingVersion,
+        Rule::PrefixMismatch,
+        Rule::GitSiteMethod,
+        Rule::MissingHash,
+        Rule::MissingLicense,
+        Rule::TrailingWhitespace,
+        Rule::VariableOrder,
+    ];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Rule::MissingVersion => "missing-version",
+            Rule::PrefixMismatch => "prefix-mismatch",
+            Rule::GitSiteMethod => "git-site-method",
+            Rule::MissingHash => "missing-hash",
+            Rule::MissingLicense => "missing-license",
+            Rule::TrailingWhitespace => "trailing-whitespace",
+            Rule::VariableOrder => "variable-order",
+        }
+    }
+
+    /// Rules `--fix` can correct.
+    pub fn is_fixable(&self) -> bool {
+        matches!(self, Rule::GitSiteMethod | Rule::TrailingWhitespace)
+    }
+}
+
+impl FromStr for Rule {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        Rule::ALL
+            .into_iter()
+            .find(|x| x.id() == s.trim())
+            .ok_or_else(|| Error::parse(s, None, "unknown lint rule"))
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.id())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub rule: Rule,
+    pub file: String,
+    /// 1-based, `None` for problems of the whole file.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: ", self.file, line)?,
+            None => write!(f, "{}: ", self.file)?,
+        }
+        write!(f, "[{}] {}", self.rule, self.message)
+    }
+}
+
+/// Header variables in the order of Buildroot's coding style.
+const ORDER: [&str; 6] = [
+    "VERSION",
+    "SOURCE",
+    "SITE",
+    "SITE_METHOD",
+    "LICENSE",
+    "LICENSE_FILES",
+];
+
+const DISABLE_COMMENT: &str = "# brlint: disable=";
+
+/// A variable assignment outside of `define` blocks and recipes.
+struct Assignment<'a> {
+    line: usize,
+    key: &'a str,
+    value: &'a str,
+    /// Nesting level of conditionals.
+    depth: usize,
+}
+
+fn assignments(content: &str) -> Vec<Assignment<'_>> {
+    let mut result = Vec::new();
+    let mut depth = 0;
+    let mut define = false;
+    for (idx, line) in content.lines().enumerate() {
+        let cmdline = match try_read_cmdline(line) {
+            Some(cmdline) => cmdline,
+            None => continue,
+        };
+        let word = cmdline.split_whitespace().next().unwrap_or_default();
+        match word {
+            "define" => define = true,
+            "endef" => define = false,
+            "ifeq" | "ifneq" | "ifdef" | "ifndef" => depth += 1,
+            "endif" => depth = usize::saturating_sub(depth, 1),
+            _ if define || line.starts_with('\t') => {}
+            _ => {
+                if let Some((key, value)) = try_read_key_value(cmdline) {
+                    let key = key.trim_end_matches(['+', ':', '?', ' ']);
+                    result.push(Assignment {
+                        line: idx + 1,
+                        key,
+                        value,
+                        depth,
+                    });
+                }
+            }
+        }
+    }
+    result
+}
+
+fn is_virtual(content: &str) -> bool {
+    content.contains("virtual-package)")
+}
+
+/// Rules disabled in the file itself.
+fn disabled_in(content: &str) -> HashSet<Rule> {
+    content
+        .lines()
+        .filter_map(|x| x.trim().strip_prefix(DISABLE_COMMENT))
+        .flat_map(|x| x.split(','))
+        .filter_map(|x| Rule::from_str(x).ok())
+        .collect()
+}
+
+/// Site lines to be followed by `<PKG>_SITE_METHOD = git`.
+fn git_sites(pkg: &str, vars: &[Assignment]) -> Vec<usize> {
+    let site_method = format!("{}_SITE_METHOD", pkg);
+    if vars.iter().any(|x| x.key == site_method) {
+        return Vec::new();
+    }
+    let site = format!("{}_SITE", pkg);
+    vars.iter()
+        .filter(|x| x.key == site)
+        .filter(|x| FetchMethod::guess(x.value) == Some(FetchMethod::Git))
+        .filter(|x| !x.value.starts_with("git://"))
+        .map(|x| x.line)
+        .collect()
+}
+
+/// Checks mk files of packages.
+#[derive(Debug, Default)]
+pub struct Linter {
+    disabled: HashSet<Rule>,
+}
+
+impl Linter {
+    pub fn new() -> Linter {
+        Linter::default()
+    }
+
+    pub fn disable(mut self, rule: Rule) -> Self {
+        self.disabled.insert(rule);
+        self
+    }
+
+    /// Check mk file content; the path gives the package name and the place
+    /// of the hash file.
+    pub fn check_content(&self, path: &str, content: &str) -> Vec<Diagnostic> {
+        let name = Path::new(path)
+            .file_stem()
+            .and_then(|x| x.to_str())
+            .unwrap_or_default();
+        let pkg = var_prefix(name);
+        let vars = assignments(content);
+        let virtual_pkg = is_virtual(content);
+        let has = |suffix: &str| {
+            let key = format!("{}_{}", pkg, suffix);
+            vars.iter().any(|x| x.key == key)
+        };
+
+        let mut result = Vec::new();
+        let mut report = |rule: Rule, line: Option<usize>, message: String| {
+            result.push(Diagnostic {
+                rule,
+                file: path.to_owned(),
+                line,
+                message,
+            })
+        };
+
+        if !virtual_pkg && !has("VERSION") {
+            report(
+                Rule::MissingVersion,
+                None,
+                format!("{}_VERSION is not set", pkg),
+            );
+        }
+
+        let first_version = vars
+            .iter()
+            .find(|x| x.key.ends_with("_VERSION") && !x.key.starts_with("HOST_"));
+        if let Some(var) = first_version {
+            if !var.key.starts_with(&format!("{}_", pkg)) {
+                report(
+                    Rule::PrefixMismatch,
+                    Some(var.line),
+                    format!(
+                        "{} doesn't match the file name, expected {}_VERSION",
+                        var.key, pkg
+                    ),
+                );
+            }
+        }
+
+        for line in git_sites(&pkg, &vars) {
+            report(
+                Rule::GitSiteMethod,
+                Some(line),
+                format!("git site without {}_SITE_METHOD = git", pkg),
+            );
+        }
+
+        let local = vars
+            .iter()
+            .any(|x| x.key == format!("{}_SITE_METHOD", pkg) && x.value == "local");
+        let hash = Path::new(path).with_extension("hash");
+        if !virtual_pkg && !local && has("VERSION") && !hash.is_file() {
+            report(
+                Rule::MissingHash,
+                None,
+                format!("{} not found", hash.display()),
+            );
+        }
+
+        if !virtual_pkg && !has("LICENSE") {
+            report(
+                Rule::MissingLicense,
+                None,
+                format!("{}_LICENSE is not set", pkg),
+            );
+        }
+
+        for (idx, line) in content.lines().enumerate() {
+            if line.ends_with([' ', '\t']) {
+                report(
+                    Rule::TrailingWhitespace,
+                    Some(idx + 1),
+                    "trailing whitespace".to_owned(),
+                );
+            }
+        }
+
+        let mut latest: Option<(usize, &str)> = None;
+        let mut seen = HashSet::new();
+        for var in vars.iter().filter(|x| x.depth == 0) {
+            let suffix = match var.key.strip_prefix(&format!("{}_", pkg)) {
+                Some(suffix) => suffix,
+                None => continue,
+            };
+            let pos = match ORDER.iter().position(|x| *x == suffix) {
+                Some(pos) if seen.insert(pos) => pos,
+                _ => continue,
+            };
+            match latest {
+                Some((max, key)) if pos < max => report(
+                    Rule::VariableOrder,
+                    Some(var.line),
+                    format!("{} should come before {}", var.key, key),
+                ),
+                _ => latest = Some((pos, var.key)),
+            }
+        }
+
+        let disabled = disabled_in(content);
+        result.retain(|x| !self.disabled.contains(&x.rule) && !disabled.contains(&x.rule));
+        result
+    }
+
+    /// Content with fixable problems corrected.
+    pub fn fix_content(&self, path: &str, content: &str) -> String {
+        let diagnostics = self.check_content(path, content);
+        let lines_of = |rule: Rule| -> HashSet<usize> {
+            diagnostics
+                .iter()
+                .filter(|x| x.rule == rule)
+                .filter_map(|x| x.line)
+                .collect()
+        };
+        let trailing = lines_of(Rule::TrailingWhitespace);
+        let sites = lines_of(Rule::GitSiteMethod);
+        let name = Path::new(path)
+            .file_stem()
+            .and_then(|x| x.to_str())
+            .unwrap_or_default();
+
+        let mut result = String::new();
+        for (idx, line) in content.lines().enumerate() {
+            let line = if trailing.contains(&(idx + 1)) {
+                line.trim_end()
+            } else {
+                line
+            };
+            result.push_str(line);
+            result.push('\n');
+            if sites.contains(&(idx + 1)) {
+                result.push_str(&format!("{}_SITE_METHOD = git\n", var_prefix(name)));
+            }
+        }
+        if !content.ends_with('\n') {
+            result.pop();
+        }
+        result
+    }
+
+    /// Check a mk file or package mk files of a directory. With `fix`,
+    /// fixable problems are corrected in place and only the remaining ones
+    /// are returned.
+    pub fn run(&self, path: &str, fix: bool) -> Result<Vec<Diagnostic>> {
+        let files: Vec<String> = if Path::new(path).is_dir() {
+            WalkDir::new(path)
+                .sort_by_file_name()
+                .into_iter()
+                .flatten()
+                .filter(|e| is_package_mk(e.path()))
+                .filter_map(|e| e.path().to_str().map(|x| x.to_owned()))
+                .collect()
+        } else {
+            vec![path.to_owned()]
+        };
+
+        let mut result = Vec::new();
+        for file in files {
+            let mut content =
+                std::fs::read_to_string(&file).map_err(|err| Error::io(&file, err))?;
+            if fix {
+                let fixed = self.fix_content(&file, &content);
+                if fixed != content {
+                    info!("fix {}", file);
+                    std::fs::write(&file, &fixed).map_err(|err| Error::io(&file, err))?;
+                    content = fixed;
+                }
+            }
+            result.extend(self.check_content(&file, &content));
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const FOO_MK: &str =
+        "################################################################################
+#
+# foo
+#
+################################################################################
+
+FOO_SITE = https://github.com/example/foo.git \n\
+FOO_VERSION = 1.0
+FOO_LICENSE = MIT
+
+define FOO_BUILD_CMDS
+\t$(MAKE) PREFIX=/usr -C $(@D)
+endef
+
+$(eval $(generic-package))
+";
+
+    fn rules(diagnostics: &[Diagnostic]) -> Vec<Rule> {
+        diagnostics.iter().map(|x| x.rule).collect()
+    }
+
+    #[test]
+    fn check() {
+        let diagnostics = Linter::new().check_content("/nonexistent/foo/foo.mk", FOO_MK);
+        assert_eq!(
+            rules(&diagnostics),
+            vec![
+                Rule::GitSiteMethod,
+                Rule::MissingHash,
+                Rule::TrailingWhitespace,
+                Rule::VariableOrder
+            ]
+        );
+        assert_eq!(
+            diagnostics[3].to_string(),
+            "/nonexistent/foo/foo.mk:8: [variable-order] FOO_VERSION should come before FOO_SITE"
+        );
+
+        let diagnostics = Linter::new().check_content("/nonexistent/bar/bar.mk", FOO_MK);
+        assert_eq!(
+            rules(&diagnostics),
+            vec![
+                Rule::MissingVersion,
+                Rule::PrefixMismatch,
+                Rule::MissingLicense,
+                Rule::TrailingWhitespace
+            ]
+        );
+    }
+
+    #[test]
+    fn disable() {
+        let linter = Linter::new()
+            .disable(Rule::MissingHash)
+            .disable(Rule::VariableOrder);
+        let content = format!("{}# brlint: disable=trailing-whitespace\n", FOO_MK);
+        let diagnostics = linter.check_content("/nonexistent/foo/foo.mk", &content);
+        assert_eq!(rules(&diagnostics), vec![Rule::GitSiteMethod]);
+        assert!(Rule::from_str("no-such-rule").is_err());
+    }
+
+    #[test]
+    fn fix() {
+        let linter = Linter::new().disable(Rule::MissingHash);
+        let fixed = linter.fix_content("/nonexistent/foo/foo.mk", FOO_MK);
+        assert!(fixed.contains(
+            "FOO_SITE = https://github.com/example/foo.git\nFOO_SITE_METHOD = git\nFOO_VERSION = 1.0\n"
+        ));
+        let diagnostics = linter.check_content("/nonexistent/foo/foo.mk", &fixed);
+        assert_eq!(rules(&diagnostics), vec![Rule::VariableOrder]);
+    }
+
+    #[test]
+    fn virtual_package() {
+        let diagnostics = Linter::new().check_content(
+            "/nonexistent/jpeg/jpeg.mk",
+            "JPEG_PROVIDES = jpeg\n$(eval $(virtual-package))\n",
+        );
+        assert!(diagnostics.is_empty());
+    }
+}
@@ -1,10 +1,19 @@
-use crate::package::{Package, PackageReader, PackageSource, Packages};
+use crate::error::{Error, Result};
+use crate::package::{FetchMethod, Package, PackageReader, PackageSource, Packages};
 use log::{info, warn};
+use std::fmt::Display;
 use std::io::{BufRead, BufReader};
-use std::io::{Error, ErrorKind};
+use std::str::FromStr;
 
 use walkdir::WalkDir;
 
+const CPE_SUFFIXES: [&str; 4] = [
+    "_CPE_ID_VENDOR",
+    "_CPE_ID_PRODUCT",
+    "_CPE_ID_VERSION",
+    "_CPE_ID_VALID",
+];
+
 pub struct MkFile {
     path: String,
 }
@@ -28,17 +37,90 @@ fn try_read_pkgver<'a>((key, value): (&'a str, &'a str)) -> Option<&'a str> {
     key.trim().strip_suffix("_VERSION").map(|_| value)
 }
 
-fn try_read_git_source<'a>((key, value): (&'a str, &'a str)) -> Option<&'a str> {
-    let key = key.trim();
+fn try_read_suffixed<'a>((key, value): (&'a str, &'a str), suffix: &str) -> Option<&'a str> {
     let value = value.trim();
-    if key.ends_with("_SITE") && value.ends_with(".git") {
+    if key.trim().ends_with(suffix) && !value.is_empty() {
         Some(value)
     } else {
         None
     }
 }
 
-fn try_read_cmdline(line: &str) -> Option<&str> {
+fn try_read_site<'a>(kv: (&'a str, &'a str)) -> Option<&'a str> {
+    try_read_suffixed(kv, "_SITE")
+}
+
+fn try_read_site_method<'a>(kv: (&'a str, &'a str)) -> Option<&'a str> {
+    try_read_suffixed(kv, "_SITE_METHOD")
+}
+
+fn try_read_dl_subdir<'a>(kv: (&'a str, &'a str)) -> Option<&'a str> {
+    try_read_suffixed(kv, "_DL_SUBDIR")
+}
+
+fn try_read_license<'a>(kv: (&'a str, &'a str)) -> Option<&'a str> {
+    try_read_suffixed(kv, "_LICENSE")
+}
+
+fn try_read_license_files<'a>(kv: (&'a str, &'a str)) -> Option<&'a str> {
+    try_read_suffixed(kv, "_LICENSE_FILES")
+}
+
+/// `<PKG>_IGNORE_CVES` is usually appended to, one CVE per line, so the key
+/// may end with `+`.
+fn try_read_ignore_cves<'a>((key, value): (&'a str, &'a str)) -> Option<&'a str> {
+    try_read_suffixed((key.trim_end_matches(['+', ' ']), value), "_IGNORE_CVES")
+}
+
+/// Target dependencies of the package, conditional ones included since
+/// Kconfig symbols are not evaluated. Host packages have their own
+/// `HOST_<PKG>_DEPENDENCIES`, which are skipped, as well as make expressions.
+fn try_read_dependencies<'a>((key, value): (&'a str, &'a str), prefix: &str) -> Vec<&'a str> {
+    if key.trim_end_matches(['+', ':', '?', ' ']) != format!("{}_DEPENDENCIES", prefix) {
+        return Vec::new();
+    }
+    value
+        .split_whitespace()
+        .filter(|x| !x.contains('$') && !x.contains(')'))
+        .collect()
+}
+
+/// CPE id the way Buildroot builds it from `<PKG>_CPE_ID_*` variables: set if
+/// any of them is (`_CPE_ID_VALID = YES` included), with the package name and
+/// version as defaults.
+fn make_cpe_id(name: &str, version: Option<&str>, cpe: &[Option<String>; 4]) -> Option<String> {
+    if cpe.iter().all(|x| x.is_none()) {
+        return None;
+    }
+    let [vendor, product, cpe_version, _] = cpe;
+    Some(format!(
+        "cpe:2.3:a:{}:{}:{}:*:*:*:*:*:*:*",
+        vendor
+            .clone()
+            .unwrap_or_else(|| format!("{}_project", name)),
+        product.as_deref().unwrap_or(name),
+        cpe_version.as_deref().or(version).unwrap_or("*")
+    ))
+}
+
+fn make_source(site: &str, method: Option<&str>, subdir: Option<&str>) -> Option<PackageSource> {
+    let method = match method {
+        Some(method) => FetchMethod::from_str(method)
+            .map_err(|err| warn!("{} for {}", err, site))
+            .ok()?,
+        None => FetchMethod::guess(site)?,
+    };
+    let mut source = PackageSource::new(method, site);
+    source.subdir = subdir.map(|x| x.to_owned());
+    Some(source)
+}
+
+/// Variable prefix of a package: `python-foo` is `PYTHON_FOO`.
+pub(crate) fn var_prefix(name: &str) -> String {
+    name.to_uppercase().replace(['-', '.'], "_")
+}
+
+pub(crate) fn try_read_cmdline(line: &str) -> Option<&str> {
     let from = line
         .chars()
         .position(|symb| !symb.is_whitespace())
@@ -55,7 +137,7 @@ fn try_read_cmdline(line: &str) -> Option<&str> {
     }
 }
 
-fn try_read_key_value(line: &str) -> Option<(&str, &str)> {
+pub(crate) fn try_read_key_value(line: &str) -> Option<(&str, &str)> {
     if let Some(key_idx) = line.chars().position(|symb| symb == '=') {
         if key_idx + 1 < line.len() {
             let key = (line[..key_idx]).trim();
@@ -73,16 +155,29 @@ impl MkFile {
         }
     }
 
-    pub fn read_info(&self) -> Result<Package, Error> {
-        let file = std::fs::File::open(&self.path)?;
-        let buf = BufReader::new(file);
+    pub fn read_info(&self) -> Result<Package> {
+        let file = std::fs::File::open(&self.path).map_err(|err| Error::io(&self.path, err))?;
+        self.parse(BufReader::new(file))
+    }
+
+    /// Parse mk file content; the path is only used for the package name and
+    /// location.
+    pub fn parse<R: BufRead>(&self, buf: R) -> Result<Package> {
         let name_file = try_extract_pkgname(&self.path)
-            .ok_or_else(|| Error::new(ErrorKind::Other, "invalid name"))?;
+            .ok_or_else(|| Error::parse(&self.path, None, "invalid name"))?;
         let mut name_mk = None;
         let mut ver_mk = None;
-        let mut source_mk = None;
-
-        for line in buf.lines().flatten() {
+        let mut site_mk = None;
+        let mut method_mk = None;
+        let mut subdir_mk = None;
+        let mut license_mk = None;
+        let mut license_files_mk = None;
+        let mut cpe_mk: [Option<String>; 4] = Default::default();
+        let mut ignore_cves_mk = Vec::new();
+        let mut dependencies_mk: Vec<String> = Vec::new();
+        let prefix = var_prefix(name_file);
+
+        for line in buf.lines().map_while(|line| line.ok()) {
             if let Some((k, v)) = try_read_cmdline(&line).and_then(try_read_key_value) {
                 if name_mk.is_none() {
                     name_mk = try_read_pkgname(k);
@@ -92,8 +187,40 @@ impl MkFile {
                     ver_mk = try_read_pkgver((k, v)).map(|x| x.to_owned());
                 }
 
-                if source_mk.is_none() {
-                    source_mk = try_read_git_source((k, v)).map(|x| x.to_owned());
+                if site_mk.is_none() {
+                    site_mk = try_read_site((k, v)).map(|x| x.to_owned());
+                }
+
+                if method_mk.is_none() {
+                    method_mk = try_read_site_method((k, v)).map(|x| x.to_owned());
+                }
+
+                if subdir_mk.is_none() {
+                    subdir_mk = try_read_dl_subdir((k, v)).map(|x| x.to_owned());
+                }
+
+                if license_mk.is_none() {
+                    license_mk = try_read_license((k, v)).map(|x| x.to_owned());
+                }
+
+                if license_files_mk.is_none() {
+                    license_files_mk = try_read_license_files((k, v)).map(|x| x.to_owned());
+                }
+
+                for dependency in try_read_dependencies((k, v), &prefix) {
+                    if !dependencies_mk.iter().any(|x| x == dependency) {
+                        dependencies_mk.push(dependency.to_owned());
+                    }
+                }
+
+                if let Some(cves) = try_read_ignore_cves((k, v)) {
+                    ignore_cves_mk.extend(cves.split_whitespace().map(|x| x.to_owned()));
+                }
+
+                for (cpe, suffix) in cpe_mk.iter_mut().zip(CPE_SUFFIXES) {
+                    if cpe.is_none() {
+                        *cpe = try_read_suffixed((k, v), suffix).map(|x| x.to_owned());
+                    }
                 }
             }
         }
@@ -107,13 +234,24 @@ impl MkFile {
             }
         }
 
-        let sources = source_mk.map_or(Vec::new(), |url| vec![PackageSource::Git(url)]);
+        let sources = site_mk
+            .and_then(|site| make_source(&site, method_mk.as_deref(), subdir_mk.as_deref()))
+            .map_or(Vec::new(), |source| vec![source]);
 
+        let cpe_id = make_cpe_id(name_file, ver_mk.as_deref(), &cpe_mk);
         Ok(Package {
             name: name_file.to_owned(),
             version: ver_mk,
             sources,
             location: Some(self.path.to_owned()),
+            license: license_mk,
+            license_files: license_files_mk
+                .map(|x| x.split_whitespace().map(|x| x.to_owned()).collect())
+                .unwrap_or_default(),
+            cpe_id,
+            ignore_cves: ignore_cves_mk,
+            dependencies: dependencies_mk,
+            ..Default::default()
         })
     }
 }
@@ -132,7 +270,7 @@ impl MkFileReader {
 
 impl PackageReader for MkFileReader {
     type Error = Error;
-    fn read(&mut self) -> Result<Packages, Self::Error> {
+    fn read(&mut self) -> Result<Packages> {
         let mkreader = MkFile::new(&self.path);
         let pkg = mkreader.read_info()?;
         let mut result = Packages::new();
@@ -141,47 +279,175 @@ impl PackageReader for MkFileReader {
     }
 }
 
+/// Which definition wins if several mk files define the same package.
+/// Files are processed in path order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Precedence {
+    #[default]
+    First,
+    Last,
+    Fail,
+}
+
+impl FromStr for Precedence {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "first" => Ok(Precedence::First),
+            "last" => Ok(Precedence::Last),
+            "error" => Ok(Precedence::Fail),
+            other => Err(Error::parse(
+                other,
+                None,
+                "unknown precedence, expected first, last or error",
+            )),
+        }
+    }
+}
+
+/// Package defined in more than one mk file.
+#[derive(Debug, Clone)]
+pub struct Duplicate {
+    pub name: String,
+    /// Definition in use.
+    pub kept: Package,
+    /// Conflicting definitions, in path order.
+    pub dropped: Vec<Package>,
+}
+
+impl Display for Duplicate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[!] {} is defined in {}, {} is used",
+            self.name,
+            self.locations().join(", "),
+            location(&self.kept)
+        )
+    }
+}
+
+impl Duplicate {
+    pub fn locations(&self) -> Vec<&str> {
+        std::iter::once(&self.kept)
+            .chain(self.dropped.iter())
+            .filter_map(|x| x.location.as_deref())
+            .collect()
+    }
+}
+
+fn location(package: &Package) -> &str {
+    package.location.as_deref().unwrap_or_default()
+}
+
+/// Merge packages of several mk files. Files that can't be read are
+/// skipped, unless none of them can. Duplicates are resolved according to
+/// `precedence` and returned along with the packages.
+pub fn collect_packages<I: IntoIterator<Item = Result<Package>>>(
+    packages: I,
+    precedence: Precedence,
+) -> Result<(Packages, Vec<Duplicate>)> {
+    let mut result = Packages::new();
+    let mut duplicates: Vec<Duplicate> = Vec::new();
+    let mut last_error = None;
+    for package in packages {
+        let pkg = match package {
+            Ok(pkg) => pkg,
+            Err(err) => {
+                warn!("failed to process {}", err);
+                last_error = Some(err);
+                continue;
+            }
+        };
+
+        let previous = match result.remove(&pkg.name) {
+            Some(previous) => previous,
+            None => {
+                result.insert(pkg.name.clone(), pkg);
+                continue;
+            }
+        };
+
+        warn!(
+            "{} is defined in {} and {}",
+            pkg.name,
+            location(&previous),
+            location(&pkg)
+        );
+        if precedence == Precedence::Fail {
+            let message = format!(
+                "duplicate package {}, also defined in {}",
+                pkg.name,
+                location(&previous)
+            );
+            return Err(Error::parse(location(&pkg), None, &message));
+        }
+
+        let (kept, dropped) = match precedence {
+            Precedence::Last => (pkg, previous),
+            _ => (previous, pkg),
+        };
+        match duplicates.iter_mut().find(|x| x.name == kept.name) {
+            Some(duplicate) => {
+                duplicate.kept = kept.clone();
+                duplicate.dropped.push(dropped);
+            }
+            None => duplicates.push(Duplicate {
+                name: kept.name.clone(),
+                kept: kept.clone(),
+                dropped: vec![dropped],
+            }),
+        }
+        result.insert(kept.name.clone(), kept);
+    }
+
+    match last_error {
+        Some(err) if result.is_empty() => Err(err),
+        _ => Ok((result, duplicates)),
+    }
+}
+
 pub struct MkFileDirReader {
     path: String,
+    precedence: Precedence,
+    duplicates: Vec<Duplicate>,
 }
 
 impl MkFileDirReader {
     pub fn new(path: &str) -> MkFileDirReader {
         MkFileDirReader {
             path: path.to_owned(),
+            precedence: Precedence::default(),
+            duplicates: Vec::new(),
         }
     }
+
+    pub fn precedence(mut self, precedence: Precedence) -> MkFileDirReader {
+        self.precedence = precedence;
+        self
+    }
 }
 
 impl PackageReader for MkFileDirReader {
     type Error = Error;
-    fn read(&mut self) -> Result<Packages, Self::Error> {
-        let mut result = Packages::new();
-        let mut last_error = None;
-        let entries = WalkDir::new(&self.path);
-        for e in entries.into_iter().flatten() {
-            if let Some(file) = e.path().to_str() {
-                if file.ends_with(".mk") {
-                    info!("process {}", file);
-                    let mkreader = MkFile::new(file);
-                    match mkreader.read_info() {
-                        Ok(pkg) => {
-                            result.insert(pkg.name.clone(), pkg);
-                        }
-                        Err(err) => {
-                            warn!("failed to process {}:{:?}", file, err);
-                            last_error = Some(err);
-                        }
-                    };
-                }
-            }
-        }
+    fn read(&mut self) -> Result<Packages> {
+        let entries = WalkDir::new(&self.path).sort_by_file_name();
+        let packages = entries
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.path().to_str().map(|x| x.to_owned()))
+            .filter(|file| file.ends_with(".mk"))
+            .map(|file| {
+                info!("process {}", file);
+                MkFile::new(&file).read_info()
+            });
+        let (packages, duplicates) = collect_packages(packages, self.precedence)?;
+        self.duplicates = duplicates;
+        Ok(packages)
+    }
 
-        if result.is_empty() && last_error.is_some() {
-            Err(Error::new(ErrorKind::Other, "failed to read mk-files"))
-        } else {
-            Ok(result)
-        }
+    fn duplicates(&self) -> &[Duplicate] {
+        &self.duplicates
     }
 }
 
@@ -189,6 +455,7 @@ impl PackageReader for MkFileDirReader {
 mod test {
 
     use super::*;
+    use crate::testutils::TempDir;
     #[test]
     fn test_get_cmd() {
         assert_eq!(try_read_cmdline("#commented"), None);
@@ -252,11 +519,128 @@ mod test {
     #[test]
     fn test_pkg_git_source() {
         let kv = try_read_key_value("MAGIC_PACKAGE_SITE = git@ololo.git").unwrap();
-        let res = try_read_git_source(kv).unwrap();
+        let res = try_read_site(kv).unwrap();
         assert_eq!(res, "git@ololo.git");
 
         let kv = try_read_key_value("   MAGIC_PACKAGE_SITE = git@ololo.git  ").unwrap();
-        let res = try_read_git_source(kv).unwrap();
+        let res = try_read_site(kv).unwrap();
         assert_eq!(res, "git@ololo.git");
+
+        let src = make_source(res, None, None).unwrap();
+        assert_eq!(src.method, FetchMethod::Git);
+        assert_eq!(src.url, "git@ololo.git");
+    }
+
+    #[test]
+    fn test_pkg_license() {
+        let kv = try_read_key_value("ZLIB_LICENSE = Zlib").unwrap();
+        assert_eq!(try_read_license(kv), Some("Zlib"));
+        assert_eq!(try_read_license_files(kv), None);
+
+        let kv = try_read_key_value("ZLIB_LICENSE_FILES = LICENSE README").unwrap();
+        assert_eq!(try_read_license(kv), None);
+        assert_eq!(try_read_license_files(kv), Some("LICENSE README"));
+
+        let pkg = MkFile::new("zlib.mk")
+            .parse("ZLIB_LICENSE = Zlib\nZLIB_LICENSE_FILES = LICENSE README\n".as_bytes())
+            .unwrap();
+        assert_eq!(pkg.license.as_deref(), Some("Zlib"));
+        assert_eq!(pkg.license_files, vec!["LICENSE", "README"]);
+    }
+
+    #[test]
+    fn test_pkg_cpe_id() {
+        let pkg = MkFile::new("libcurl.mk")
+            .parse("LIBCURL_VERSION = 8.4.0\nLIBCURL_CPE_ID_VENDOR = haxx\nLIBCURL_CPE_ID_PRODUCT = libcurl\n".as_bytes())
+            .unwrap();
+        assert_eq!(
+            pkg.cpe_id.as_deref(),
+            Some("cpe:2.3:a:haxx:libcurl:8.4.0:*:*:*:*:*:*:*")
+        );
+
+        let pkg = MkFile::new("zlib.mk")
+            .parse("ZLIB_VERSION = 1.3\n".as_bytes())
+            .unwrap();
+        assert_eq!(pkg.cpe_id, None);
+    }
+
+    #[test]
+    fn test_pkg_dependencies() {
+        let pkg = MkFile::new("libcurl.mk")
+            .parse("LIBCURL_VERSION = 8.4.0\nLIBCURL_DEPENDENCIES = host-pkgconf zlib\nHOST_LIBCURL_DEPENDENCIES = host-zlib\nifeq ($(BR2_PACKAGE_OPENSSL),y)\nLIBCURL_DEPENDENCIES += openssl $(if $(BR2_PACKAGE_C_ARES),c-ares)\nendif\n".as_bytes())
+            .unwrap();
+        assert_eq!(pkg.dependencies, vec!["host-pkgconf", "zlib", "openssl"]);
+    }
+
+    #[test]
+    fn test_pkg_ignore_cves() {
+        let pkg = MkFile::new("zlib.mk")
+            .parse(
+                "ZLIB_VERSION = 1.2.11
+# 0001-fix.patch
+ZLIB_IGNORE_CVES += CVE-2018-25032
+ZLIB_IGNORE_CVES += CVE-2022-37434 CVE-2023-45853
+"
+                .as_bytes(),
+            )
+            .unwrap();
+        assert_eq!(
+            pkg.ignore_cves,
+            vec!["CVE-2018-25032", "CVE-2022-37434", "CVE-2023-45853"]
+        );
+    }
+
+    #[test]
+    fn test_pkg_site_method() {
+        let kv = try_read_key_value("MAGIC_PACKAGE_SITE_METHOD = hg").unwrap();
+        assert_eq!(try_read_site(kv), None);
+        assert_eq!(try_read_site_method(kv), Some("hg"));
+
+        let src = make_source("https://hg.example.org/magic", Some("hg"), Some("magic")).unwrap();
+        assert_eq!(src.method, FetchMethod::Hg);
+        assert_eq!(src.subdir, Some("magic".to_owned()));
+
+        let src = make_source("https://example.org/magic", None, None).unwrap();
+        assert_eq!(src.method, FetchMethod::Https);
+
+        assert_eq!(
+            make_source("https://example.org/magic", Some("cp"), None),
+            None
+        );
+    }
+
+    #[test]
+    fn duplicates() {
+        let root = TempDir::new("mkfile-dups");
+        for (dir, version) in [("a", "1.0"), ("b", "2.0"), ("c", "3.0")] {
+            let path = root.join(dir).join("zlib");
+            std::fs::create_dir_all(&path).unwrap();
+            std::fs::write(
+                path.join("zlib.mk"),
+                format!("ZLIB_VERSION = {}\n", version),
+            )
+            .unwrap();
+        }
+        let root = root.to_str().unwrap();
+
+        let mut reader = MkFileDirReader::new(root);
+        let packages = reader.read().unwrap();
+        assert_eq!(packages["zlib"].version.as_deref(), Some("1.0"));
+        assert_eq!(reader.duplicates().len(), 1);
+        let duplicate = &reader.duplicates()[0];
+        assert_eq!(duplicate.dropped.len(), 2);
+        assert_eq!(duplicate.locations().len(), 3);
+        assert!(duplicate.locations()[0].ends_with("a/zlib/zlib.mk"));
+
+        let mut reader = MkFileDirReader::new(root).precedence(Precedence::Last);
+        let packages = reader.read().unwrap();
+        assert_eq!(packages["zlib"].version.as_deref(), Some("3.0"));
+        assert!(reader.duplicates()[0].locations()[0].ends_with("c/zlib/zlib.mk"));
+
+        let mut reader = MkFileDirReader::new(root).precedence(Precedence::Fail);
+        assert!(matches!(reader.read(), Err(Error::Parse { .. })));
+
+        assert_eq!(Precedence::from_str("last").unwrap(), Precedence::Last);
+        assert!(Precedence::from_str("random").is_err());
     }
 }
@@ -1,19 +1,145 @@
+use crate::error::Error;
+use crate::mkfile::Duplicate;
 use std::collections::HashMap;
+use std::fmt::Display;
 use std::str::FromStr;
 
+/// Download methods supported by Buildroot (see `<PKG>_SITE_METHOD`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FetchMethod {
+    Git,
+    Svn,
+    Hg,
+    Bzr,
+    Cvs,
+    Scp,
+    Sftp,
+    File,
+    Local,
+    Http,
+    Https,
+    Ftp,
+    Wget,
+}
+
+impl FetchMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FetchMethod::Git => "git",
+            FetchMethod::Svn => "svn",
+            FetchMethod::Hg => "hg",
+            FetchMethod::Bzr => "bzr",
+            FetchMethod::Cvs => "cvs",
+            FetchMethod::Scp => "scp",
+            FetchMethod::Sftp => "sftp",
+            FetchMethod::File => "file",
+            FetchMethod::Local => "local",
+            FetchMethod::Http => "http",
+            FetchMethod::Https => "https",
+            FetchMethod::Ftp => "ftp",
+            FetchMethod::Wget => "wget",
+        }
+    }
+
+    /// Version control systems, as opposed to plain file transfers.
+    pub fn is_vcs(&self) -> bool {
+        matches!(
+            self,
+            FetchMethod::Git
+                | FetchMethod::Svn
+                | FetchMethod::Hg
+                | FetchMethod::Bzr
+                | FetchMethod::Cvs
+        )
+    }
+
+    /// Guess the method from the URL scheme the same way Buildroot does when
+    /// `<PKG>_SITE_METHOD` is not set. Sites ending with `.git` are treated as
+    /// git repositories.
+    pub fn guess(url: &str) -> Option<FetchMethod> {
+        let url = url.trim();
+        if url.ends_with(".git") {
+            return Some(FetchMethod::Git);
+        }
+        let (scheme, _) = url.split_once("://")?;
+        FetchMethod::from_str(scheme).ok()
+    }
+}
+
+impl FromStr for FetchMethod {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let method = match s.trim() {
+            "git" => FetchMethod::Git,
+            "svn" => FetchMethod::Svn,
+            "hg" => FetchMethod::Hg,
+            "bzr" => FetchMethod::Bzr,
+            "cvs" => FetchMethod::Cvs,
+            "scp" => FetchMethod::Scp,
+            "sftp" => FetchMethod::Sftp,
+            "file" => FetchMethod::File,
+            "local" => FetchMethod::Local,
+            "http" => FetchMethod::Http,
+            "https" => FetchMethod::Https,
+            "ftp" => FetchMethod::Ftp,
+            "wget" => FetchMethod::Wget,
+            other => return Err(Error::unsupported_source(other)),
+        };
+        Ok(method)
+    }
+}
+
+impl Display for FetchMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Where and how a package is fetched.
+///
+/// Textual form follows the show-info URIs: `<method>[|<modifier>]+<url>`,
+/// optionally followed by `#<ref>` for VCS methods.
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub enum PackageSource {
-    Git(String),
-    Https(String),
-    Other(String),
+pub struct PackageSource {
+    pub method: FetchMethod,
+    pub url: String,
+    pub reference: Option<String>,
+    pub subdir: Option<String>,
+}
+
+impl PackageSource {
+    pub fn new(method: FetchMethod, url: &str) -> PackageSource {
+        PackageSource {
+            method,
+            url: url.to_owned(),
+            reference: None,
+            subdir: None,
+        }
+    }
+
+    pub fn is_git(&self) -> bool {
+        self.method == FetchMethod::Git
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Package {
     pub name: String,
     pub version: Option<String>,
     pub sources: PackageSources,
     pub location: Option<String>,
+    /// Name of the br2-external tree the package comes from (from
+    /// `external.desc`), `None` for Buildroot itself.
+    pub external: Option<String>,
+    /// License expression as Buildroot writes it (`GPL-2.0+, LGPL-2.1`).
+    pub license: Option<String>,
+    pub license_files: Vec<String>,
+    /// CPE 2.3 identifier, if known.
+    pub cpe_id: Option<String>,
+    /// CVEs that don't apply to the package (`<PKG>_IGNORE_CVES`).
+    pub ignore_cves: Vec<String>,
+    /// Names of packages the package depends on.
+    pub dependencies: Vec<String>,
 }
 
 impl PartialEq for Package {
@@ -23,13 +149,12 @@ impl PartialEq for Package {
 }
 
 impl Package {
+    pub fn get_source(&self, method: FetchMethod) -> Option<&PackageSource> {
+        self.sources.iter().find(|s| s.method == method)
+    }
+
     pub fn get_git_source(&self) -> Option<String> {
-        for s in &self.sources {
-            if let PackageSource::Git(uri) = s {
-                return Some(uri.to_owned());
-            }
-        }
-        None
+        self.get_source(FetchMethod::Git).map(|s| s.url.to_owned())
     }
 }
 
@@ -39,19 +164,53 @@ pub type PackageSources = Vec<PackageSource>;
 pub trait PackageReader {
     type Error;
     fn read(&mut self) -> Result<Packages, Self::Error>;
+
+    /// Packages defined more than once in the input, filled by `read`.
+    fn duplicates(&self) -> &[Duplicate] {
+        &[]
+    }
 }
 
 impl FromStr for PackageSource {
-    type Err = ();
+    type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let prefixes = ["git+", "https+", "other"];
-        let mode = prefixes.iter().find(|&x| s.contains(x));
-        let output = match mode {
-            Some(&"https+") => PackageSource::Https(s[6..].to_owned()),
-            Some(&"git+") => PackageSource::Git(s[4..].to_owned()),
-            _ => PackageSource::Other(s.to_owned()),
+        let s = s.trim();
+        let (prefix, url) = s
+            .split_once('+')
+            .ok_or_else(|| Error::unsupported_source(s))?;
+
+        // modifiers like 'urlencode' or 'split' only affect the way BR
+        // builds the final URL, so they are dropped
+        let method = prefix.split('|').next().unwrap_or(prefix);
+        let method = FetchMethod::from_str(method).map_err(|_| Error::unsupported_source(s))?;
+
+        let (url, reference) = match url.rsplit_once('#') {
+            Some((url, reference)) if method.is_vcs() && !reference.is_empty() => {
+                (url, Some(reference.to_owned()))
+            }
+            _ => (url, None),
         };
-        Ok(output)
+
+        if url.is_empty() {
+            return Err(Error::unsupported_source(s));
+        }
+
+        Ok(PackageSource {
+            method,
+            url: url.to_owned(),
+            reference,
+            subdir: None,
+        })
+    }
+}
+
+impl Display for PackageSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}+{}", self.method, self.url)?;
+        if let Some(reference) = &self.reference {
+            write!(f, "#{}", reference)?;
+        }
+        Ok(())
     }
 }
 
@@ -60,36 +219,78 @@ mod test {
     use super::*;
     #[test]
     fn from_str_https() {
-        match PackageSource::from_str("https+https://snapshot.debian.org/archive/debian/20201008T205817Z/pool/main/f/fakeroot").unwrap()
-        {
-            PackageSource::Https(src) => {
-                assert_eq!(src,"https://snapshot.debian.org/archive/debian/20201008T205817Z/pool/main/f/fakeroot")
-            },
-            _ => {unimplemented!()}
-        }
+        let src = PackageSource::from_str("https+https://snapshot.debian.org/archive/debian/20201008T205817Z/pool/main/f/fakeroot").unwrap();
+        assert_eq!(src.method, FetchMethod::Https);
+        assert_eq!(
+            src.url,
+            "https://snapshot.debian.org/archive/debian/20201008T205817Z/pool/main/f/fakeroot"
+        );
     }
 
     #[test]
     fn from_str_git() {
-        match PackageSource::from_str("git+git@github.com:rust-lang/rust.git").unwrap() {
-            PackageSource::Git(src) => {
-                assert_eq!(src, "git@github.com:rust-lang/rust.git")
-            }
-            _ => {
-                unimplemented!()
-            }
-        }
+        let src = PackageSource::from_str("git+git@github.com:rust-lang/rust.git").unwrap();
+        assert_eq!(src.method, FetchMethod::Git);
+        assert_eq!(src.url, "git@github.com:rust-lang/rust.git");
+        assert_eq!(src.reference, None);
     }
 
     #[test]
-    fn from_str_other() {
-        match PackageSource::from_str("local+/tmp/build/custom/super-package").unwrap() {
-            PackageSource::Other(src) => {
-                assert_eq!(src, "local+/tmp/build/custom/super-package")
-            }
-            _ => {
-                unimplemented!()
-            }
-        }
+    fn from_str_local() {
+        let src = PackageSource::from_str("local+/tmp/build/custom/super-package").unwrap();
+        assert_eq!(src.method, FetchMethod::Local);
+        assert_eq!(src.url, "/tmp/build/custom/super-package");
+    }
+
+    #[test]
+    fn from_str_modifier() {
+        let src =
+            PackageSource::from_str("http|urlencode+http://sources.buildroot.net/zlib").unwrap();
+        assert_eq!(src.method, FetchMethod::Http);
+        assert_eq!(src.url, "http://sources.buildroot.net/zlib");
+    }
+
+    #[test]
+    fn from_str_wget() {
+        let src = PackageSource::from_str("wget+http://host/file.tar.gz").unwrap();
+        assert_eq!(src.method, FetchMethod::Wget);
+        assert!(!src.method.is_vcs());
+    }
+
+    #[test]
+    fn from_str_reference() {
+        let src = PackageSource::from_str("git+https://host/repo.git#v1.0").unwrap();
+        assert_eq!(src.url, "https://host/repo.git");
+        assert_eq!(src.reference, Some("v1.0".to_owned()));
+        assert_eq!(src.to_string(), "git+https://host/repo.git#v1.0");
+
+        let src = PackageSource::from_str("https+https://host/page#anchor").unwrap();
+        assert_eq!(src.url, "https://host/page#anchor");
+        assert_eq!(src.reference, None);
+    }
+
+    #[test]
+    fn from_str_invalid() {
+        assert!(PackageSource::from_str("other").is_err());
+        assert!(PackageSource::from_str("git+").is_err());
+        assert!(PackageSource::from_str("curl+http://host/file").is_err());
+        assert!(PackageSource::from_str("/tmp/https+/file").is_err());
+    }
+
+    #[test]
+    fn guess_method() {
+        assert_eq!(
+            FetchMethod::guess("https://github.com/foo/bar.git"),
+            Some(FetchMethod::Git)
+        );
+        assert_eq!(
+            FetchMethod::guess("https://github.com/foo/bar"),
+            Some(FetchMethod::Https)
+        );
+        assert_eq!(
+            FetchMethod::guess("svn://svn.code.sf.net/p/foo"),
+            Some(FetchMethod::Svn)
+        );
+        assert_eq!(FetchMethod::guess("$(call github,foo,bar,v1)"), None);
     }
 }
@@ -0,0 +1,317 @@
+use crate::error::{Error, Result};
+use crate::gitworkspace::{self, RefKind};
+use git2::{Diff, Oid, Repository, Sort, Tree};
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+/// Without the old version, upstream patches are searched for among that
+/// many latest commits.
+const UPSTREAM_DEPTH: usize = 1000;
+
+/// Patches of a package, in the order Buildroot applies them: `*.patch` of
+/// the `<version>` subdirectory if it exists, of the mk file directory
+/// otherwise, sorted by name.
+pub fn find_patches(mkfile: &str, version: Option<&str>) -> Vec<PathBuf> {
+    let dir = match Path::new(mkfile).parent() {
+        Some(dir) => dir.to_path_buf(),
+        None => return Vec::new(),
+    };
+    let dir = match version.map(|x| dir.join(x)) {
+        Some(versioned) if versioned.is_dir() => versioned,
+        _ => dir,
+    };
+
+    let mut result: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|x| x.ok())
+        .map(|x| x.path())
+        .filter(|x| x.is_file() && x.extension().is_some_and(|x| x == "patch"))
+        .collect();
+    result.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+    result
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchStatus {
+    Applies,
+    /// The patch doesn't apply (or can't be parsed).
+    Fails(String),
+    /// The change is already upstream, in the given commit.
+    Upstream(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct PatchResult {
+    pub path: PathBuf,
+    pub status: PatchStatus,
+}
+
+impl PatchResult {
+    pub fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+}
+
+impl Display for PatchResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.status {
+            PatchStatus::Applies => write!(f, "{}: applies", self.file_name()),
+            PatchStatus::Fails(err) => write!(f, "{}: fails: {}", self.file_name(), err),
+            PatchStatus::Upstream(commit) => {
+                write!(f, "{}: upstream ({})", self.file_name(), commit)
+            }
+        }
+    }
+}
+
+/// Patch ids of non-merge commits reachable from `new`, but not from `old`.
+fn upstream_ids(
+    repo: &Repository,
+    url: &str,
+    old: Option<Oid>,
+    new: Oid,
+) -> Result<HashMap<Oid, String>> {
+    let git_error = |err| Error::git(url, err);
+    let mut walk = repo.revwalk().map_err(git_error)?;
+    walk.set_sorting(Sort::TOPOLOGICAL).map_err(git_error)?;
+    walk.push(new).map_err(git_error)?;
+    if let Some(old) = old {
+        walk.hide(old).map_err(git_error)?;
+    }
+
+    let limit = if old.is_some() {
+        usize::MAX
+    } else {
+        UPSTREAM_DEPTH
+    };
+    let mut result = HashMap::new();
+    for id in walk.take(limit) {
+        let commit = id.and_then(|x| repo.find_commit(x)).map_err(git_error)?;
+        if commit.parent_count() != 1 {
+            continue;
+        }
+        let parent = commit.parent(0).and_then(|x| x.tree()).map_err(git_error)?;
+        let tree = commit.tree().map_err(git_error)?;
+        let patch_id = repo
+            .diff_tree_to_tree(Some(&parent), Some(&tree), None)
+            .and_then(|x| x.patchid(None))
+            .map_err(git_error)?;
+        result.insert(patch_id, commit.id().to_string());
+    }
+    debug!("{}: {} upstream commit(s) to compare", url, result.len());
+    Ok(result)
+}
+
+fn apply<'a>(
+    repo: &'a Repository,
+    tree: &Tree,
+    diff: &Diff,
+) -> std::result::Result<Tree<'a>, git2::Error> {
+    let mut index = repo.apply_to_tree(tree, diff, None)?;
+    let id = index.write_tree_to(repo)?;
+    repo.find_tree(id)
+}
+
+/// Check patches against the `new` version of the repository: a patch is
+/// upstream if a commit between `old` and `new` has the same patch id,
+/// otherwise it is applied on top of the previous ones.
+pub fn check(
+    repo: &Repository,
+    url: &str,
+    old: Option<&str>,
+    new: &str,
+    patches: &[PathBuf],
+) -> Result<Vec<PatchResult>> {
+    let git_error = |err| Error::git(url, err);
+    let new = gitworkspace::resolve(repo, RefKind::Any, new)?
+        .peel_to_commit()
+        .map_err(git_error)?;
+    let old = old.and_then(|old| match gitworkspace::resolve(repo, RefKind::Any, old) {
+        Ok(object) => Some(object.id()),
+        Err(err) => {
+            warn!(
+                "{}: upstream patches are searched without the old version: {}",
+                url, err
+            );
+            None
+        }
+    });
+    let upstream = upstream_ids(repo, url, old, new.id())?;
+
+    let mut tree = new.tree().map_err(git_error)?;
+    let mut result = Vec::new();
+    for path in patches {
+        let file = path.to_string_lossy();
+        let data = std::fs::read(path).map_err(|err| Error::io(&file, err))?;
+        let status = match Diff::from_buffer(&data) {
+            Err(err) => PatchStatus::Fails(err.message().to_owned()),
+            Ok(diff) => match diff.patchid(None).ok().and_then(|x| upstream.get(&x)) {
+                Some(commit) => PatchStatus::Upstream(commit.clone()),
+                None => match apply(repo, &tree, &diff) {
+                    Ok(patched) => {
+                        tree = patched;
+                        PatchStatus::Applies
+                    }
+                    Err(err) => PatchStatus::Fails(err.message().to_owned()),
+                },
+            },
+        };
+        info!("{}: {:?}", file, status);
+        result.push(PatchResult {
+            path: path.clone(),
+            status,
+        });
+    }
+    Ok(result)
+}
+
+/// Remove upstream patches; returns the removed files.
+pub fn drop_upstream(results: &[PatchResult]) -> Result<Vec<PathBuf>> {
+    let mut removed = Vec::new();
+    for result in results {
+        if let PatchStatus::Upstream(commit) = &result.status {
+            let file = result.path.to_string_lossy();
+            info!("drop {}, upstream in {}", file, commit);
+            std::fs::remove_file(&result.path).map_err(|err| Error::io(&file, err))?;
+            removed.push(result.path.clone());
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutils::TempDir;
+    use git2::{DiffFormat, Signature};
+
+    fn commit(repo: &Repository, content: &str, message: &str) -> Oid {
+        let dir = repo.workdir().unwrap();
+        std::fs::write(dir.join("main.c"), content).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("main.c")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("test", "test@example.com").unwrap();
+        let parent = repo.head().ok().and_then(|x| x.peel_to_commit().ok());
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )
+        .unwrap()
+    }
+
+    fn patch(repo: &Repository, from: &str, to: &str, path: &Path) {
+        let blob = |content: &str| repo.blob(content.as_bytes()).unwrap();
+        let (from, to) = (blob(from), blob(to));
+        let mut index = git2::Index::new().unwrap();
+        let entry = |id: Oid| git2::IndexEntry {
+            ctime: git2::IndexTime::new(0, 0),
+            mtime: git2::IndexTime::new(0, 0),
+            dev: 0,
+            ino: 0,
+            mode: 0o100644,
+            uid: 0,
+            gid: 0,
+            file_size: 0,
+            id,
+            flags: 0,
+            flags_extended: 0,
+            path: b"main.c".to_vec(),
+        };
+        index.add(&entry(from)).unwrap();
+        let old = repo.find_tree(index.write_tree_to(repo).unwrap()).unwrap();
+        index.add(&entry(to)).unwrap();
+        let new = repo.find_tree(index.write_tree_to(repo).unwrap()).unwrap();
+
+        let diff = repo
+            .diff_tree_to_tree(Some(&old), Some(&new), None)
+            .unwrap();
+        let mut data = b"From: test\nSubject: [PATCH] change\n\n---\n".to_vec();
+        diff.print(DiffFormat::Patch, |_, _, line| {
+            if matches!(line.origin(), '+' | '-' | ' ') {
+                data.push(line.origin() as u8);
+            }
+            data.extend_from_slice(line.content());
+            true
+        })
+        .unwrap();
+        std::fs::write(path, data).unwrap();
+    }
+
+    #[test]
+    fn check_patches() {
+        let dir = TempDir::new("patches");
+        let repo = Repository::init(dir.join("repo")).unwrap();
+        let pkg = dir.join("package").join("foo");
+        std::fs::create_dir_all(pkg.join("1.1")).unwrap();
+
+        let v1 = "int a;\nint b;\nint c;\nint d;\nint e;\nint f;\nint g;\nint h;\n";
+        let v2 = "int a;\nint b2;\nint c;\nint d;\nint e;\nint f;\nint g;\nint h;\n";
+        let v3 = "int a;\nint b2;\nint c;\nint d;\nint e;\nint f;\nint g;\nint h2;\n";
+        let old = commit(&repo, v1, "v1");
+        commit(&repo, v2, "fix b");
+        let new = commit(&repo, v3, "fix h");
+
+        // already upstream, applies on top of v3, conflicts with v3
+        patch(&repo, v1, v2, &pkg.join("0001-fix-b.patch"));
+        patch(
+            &repo,
+            v3,
+            &v3.replace("int d;", "int d2;"),
+            &pkg.join("0002-fix-d.patch"),
+        );
+        patch(
+            &repo,
+            v1,
+            &v1.replace("int h;", "int h3;"),
+            &pkg.join("1.1").join("0003-fix-h.patch"),
+        );
+        std::fs::write(pkg.join("0004-broken.patch"), "not a patch").unwrap();
+
+        let mkfile = pkg.join("foo.mk");
+        let mkfile = mkfile.to_str().unwrap();
+        let names = |patches: &[PathBuf]| -> Vec<String> {
+            patches
+                .iter()
+                .map(|x| x.file_name().unwrap().to_string_lossy().to_string())
+                .collect()
+        };
+        let versioned = find_patches(mkfile, Some("1.1"));
+        assert_eq!(names(&versioned), vec!["0003-fix-h.patch"]);
+        let patches = find_patches(mkfile, Some("1.2"));
+        assert_eq!(
+            names(&patches),
+            vec!["0001-fix-b.patch", "0002-fix-d.patch", "0004-broken.patch"]
+        );
+        assert_eq!(find_patches(mkfile, None), patches);
+
+        let old = old.to_string();
+        let results = check(&repo, "repo", Some(&old), &new.to_string(), &patches).unwrap();
+        let statuses: Vec<&PatchStatus> = results.iter().map(|x| &x.status).collect();
+        assert!(matches!(statuses[0], PatchStatus::Upstream(_)));
+        assert_eq!(statuses[1], &PatchStatus::Applies);
+        assert!(matches!(statuses[2], PatchStatus::Fails(_)));
+        let results = check(&repo, "repo", Some(&old), &new.to_string(), &versioned).unwrap();
+        assert!(matches!(results[0].status, PatchStatus::Fails(_)));
+
+        // without the old version, the latest commits are searched
+        let results = check(&repo, "repo", None, &new.to_string(), &patches[..1]).unwrap();
+        assert!(matches!(results[0].status, PatchStatus::Upstream(_)));
+
+        let removed = drop_upstream(&results).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert!(!pkg.join("0001-fix-b.patch").exists());
+    }
+}
@@ -0,0 +1,58 @@
+use crate::error::{Error, Result};
+use regex::Regex;
+
+/// Name pattern: a regex if wrapped with slashes (`/^v[0-9.]+$/`),
+/// otherwise a glob where `*` matches any sequence and `?` any character.
+#[derive(Debug, Clone)]
+pub struct Pattern(Regex);
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut result = String::from("^");
+    for symb in glob.chars() {
+        match symb {
+            '*' => result.push_str(".*"),
+            '?' => result.push('.'),
+            symb => result.push_str(&regex::escape(&symb.to_string())),
+        }
+    }
+    result.push('$');
+    result
+}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Result<Pattern> {
+        let regex = match pattern.strip_prefix('/').and_then(|x| x.strip_suffix('/')) {
+            Some(regex) => regex.to_owned(),
+            None => glob_to_regex(pattern),
+        };
+        Regex::new(&regex)
+            .map(Pattern)
+            .map_err(|err| Error::parse(pattern, None, &err.to_string()))
+    }
+
+    pub fn is_match(&self, name: &str) -> bool {
+        self.0.is_match(name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn patterns() {
+        let glob = Pattern::new("v*.*.*").unwrap();
+        assert!(glob.is_match("v2.3.1"));
+        assert!(!glob.is_match("2.3.1"));
+
+        let glob = Pattern::new("lib?.mk").unwrap();
+        assert!(glob.is_match("libz.mk"));
+        assert!(!glob.is_match("libzz.mk"));
+
+        let regex = Pattern::new("/^v[0-9]+$/").unwrap();
+        assert!(regex.is_match("v2"));
+        assert!(!regex.is_match("v2.3"));
+
+        assert!(Pattern::new("/[/").is_err());
+    }
+}
@@ -0,0 +1,87 @@
+use crate::error::{Error, Result};
+use crate::mkfile::{self, Precedence};
+use crate::package::PackageReader;
+use crate::{defconfig, external, legalinfo, sbom, showinfo};
+use log::info;
+
+pub type BoxedReader = Box<dyn PackageReader<Error = Error>>;
+
+/// Pick a reader by the input: legal-info, an SBOM, show-info JSON, a
+/// defconfig, mk files or trees. `precedence` resolves packages defined twice
+/// in a tree.
+pub fn guess(filename: &str, precedence: Precedence) -> Result<BoxedReader> {
+    if legalinfo::is_legal_info(filename) {
+        info!("use legal-info manifest reader for {}", filename);
+        Ok(Box::new(legalinfo::ManifestReader::new(filename)))
+    } else if let Some(format) = sbom::detect(filename) {
+        info!("use {:?} SBOM reader for {}", format, filename);
+        match format {
+            sbom::SbomFormat::Spdx => Ok(Box::new(sbom::SpdxReader::new(filename))),
+            sbom::SbomFormat::CycloneDx => Ok(Box::new(sbom::CycloneDxReader::new(filename))),
+        }
+    } else if is_show_info(filename) {
+        info!("use ShowInfo reader for {}", filename);
+        Ok(Box::new(showinfo::ReportReader::new(filename)))
+    } else {
+        guess_mk(filename, precedence)
+    }
+}
+
+/// Inputs [`guess`] reads as a show-info JSON: `.json` files which are not
+/// SBOMs and anything that isn't read from mk files.
+pub fn is_show_info(filename: &str) -> bool {
+    !legalinfo::is_legal_info(filename)
+        && sbom::detect(filename).is_none()
+        && (filename.ends_with(".json") || !is_mk_input(filename))
+}
+
+/// Inputs read from mk files: a defconfig, mk files, trees or a package
+/// directory.
+pub fn is_mk_input(filename: &str) -> bool {
+    defconfig::is_defconfig(filename)
+        || filename.ends_with(".mk")
+        || external::is_external_tree(filename)
+        || std::fs::read_dir(filename).is_ok()
+}
+
+/// Like [`guess`], but only for inputs backed by mk files, for tools which
+/// edit them or look next to them.
+pub fn guess_mk(filename: &str, precedence: Precedence) -> Result<BoxedReader> {
+    if defconfig::is_defconfig(filename) {
+        info!("use Defconfig reader for {}", filename);
+        Ok(Box::new(defconfig::DefconfigReader::new(filename)))
+    } else if filename.ends_with(".mk") {
+        info!("use MkFile reader for {}", filename);
+        Ok(Box::new(mkfile::MkFileReader::new(filename)))
+    } else if external::is_external_tree(filename) {
+        info!("use external tree reader for {}", filename);
+        Ok(Box::new(
+            external::ExternalTreeReader::from_list(filename).precedence(precedence),
+        ))
+    } else if std::fs::read_dir(filename).is_ok() {
+        info!("use default dir. reader for {}", filename);
+        Ok(Box::new(
+            mkfile::MkFileDirReader::new(filename).precedence(precedence),
+        ))
+    } else {
+        info!("use default file reader for {}", filename);
+        Ok(Box::new(mkfile::MkFileReader::new(filename)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutils::TempDir;
+
+    #[test]
+    fn mk_inputs() {
+        let root = TempDir::new("reader");
+        let dir = root.join("package");
+        std::fs::create_dir_all(&dir).unwrap();
+        assert!(is_mk_input(dir.to_str().unwrap()));
+        assert!(is_mk_input("package/zlib/zlib.mk"));
+        assert!(!is_mk_input("show-info.json"));
+        assert!(!is_mk_input("/nonexistent/output"));
+    }
+}
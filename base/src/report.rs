@@ -1,15 +1,156 @@
+use crate::buildtime::BuildTimeDiff;
+use crate::configdiff::ConfigDiffs;
+use crate::cve::{CveDiff, Vulnerabilities};
 use crate::diffs::PackagesDiff;
-use std::io::{Error, Write};
+use crate::error::{Error, Result};
+use crate::filelist::{FileDiffs, SizeDiff};
+use crate::legalinfo::LicenseReport;
+use crate::mkfile::Duplicate;
+use crate::package::Packages;
+use std::io::Write;
 
 pub fn print_diffs(diffs: &PackagesDiff) {
     diffs.iter().for_each(|(_, diff)| println!("{}", diff));
 }
 
-pub fn write_diffs(file: &str, diffs: &PackagesDiff) -> Result<(), Error> {
-    let mut file = std::fs::File::create(file).or_else(|_| std::fs::File::open(file))?;
+fn format_config_diffs(diffs: &ConfigDiffs) -> String {
+    let mut result = String::new();
+    for (group, symbols) in diffs {
+        result.push_str(&format!("[*] {} [config]\n", group));
+        for symbol in symbols {
+            result.push_str(&format!("{}\n", symbol));
+        }
+        result.push('\n');
+    }
+    result
+}
+
+pub fn print_config_diffs(diffs: &ConfigDiffs) {
+    print!("{}", format_config_diffs(diffs));
+}
+
+pub fn print_failures(failures: &[(String, Error)]) {
+    if failures.is_empty() {
+        return;
+    }
+    println!("failed {} package(s):", failures.len());
+    failures
+        .iter()
+        .for_each(|(name, err)| println!("[!] {}: {}", name, err));
+}
+
+pub fn write_diffs(file: &str, diffs: &PackagesDiff, config_diffs: &ConfigDiffs) -> Result<()> {
+    let mut file = std::fs::File::create(file)
+        .or_else(|_| std::fs::File::open(file))
+        .map_err(|err| Error::io(file, err))?;
 
     diffs.iter().for_each(|(_, diff)| {
         let _ = file.write_fmt(format_args!("{}", diff));
     });
+    let _ = file.write_all(format_config_diffs(config_diffs).as_bytes());
     Ok(())
 }
+
+fn format_duplicates(duplicates: &[Duplicate]) -> String {
+    duplicates.iter().map(|x| format!("{}\n", x)).collect()
+}
+
+pub fn print_duplicates(duplicates: &[Duplicate]) {
+    print!("{}", format_duplicates(duplicates));
+}
+
+pub fn append_duplicates(file: &str, duplicates: &[Duplicate]) -> Result<()> {
+    append(file, &format_duplicates(duplicates))
+}
+
+pub fn print_license_report(report: &LicenseReport) {
+    print!("{}", report);
+}
+
+fn append(file: &str, report: &dyn std::fmt::Display) -> Result<()> {
+    std::fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(file)
+        .and_then(|mut out| out.write_fmt(format_args!("{}", report)))
+        .map_err(|err| Error::io(file, err))
+}
+
+pub fn append_license_report(file: &str, report: &LicenseReport) -> Result<()> {
+    append(file, report)
+}
+
+pub fn print_cve_diff(diff: &CveDiff) {
+    print!("{}", diff);
+}
+
+pub fn append_cve_diff(file: &str, diff: &CveDiff) -> Result<()> {
+    append(file, diff)
+}
+
+pub fn print_build_time_diff(diff: &BuildTimeDiff) {
+    print!("{}", diff);
+}
+
+pub fn append_build_time_diff(file: &str, diff: &BuildTimeDiff) -> Result<()> {
+    append(file, diff)
+}
+
+pub fn print_size_diff(diff: &SizeDiff) {
+    print!("{}", diff);
+}
+
+pub fn append_size_diff(file: &str, diff: &SizeDiff) -> Result<()> {
+    append(file, diff)
+}
+
+fn format_file_diffs(diffs: &FileDiffs) -> String {
+    let mut result = String::new();
+    for (package, diff) in diffs {
+        result.push_str(&format!("[*] {} [files]\n", package));
+        for path in &diff.added {
+            result.push_str(&format!("      + {}\n", path));
+        }
+        for path in &diff.removed {
+            result.push_str(&format!("      - {}\n", path));
+        }
+        result.push('\n');
+    }
+    result
+}
+
+pub fn print_file_diffs(diffs: &FileDiffs) {
+    print!("{}", format_file_diffs(diffs));
+}
+
+pub fn append_file_diffs(file: &str, diffs: &FileDiffs) -> Result<()> {
+    append(file, &format_file_diffs(diffs))
+}
+
+fn format_vulnerabilities(packages: &Packages, vulnerabilities: &Vulnerabilities) -> String {
+    let mut result = String::new();
+    for (name, cves) in vulnerabilities {
+        let version = packages
+            .get(name)
+            .and_then(|x| x.version.as_deref())
+            .unwrap_or("-");
+        result.push_str(&format!("[!] {} {}\n", name, version));
+        for cve in cves {
+            result.push_str(&format!("      {}\n", cve));
+        }
+    }
+    result
+}
+
+pub fn print_vulnerabilities(packages: &Packages, vulnerabilities: &Vulnerabilities) {
+    print!("{}", format_vulnerabilities(packages, vulnerabilities));
+}
+
+pub fn write_vulnerabilities(
+    file: &str,
+    packages: &Packages,
+    vulnerabilities: &Vulnerabilities,
+) -> Result<()> {
+    std::fs::write(file, format_vulnerabilities(packages, vulnerabilities))
+        .map_err(|err| Error::io(file, err))
+}
@@ -0,0 +1,835 @@
+use crate::error::{Error, Result};
+use crate::gitworkspace::{self, GitWorkspace, RefKind};
+use crate::json::{self, items};
+use crate::legalinfo;
+use crate::package::{FetchMethod, Package, PackageReader, PackageSource, Packages};
+use crate::utils;
+use log::{debug, info, warn};
+use serde_json::{json, Value};
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hasher};
+use std::str::FromStr;
+
+/// SBOM formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SbomFormat {
+    /// SPDX 2.3 JSON.
+    Spdx,
+    /// CycloneDX 1.5 JSON.
+    CycloneDx,
+}
+
+impl FromStr for SbomFormat {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "spdx" => Ok(SbomFormat::Spdx),
+            "cyclonedx" | "cdx" => Ok(SbomFormat::CycloneDx),
+            other => Err(Error::parse(
+                other,
+                None,
+                "unknown SBOM format, expected spdx or cyclonedx",
+            )),
+        }
+    }
+}
+
+/// Document level information.
+#[derive(Debug, Clone)]
+pub struct Document {
+    /// Name of the image (or product) the SBOM describes.
+    pub name: String,
+    /// Name and version of the tool, `brsbom-0.1.0`.
+    pub tool: String,
+    /// Seconds since epoch.
+    pub created: i64,
+}
+
+/// Exact commits of git packages, by package name.
+pub type Commits = HashMap<String, String>;
+
+/// Resolve versions of git packages (tags, branches, short hashes) to full
+/// commit ids in the workspace clones. Packages that can't be resolved are
+/// returned as failures and keep their version in the SBOM.
+pub fn resolve_commits(
+    packages: &Packages,
+    options: &gitworkspace::Options,
+) -> Result<(Commits, Vec<(String, Error)>)> {
+    let ws = GitWorkspace::new(options);
+    ws.init()?;
+
+    let mut commits = Commits::new();
+    let mut failures = Vec::new();
+    for package in packages.values() {
+        let (url, version) = match (package.get_git_source(), &package.version) {
+            (Some(url), Some(version)) => (url, version),
+            _ => continue,
+        };
+
+        let commit = ws.create_repo(&url).and_then(|repo| {
+            gitworkspace::resolve(&repo, RefKind::Any, version).map(|x| x.id().to_string())
+        });
+        match commit {
+            Ok(commit) => {
+                info!("{}: {} is {}", package.name, version, commit);
+                commits.insert(package.name.clone(), commit);
+            }
+            Err(err) => {
+                warn!("{}: can't resolve {}: {}", package.name, version, err);
+                failures.push((package.name.clone(), err));
+            }
+        }
+    }
+    Ok((commits, failures))
+}
+
+/// SPDX license ids Buildroot packages use; other license names become
+/// `LicenseRef-`s.
+const SPDX_LICENSES: [&str; 74] = [
+    "0BSD",
+    "AFL-2.1",
+    "AFL-3.0",
+    "AGPL-3.0",
+    "Apache-1.1",
+    "Apache-2.0",
+    "APSL-2.0",
+    "Artistic-1.0",
+    "Artistic-2.0",
+    "Beerware",
+    "BSD-1-Clause",
+    "BSD-2-Clause",
+    "BSD-2-Clause-Patent",
+    "BSD-3-Clause",
+    "BSD-3-Clause-Clear",
+    "BSD-4-Clause",
+    "BSL-1.0",
+    "bzip2-1.0.6",
+    "CC-BY-3.0",
+    "CC-BY-4.0",
+    "CC-BY-SA-3.0",
+    "CC-BY-SA-4.0",
+    "CC0-1.0",
+    "CDDL-1.0",
+    "CECILL-2.1",
+    "curl",
+    "EPL-1.0",
+    "EPL-2.0",
+    "EUPL-1.2",
+    "FSFAP",
+    "FTL",
+    "GFDL-1.2",
+    "GFDL-1.3",
+    "GPL-1.0",
+    "GPL-2.0",
+    "GPL-3.0",
+    "HPND",
+    "ICU",
+    "IJG",
+    "IPL-1.0",
+    "ISC",
+    "LGPL-2.0",
+    "LGPL-2.1",
+    "LGPL-3.0",
+    "Libpng",
+    "libpng-2.0",
+    "LPPL-1.3c",
+    "MIT",
+    "MIT-0",
+    "MPL-1.1",
+    "MPL-2.0",
+    "MS-PL",
+    "NCSA",
+    "OFL-1.1",
+    "OLDAP-2.8",
+    "OpenSSL",
+    "PHP-3.01",
+    "PostgreSQL",
+    "PSF-2.0",
+    "Python-2.0",
+    "Ruby",
+    "SGI-B-2.0",
+    "Sleepycat",
+    "SMLNJ",
+    "TCL",
+    "Unicode-DFS-2016",
+    "Unlicense",
+    "UPL-1.0",
+    "Vim",
+    "W3C",
+    "WTFPL",
+    "X11",
+    "Zlib",
+    "ZPL-2.1",
+];
+
+/// SPDX exceptions for `<license> with <exception>` names.
+const SPDX_EXCEPTIONS: [&str; 14] = [
+    "Autoconf-exception-3.0",
+    "Bison-exception-2.2",
+    "Classpath-exception-2.0",
+    "eCos-exception-2.0",
+    "Font-exception-2.0",
+    "GCC-exception-2.0",
+    "GCC-exception-3.1",
+    "LGPL-3.0-linking-exception",
+    "Libtool-exception",
+    "Linux-syscall-note",
+    "LLVM-exception",
+    "openvpn-openssl-exception",
+    "u-boot-exception-2.0",
+    "WxWindows-exception-3.1",
+];
+
+fn known(ids: &[&str], name: &str) -> Option<String> {
+    ids.iter()
+        .find(|id| id.eq_ignore_ascii_case(name))
+        .map(|id| (*id).to_owned())
+}
+
+/// SPDX id of a license name: a known id, optionally followed by `+`,
+/// `-only` or `-or-later`.
+fn license_id(name: &str) -> Option<String> {
+    let (base, suffix) = ["+", "-only", "-or-later"]
+        .iter()
+        .find_map(|suffix| name.strip_suffix(suffix).map(|base| (base, *suffix)))
+        .unwrap_or((name, ""));
+    known(&SPDX_LICENSES, base).map(|id| format!("{}{}", id, suffix))
+}
+
+fn license_ref(name: &str) -> String {
+    let id: String = name
+        .chars()
+        .map(|x| {
+            if x.is_ascii_alphanumeric() || x == '.' || x == '-' {
+                x
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("LicenseRef-{}", id)
+}
+
+/// SPDX expression of one license name. Names which aren't SPDX ids are
+/// recorded in `refs`, by their `LicenseRef-`.
+fn license_expression(name: &str, refs: &mut BTreeMap<String, String>) -> String {
+    if let Some(id) = license_id(name) {
+        return id;
+    }
+    let exception = name
+        .split_once(" with ")
+        .or_else(|| name.split_once(" WITH "))
+        .and_then(|(license, exception)| {
+            let exception = known(&SPDX_EXCEPTIONS, exception.trim())?;
+            Some(format!(
+                "{} WITH {}",
+                license_id(license.trim())?,
+                exception
+            ))
+        });
+    if let Some(expression) = exception {
+        return expression;
+    }
+
+    let name = name.strip_prefix("LicenseRef-").unwrap_or(name);
+    let id = license_ref(name);
+    refs.entry(id.clone()).or_insert_with(|| name.to_owned());
+    id
+}
+
+/// Convert a Buildroot license string into an SPDX license expression, with
+/// names which aren't SPDX ids added to `refs`.
+fn spdx_expression(license: &str, refs: &mut BTreeMap<String, String>) -> String {
+    let license = legalinfo::strip_comments(license);
+    let mut parts: Vec<(String, usize)> = Vec::new();
+    for part in license.split([',', ';']) {
+        let mut names: Vec<String> = Vec::new();
+        let mut operators: Vec<&str> = Vec::new();
+        let mut words: Vec<&str> = Vec::new();
+        for word in part.split_whitespace() {
+            let operator = match word {
+                "or" | "OR" => "OR",
+                "and" | "AND" => "AND",
+                word => {
+                    words.push(word);
+                    continue;
+                }
+            };
+            if !words.is_empty() {
+                names.push(words.join(" "));
+                operators.push(operator);
+                words.clear();
+            }
+        }
+        if !words.is_empty() {
+            names.push(words.join(" "));
+        }
+
+        let mut expression = String::new();
+        for (idx, name) in names.iter().enumerate() {
+            if idx > 0 {
+                expression.push_str(&format!(" {} ", operators[idx - 1]));
+            }
+            expression.push_str(&license_expression(name, refs));
+        }
+        if !names.is_empty() {
+            parts.push((expression, names.len()));
+        }
+    }
+
+    if parts.len() == 1 {
+        return parts.remove(0).0;
+    }
+    parts
+        .into_iter()
+        .map(|(expression, count)| {
+            if count > 1 {
+                format!("({})", expression)
+            } else {
+                expression
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" AND ")
+}
+
+/// Convert a Buildroot license string into an SPDX license expression:
+/// comma-separated licenses are all applicable (`AND`), `or` is kept as a
+/// choice, comments in parentheses are dropped and license names which
+/// aren't SPDX ids become `LicenseRef-`s of the whole name. Empty if there is
+/// no license.
+pub fn spdx_license(license: &str) -> String {
+    spdx_expression(license, &mut BTreeMap::new())
+}
+
+fn sorted(packages: &Packages) -> Vec<&Package> {
+    let mut result: Vec<&Package> = packages.values().collect();
+    result.sort_by(|a, b| a.name.cmp(&b.name));
+    result
+}
+
+fn spdx_id(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|x| {
+            if x.is_ascii_alphanumeric() || x == '.' || x == '-' {
+                x
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("SPDXRef-Package-{}", name)
+}
+
+/// Random UUID (version 4).
+fn uuid() -> String {
+    let random = |salt: i64| {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_i64(salt);
+        hasher.write_u32(std::process::id());
+        hasher.finish()
+    };
+    let high = random(utils::now());
+    let low = random(!utils::now());
+    let high = (high & 0xffff_ffff_ffff_0fff) | 0x4000;
+    let low = (low & 0x3fff_ffff_ffff_ffff) | 0x8000_0000_0000_0000;
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        high >> 32,
+        (high >> 16) & 0xffff,
+        high & 0xffff,
+        low >> 48,
+        low & 0xffff_ffff_ffff
+    )
+}
+
+/// Where to get the package from. For git packages with a resolved commit it
+/// is the SPDX VCS form, `git+<url>@<commit>`.
+fn download_location(package: &Package, commit: Option<&String>) -> Option<String> {
+    if let (Some(url), Some(commit)) = (package.get_git_source(), commit) {
+        let url = if url.starts_with("git+") || url.starts_with("git://") {
+            url
+        } else {
+            format!("git+{}", url)
+        };
+        return Some(format!("{}@{}", url, commit));
+    }
+    package.sources.first().map(|x| x.url.clone())
+}
+
+pub fn to_spdx(packages: &Packages, doc: &Document, commits: &Commits) -> Value {
+    let mut items = Vec::new();
+    let mut relationships = Vec::new();
+    let mut refs = BTreeMap::new();
+    for package in sorted(packages) {
+        let commit = commits.get(&package.name);
+        let id = spdx_id(&package.name);
+        let license = package
+            .license
+            .as_deref()
+            .map(|x| spdx_expression(x, &mut refs))
+            .filter(|x| !x.is_empty())
+            .unwrap_or_else(|| "NOASSERTION".to_owned());
+
+        let mut item = json!({
+            "name": package.name,
+            "SPDXID": id,
+            "downloadLocation": download_location(package, commit)
+                .unwrap_or_else(|| "NOASSERTION".to_owned()),
+            "filesAnalyzed": false,
+            "licenseConcluded": "NOASSERTION",
+            "licenseDeclared": license,
+            "copyrightText": "NOASSERTION",
+        });
+        if let Some(version) = &package.version {
+            item["versionInfo"] = json!(version);
+        }
+        if let Some(cpe) = &package.cpe_id {
+            item["externalRefs"] = json!([{
+                "referenceCategory": "SECURITY",
+                "referenceType": "cpe23Type",
+                "referenceLocator": cpe,
+            }]);
+        }
+        items.push(item);
+        relationships.push(json!({
+            "spdxElementId": "SPDXRef-DOCUMENT",
+            "relationshipType": "DESCRIBES",
+            "relatedSpdxElement": id,
+        }));
+    }
+
+    let mut sbom = json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": doc.name,
+        "documentNamespace": format!("https://spdx.org/spdxdocs/{}-{}", doc.name, uuid()),
+        "creationInfo": {
+            "created": utils::format_timestamp(doc.created),
+            "creators": [format!("Tool: {}", doc.tool)],
+        },
+        "packages": items,
+        "relationships": relationships,
+    });
+    if !refs.is_empty() {
+        // only the names are known, Buildroot keeps the texts in legal-info
+        sbom["hasExtractedLicensingInfos"] = refs
+            .into_iter()
+            .map(|(id, name)| {
+                json!({
+                    "licenseId": id,
+                    "name": name,
+                    "extractedText": format!("{} (license text not included)", name),
+                })
+            })
+            .collect();
+    }
+    sbom
+}
+
+pub fn to_cyclonedx(packages: &Packages, doc: &Document, commits: &Commits) -> Value {
+    let (tool, tool_version) = doc.tool.rsplit_once('-').unwrap_or((&doc.tool, ""));
+    let mut components = Vec::new();
+    for package in sorted(packages) {
+        let commit = commits.get(&package.name);
+        let mut component = json!({
+            "type": "library",
+            "bom-ref": package.name,
+            "name": package.name,
+        });
+        if let Some(version) = &package.version {
+            component["version"] = json!(version);
+        }
+        if let Some(license) = package.license.as_deref().map(spdx_license) {
+            if !license.is_empty() {
+                component["licenses"] = json!([{ "expression": license }]);
+            }
+        }
+        if let Some(cpe) = &package.cpe_id {
+            component["cpe"] = json!(cpe);
+        }
+        if let Some(url) = download_location(package, commit) {
+            let kind = if package.get_git_source().is_some() {
+                "vcs"
+            } else {
+                "distribution"
+            };
+            component["externalReferences"] = json!([{ "type": kind, "url": url }]);
+        }
+        components.push(component);
+    }
+
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "serialNumber": format!("urn:uuid:{}", uuid()),
+        "version": 1,
+        "metadata": {
+            "timestamp": utils::format_timestamp(doc.created),
+            "tools": [{ "name": tool, "version": tool_version }],
+            "component": {
+                "type": "firmware",
+                "bom-ref": doc.name,
+                "name": doc.name,
+            },
+        },
+        "components": components,
+    })
+}
+
+pub fn export(format: SbomFormat, packages: &Packages, doc: &Document, commits: &Commits) -> Value {
+    match format {
+        SbomFormat::Spdx => to_spdx(packages, doc, commits),
+        SbomFormat::CycloneDx => to_cyclonedx(packages, doc, commits),
+    }
+}
+
+fn read_json(path: &str) -> Result<Value> {
+    let data = std::fs::read_to_string(path).map_err(|err| Error::io(path, err))?;
+    serde_json::from_str(&data)
+        .map_err(|err| Error::parse(path, Some(err.line()), &err.to_string()))
+}
+
+/// Format of an SBOM file, `None` if the file is not an SPDX or CycloneDX
+/// JSON document.
+pub fn detect(path: &str) -> Option<SbomFormat> {
+    if !path.ends_with(".json") {
+        return None;
+    }
+    let data = read_json(path).ok()?;
+    if data.get("spdxVersion").is_some() {
+        Some(SbomFormat::Spdx)
+    } else if data.get("bomFormat").and_then(|x| x.as_str()) == Some("CycloneDX") {
+        Some(SbomFormat::CycloneDx)
+    } else {
+        None
+    }
+}
+
+/// Source of a download location: plain URLs or the VCS form
+/// `git+<url>[@<commit>]` written by [`to_spdx`].
+fn parse_location(location: &str) -> Option<PackageSource> {
+    let location = location.trim();
+    if let Some(url) = location.strip_prefix("git+") {
+        let mut source = PackageSource::new(FetchMethod::Git, url);
+        if let Some((url, commit)) = url.rsplit_once('@') {
+            if commit.len() >= 7 && commit.chars().all(|x| x.is_ascii_hexdigit()) {
+                source.url = url.to_owned();
+                source.reference = Some(commit.to_owned());
+            }
+        }
+        return Some(source);
+    }
+    FetchMethod::guess(location).map(|method| PackageSource::new(method, location))
+}
+
+/// Text of a field, unless it's empty or an SPDX `NOASSERTION`/`NONE`.
+fn text(value: &Value, key: &str) -> Option<String> {
+    json::text(value, key)
+        .map(|x| x.trim().to_owned())
+        .filter(|x| !x.is_empty() && x != "NOASSERTION" && x != "NONE")
+}
+
+fn insert(result: &mut Packages, path: &str, package: Package) {
+    if result.contains_key(&package.name) {
+        warn!(
+            "{}: {} is described twice, the first one is used",
+            path, package.name
+        );
+        return;
+    }
+    result.insert(package.name.clone(), package);
+}
+
+/// Reads packages of an SPDX 2.x JSON document.
+pub struct SpdxReader {
+    path: String,
+}
+
+impl SpdxReader {
+    pub fn new(path: &str) -> SpdxReader {
+        SpdxReader {
+            path: path.to_owned(),
+        }
+    }
+}
+
+impl PackageReader for SpdxReader {
+    type Error = Error;
+    fn read(&mut self) -> Result<Packages> {
+        let data = read_json(&self.path)?;
+        let mut result = Packages::new();
+        for item in items(&data, "packages") {
+            let name = match text(item, "name") {
+                Some(name) => name,
+                None => continue,
+            };
+            let cpe_id = items(item, "externalRefs")
+                .find(|x| text(x, "referenceType").as_deref() == Some("cpe23Type"))
+                .and_then(|x| text(x, "referenceLocator"));
+            let package = Package {
+                name,
+                version: text(item, "versionInfo"),
+                sources: text(item, "downloadLocation")
+                    .and_then(|x| parse_location(&x))
+                    .into_iter()
+                    .collect(),
+                location: Some(self.path.clone()),
+                license: text(item, "licenseDeclared").or_else(|| text(item, "licenseConcluded")),
+                cpe_id,
+                ..Default::default()
+            };
+            insert(&mut result, &self.path, package);
+        }
+        debug!("read {} packages from {}", result.len(), self.path);
+        Ok(result)
+    }
+}
+
+/// Reads components of a CycloneDX 1.x JSON document, nested components
+/// included.
+pub struct CycloneDxReader {
+    path: String,
+}
+
+impl CycloneDxReader {
+    pub fn new(path: &str) -> CycloneDxReader {
+        CycloneDxReader {
+            path: path.to_owned(),
+        }
+    }
+
+    fn collect(&self, components: &Value, result: &mut Packages) {
+        for item in items(components, "components") {
+            self.collect(item, result);
+            let name = match text(item, "name") {
+                Some(name) => name,
+                None => continue,
+            };
+            let licenses: Vec<String> = items(item, "licenses")
+                .filter_map(|x| {
+                    text(x, "expression").or_else(|| {
+                        x.get("license")
+                            .and_then(|x| text(x, "id").or_else(|| text(x, "name")))
+                    })
+                })
+                .collect();
+            let sources = items(item, "externalReferences")
+                .filter(|x| {
+                    matches!(
+                        text(x, "type").as_deref(),
+                        Some("vcs") | Some("distribution")
+                    )
+                })
+                .filter_map(|x| text(x, "url"))
+                .filter_map(|x| parse_location(&x))
+                .collect();
+            let package = Package {
+                name,
+                version: text(item, "version"),
+                sources,
+                location: Some(self.path.clone()),
+                license: Some(licenses.join(" AND ")).filter(|x| !x.is_empty()),
+                cpe_id: text(item, "cpe"),
+                ..Default::default()
+            };
+            insert(result, &self.path, package);
+        }
+    }
+}
+
+impl PackageReader for CycloneDxReader {
+    type Error = Error;
+    fn read(&mut self) -> Result<Packages> {
+        let data = read_json(&self.path)?;
+        let mut result = Packages::new();
+        self.collect(&data, &mut result);
+        debug!("read {} packages from {}", result.len(), self.path);
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutils::TempDir;
+
+    fn packages() -> Packages {
+        let mut packages = Packages::new();
+        packages.insert(
+            "zlib".to_owned(),
+            Package {
+                name: "zlib".to_owned(),
+                version: Some("1.3".to_owned()),
+                sources: vec![PackageSource::new(
+                    FetchMethod::Https,
+                    "https://www.zlib.net/zlib-1.3.tar.xz",
+                )],
+                license: Some("Zlib".to_owned()),
+                cpe_id: Some("cpe:2.3:a:zlib:zlib:1.3:*:*:*:*:*:*:*".to_owned()),
+                ..Default::default()
+            },
+        );
+        packages.insert(
+            "app".to_owned(),
+            Package {
+                name: "app".to_owned(),
+                version: Some("v1.0".to_owned()),
+                sources: vec![PackageSource::new(
+                    FetchMethod::Git,
+                    "https://git.example.com/app.git",
+                )],
+                license: Some("PROPRIETARY".to_owned()),
+                ..Default::default()
+            },
+        );
+        packages
+    }
+
+    fn doc() -> Document {
+        Document {
+            name: "image".to_owned(),
+            tool: "brsbom-0.1.0".to_owned(),
+            created: 1700000000,
+        }
+    }
+
+    #[test]
+    fn licenses() {
+        assert_eq!(spdx_license("Zlib"), "Zlib");
+        assert_eq!(
+            spdx_license("GPL-2.0+ (programs), LGPL-2.1+ (libraries)"),
+            "GPL-2.0+ AND LGPL-2.1+"
+        );
+        assert_eq!(spdx_license("MIT or Apache-2.0"), "MIT OR Apache-2.0");
+        assert_eq!(
+            spdx_license("GPL-2.0, MIT or BSD-3-Clause"),
+            "GPL-2.0 AND (MIT OR BSD-3-Clause)"
+        );
+        assert_eq!(spdx_license("Public_domain"), "LicenseRef-Public-domain");
+        assert_eq!(spdx_license("Public Domain"), "LicenseRef-Public-Domain");
+        assert_eq!(spdx_license("PROPRIETARY"), "LicenseRef-PROPRIETARY");
+        assert_eq!(
+            spdx_license("GPL-2.0 with linking exception"),
+            "LicenseRef-GPL-2.0-with-linking-exception"
+        );
+        assert_eq!(
+            spdx_license("GPL-2.0+ with Classpath-exception-2.0"),
+            "GPL-2.0+ WITH Classpath-exception-2.0"
+        );
+        assert_eq!(
+            spdx_license("gpl-3.0-or-later and Public Domain"),
+            "GPL-3.0-or-later AND LicenseRef-Public-Domain"
+        );
+        assert_eq!(spdx_license(""), "");
+    }
+
+    #[test]
+    fn spdx() {
+        let mut commits = Commits::new();
+        commits.insert("app".to_owned(), "0123456789abcdef".to_owned());
+        let sbom = to_spdx(&packages(), &doc(), &commits);
+        assert_eq!(sbom["spdxVersion"], "SPDX-2.3");
+        assert_eq!(sbom["creationInfo"]["created"], "2023-11-14T22:13:20Z");
+
+        let items = sbom["packages"].as_array().unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0]["name"], "app");
+        assert_eq!(items[0]["versionInfo"], "v1.0");
+        assert_eq!(
+            items[0]["downloadLocation"],
+            "git+https://git.example.com/app.git@0123456789abcdef"
+        );
+        assert_eq!(items[0]["licenseDeclared"], "LicenseRef-PROPRIETARY");
+        let refs = sbom["hasExtractedLicensingInfos"].as_array().unwrap();
+        assert_eq!(refs.len(), 1);
+        assert_eq!(refs[0]["licenseId"], "LicenseRef-PROPRIETARY");
+        assert_eq!(refs[0]["name"], "PROPRIETARY");
+        assert_eq!(
+            items[1]["externalRefs"][0]["referenceLocator"],
+            "cpe:2.3:a:zlib:zlib:1.3:*:*:*:*:*:*:*"
+        );
+        assert_eq!(sbom["relationships"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn cyclonedx() {
+        let sbom = to_cyclonedx(&packages(), &doc(), &Commits::new());
+        assert_eq!(sbom["specVersion"], "1.5");
+        assert_eq!(sbom["metadata"]["tools"][0]["version"], "0.1.0");
+        assert!(sbom["serialNumber"]
+            .as_str()
+            .unwrap()
+            .starts_with("urn:uuid:"));
+
+        let components = sbom["components"].as_array().unwrap();
+        assert_eq!(components[0]["version"], "v1.0");
+        assert_eq!(components[0]["externalReferences"][0]["type"], "vcs");
+        assert_eq!(components[1]["licenses"][0]["expression"], "Zlib");
+        assert_eq!(
+            components[1]["cpe"],
+            "cpe:2.3:a:zlib:zlib:1.3:*:*:*:*:*:*:*"
+        );
+    }
+
+    fn roundtrip(format: SbomFormat) -> Packages {
+        let mut commits = Commits::new();
+        commits.insert("app".to_owned(), "0123456789abcdef".to_owned());
+        let dir = TempDir::new("sbom");
+        let path = dir
+            .join(format!("{:?}.json", format))
+            .to_string_lossy()
+            .to_string();
+        let sbom = export(format, &packages(), &doc(), &commits);
+        std::fs::write(&path, sbom.to_string()).unwrap();
+        assert_eq!(detect(&path), Some(format));
+        let result = match format {
+            SbomFormat::Spdx => SpdxReader::new(&path).read(),
+            SbomFormat::CycloneDx => CycloneDxReader::new(&path).read(),
+        };
+        result.unwrap()
+    }
+
+    #[test]
+    fn read() {
+        for format in [SbomFormat::Spdx, SbomFormat::CycloneDx] {
+            let packages = roundtrip(format);
+            assert_eq!(packages.len(), 2);
+
+            let zlib = &packages["zlib"];
+            assert_eq!(zlib.version.as_deref(), Some("1.3"));
+            assert_eq!(zlib.license.as_deref(), Some("Zlib"));
+            assert_eq!(
+                zlib.cpe_id.as_deref(),
+                Some("cpe:2.3:a:zlib:zlib:1.3:*:*:*:*:*:*:*")
+            );
+            assert_eq!(
+                zlib.get_source(FetchMethod::Https).unwrap().url,
+                "https://www.zlib.net/zlib-1.3.tar.xz"
+            );
+
+            let app = packages["app"].get_source(FetchMethod::Git).unwrap();
+            assert_eq!(app.url, "https://git.example.com/app.git");
+            assert_eq!(app.reference.as_deref(), Some("0123456789abcdef"));
+        }
+    }
+
+    #[test]
+    fn locations() {
+        let source = parse_location("git+ssh://git@example.com/app.git").unwrap();
+        assert_eq!(source.url, "ssh://git@example.com/app.git");
+        assert_eq!(source.reference, None);
+        assert!(parse_location("NOASSERTION").is_none());
+    }
+
+    #[test]
+    fn ids() {
+        let uuid = uuid();
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+        assert_eq!(spdx_id("host-gcc_final"), "SPDXRef-Package-host-gcc-final");
+    }
+}
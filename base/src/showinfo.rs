@@ -0,0 +1,176 @@
+use crate::error::{Error, Result};
+use crate::package::PackageReader;
+use crate::package::{Package, PackageSource, PackageSources, Packages};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::str::FromStr;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Downloads {
+    source: String,
+    uris: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ShowInfoPackage {
+    name: Option<String>,
+    version: Option<String>,
+    downloads: Option<Vec<Downloads>>,
+    licenses: Option<String>,
+    #[serde(rename = "cpe-id")]
+    cpe_id: Option<String>,
+    ignore_cves: Option<Vec<String>>,
+    dependencies: Option<Vec<String>>,
+}
+
+type ShowInfoPackages = HashMap<String, ShowInfoPackage>;
+
+fn make_sources(downloads: &Vec<Downloads>) -> PackageSources {
+    let mut result = PackageSources::new();
+    for download in downloads {
+        for uri in &download.uris {
+            match PackageSource::from_str(uri) {
+                Ok(source) => result.push(source),
+                Err(err) => warn!("skip {}: {}", uri, err),
+            }
+        }
+    }
+    result
+}
+
+fn make_pkgino(input: &ShowInfoPackage) -> Option<Package> {
+    match input {
+        ShowInfoPackage {
+            name: Some(_),
+            version: Some(_),
+            downloads: Some(downloads),
+            ..
+        } if !downloads.is_empty() => Some(make_package(input)),
+        _ => None,
+    }
+}
+
+fn make_package(input: &ShowInfoPackage) -> Package {
+    Package {
+        name: input.name.clone().unwrap_or_default(),
+        version: input.version.clone(),
+        sources: input
+            .downloads
+            .as_ref()
+            .map(make_sources)
+            .unwrap_or_default(),
+        location: None,
+        license: input.licenses.clone(),
+        cpe_id: input.cpe_id.clone(),
+        ignore_cves: input.ignore_cves.clone().unwrap_or_default(),
+        dependencies: input.dependencies.clone().unwrap_or_default(),
+        ..Default::default()
+    }
+}
+
+fn convert(input: &ShowInfoPackages, all: bool) -> Packages {
+    let mut output = Packages::new();
+    for (k, v) in input {
+        let package = if all {
+            let mut package = make_package(v);
+            if package.name.is_empty() {
+                package.name = k.clone();
+            }
+            Some(package)
+        } else {
+            make_pkgino(v)
+        };
+        package.map(|x| output.insert(k.clone(), x));
+    }
+
+    output
+}
+
+pub struct ReportReader {
+    path: String,
+    all: bool,
+}
+
+impl ReportReader {
+    pub fn new(path: &str) -> ReportReader {
+        ReportReader {
+            path: path.to_owned(),
+            all: false,
+        }
+    }
+
+    /// Keep entries without a version or downloads too, like virtual
+    /// packages, so that dependency chains through them are not broken.
+    pub fn all_packages(mut self, all: bool) -> ReportReader {
+        self.all = all;
+        self
+    }
+}
+
+impl PackageReader for ReportReader {
+    type Error = Error;
+    fn read(&mut self) -> Result<Packages> {
+        let mut data = String::new();
+        File::open(&self.path)
+            .and_then(|mut file| file.read_to_string(&mut data))
+            .map_err(|err| Error::io(&self.path, err))?;
+        let data: ShowInfoPackages = serde_json::from_str(&data)
+            .map_err(|err| Error::parse(&self.path, Some(err.line()), &err.to_string()))?;
+        let res = convert(&data, self.all);
+
+        debug!("read {} packages from {}", res.len(), self.path);
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::graph::Graph;
+    use crate::testutils::TempDir;
+
+    const SHOW_INFO: &str = r#"{
+  "app": {
+    "name": "app",
+    "version": "1.0",
+    "downloads": [{"source": "app-1.0.tar.gz", "uris": ["https+https://example.com/app"]}],
+    "dependencies": ["jpeg"]
+  },
+  "jpeg": {
+    "type": "target",
+    "virtual": true,
+    "dependencies": ["libjpeg"]
+  },
+  "libjpeg": {
+    "name": "libjpeg",
+    "version": "9f",
+    "downloads": [{"source": "jpegsrc.v9f.tar.gz", "uris": ["http+http://www.ijg.org/files"]}],
+    "dependencies": []
+  }
+}"#;
+
+    #[test]
+    fn virtual_packages() {
+        let dir = TempDir::new("showinfo");
+        let path = dir.join("show-info.json");
+        std::fs::write(&path, SHOW_INFO).unwrap();
+        let path = path.to_str().unwrap();
+
+        let packages = ReportReader::new(path).read().unwrap();
+        assert_eq!(packages.len(), 2);
+        assert!(!packages.contains_key("jpeg"));
+
+        let packages = ReportReader::new(path).all_packages(true).read().unwrap();
+        assert_eq!(packages.len(), 3);
+        assert_eq!(packages["jpeg"].name, "jpeg");
+        assert_eq!(packages["jpeg"].version, None);
+        let graph = Graph::build(&packages);
+        assert_eq!(
+            graph.why("libjpeg").paths,
+            vec![vec!["app", "jpeg", "libjpeg"]]
+        );
+    }
+}
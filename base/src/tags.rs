@@ -0,0 +1,175 @@
+use crate::error::{Error, Result};
+use crate::gitworkspace::TagInfo;
+use crate::pattern::Pattern;
+use semver::{Version, VersionReq};
+use std::cmp::Ordering;
+
+/// Extract a semantic version from a tag name: `v2.3.1`, `release-2.3`,
+/// `2.3.1-rc1`, `2.3.1+build5` and so on. Missing minor/patch numbers are
+/// treated as zeros.
+pub fn parse_version(tag: &str) -> Option<Version> {
+    let from = tag.find(|symb: char| symb.is_ascii_digit())?;
+    let (tag, build) = match tag[from..].split_once('+') {
+        Some((tag, build)) => (tag, Some(build)),
+        None => (&tag[from..], None),
+    };
+    let (core, pre) = match tag.split_once('-') {
+        Some((core, pre)) => (core, Some(pre)),
+        None => (tag, None),
+    };
+
+    let numbers = core.replace('_', ".");
+    let mut numbers: Vec<&str> = numbers.split('.').collect();
+    if numbers.len() > 3 || numbers.iter().any(|x| x.parse::<u64>().is_err()) {
+        return None;
+    }
+    numbers.resize(3, "0");
+
+    let mut version = numbers.join(".");
+    if let Some(pre) = pre {
+        version.push('-');
+        version.push_str(pre);
+    }
+    if let Some(build) = build {
+        version.push('+');
+        version.push_str(build);
+    }
+    Version::parse(&version).ok()
+}
+
+pub fn parse_requirement(requirement: &str) -> Result<VersionReq> {
+    VersionReq::parse(requirement).map_err(|err| Error::parse(requirement, None, &err.to_string()))
+}
+
+/// Rules to choose a tag among all tags of a repository.
+#[derive(Debug, Clone, Default)]
+pub struct TagFilter {
+    pub pattern: Option<Pattern>,
+    pub exclude: Option<Pattern>,
+    pub requirement: Option<VersionReq>,
+}
+
+/// Tags without a version are older than any versioned one, so the order
+/// stays transitive.
+fn compare(a: &TagInfo, b: &TagInfo) -> Ordering {
+    match (parse_version(&a.name), parse_version(&b.name)) {
+        (Some(va), Some(vb)) => va.cmp(&vb).then(a.time.cmp(&b.time)),
+        (Some(_), None) => Ordering::Greater,
+        (None, Some(_)) => Ordering::Less,
+        (None, None) => a.time.cmp(&b.time),
+    }
+}
+
+impl TagFilter {
+    pub fn is_empty(&self) -> bool {
+        self.pattern.is_none() && self.exclude.is_none() && self.requirement.is_none()
+    }
+
+    pub fn is_match(&self, name: &str) -> bool {
+        if let Some(pattern) = &self.pattern {
+            if !pattern.is_match(name) {
+                return false;
+            }
+        }
+
+        if let Some(exclude) = &self.exclude {
+            if exclude.is_match(name) {
+                return false;
+            }
+        }
+
+        if let Some(requirement) = &self.requirement {
+            return parse_version(name).is_some_and(|ver| requirement.matches(&ver));
+        }
+        true
+    }
+
+    /// The newest matching tag: ordered by version, then by date; tags
+    /// without a version go before versioned ones.
+    pub fn select_latest<'a>(&self, tags: &'a [TagInfo]) -> Option<&'a TagInfo> {
+        tags.iter()
+            .filter(|tag| self.is_match(&tag.name))
+            .max_by(|a, b| compare(a, b))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use git2::Oid;
+
+    fn tag(name: &str, time: i64) -> TagInfo {
+        TagInfo {
+            name: name.to_owned(),
+            commit: Oid::zero(),
+            time,
+        }
+    }
+
+    #[test]
+    fn versions() {
+        assert_eq!(parse_version("v2.3.1"), Version::parse("2.3.1").ok());
+        assert_eq!(parse_version("release-2.3"), Version::parse("2.3.0").ok());
+        assert_eq!(parse_version("v1.0-rc1"), Version::parse("1.0.0-rc1").ok());
+        assert_eq!(parse_version("uuu_1.4.191"), Version::parse("1.4.191").ok());
+        assert_eq!(
+            parse_version("v1.2.3+foo"),
+            Version::parse("1.2.3+foo").ok()
+        );
+        assert_eq!(
+            parse_version("v1.2.3-rc1+build.5"),
+            Version::parse("1.2.3-rc1+build.5").ok()
+        );
+        assert_eq!(parse_version("OpenSSL_1_1_1j"), None);
+        assert_eq!(parse_version("latest"), None);
+    }
+
+    #[test]
+    fn select() {
+        let tags = vec![
+            tag("v2.3.0", 10),
+            tag("v2.10.0", 5),
+            tag("v3.0.0-rc1", 30),
+            tag("v3.0.0", 40),
+            tag("nightly", 50),
+        ];
+
+        let filter = TagFilter::default();
+        assert_eq!(filter.select_latest(&tags).unwrap().name, "v3.0.0");
+
+        let mixed = vec![tag("nightly", 50), tag("v1.0", 60), tag("v2.0", 10)];
+        assert_eq!(filter.select_latest(&mixed).unwrap().name, "v2.0");
+        let mut sorted = mixed.clone();
+        sorted.sort_by(compare);
+        let names: Vec<&str> = sorted.iter().map(|x| x.name.as_str()).collect();
+        assert_eq!(names, ["nightly", "v1.0", "v2.0"]);
+
+        let unversioned = vec![tag("nightly", 50), tag("latest", 60)];
+        assert_eq!(filter.select_latest(&unversioned).unwrap().name, "latest");
+
+        let filter = TagFilter {
+            pattern: Some(Pattern::new("v*").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(filter.select_latest(&tags).unwrap().name, "v3.0.0");
+
+        let filter = TagFilter {
+            requirement: Some(parse_requirement("^2.3").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(filter.select_latest(&tags).unwrap().name, "v2.10.0");
+
+        let filter = TagFilter {
+            pattern: Some(Pattern::new("v*").unwrap()),
+            exclude: Some(Pattern::new("*-rc*").unwrap()),
+            requirement: Some(parse_requirement("<3").unwrap()),
+        };
+        assert_eq!(filter.select_latest(&tags).unwrap().name, "v2.10.0");
+
+        let filter = TagFilter {
+            requirement: Some(parse_requirement(">=4").unwrap()),
+            ..Default::default()
+        };
+        assert!(filter.select_latest(&tags).is_none());
+    }
+}
@@ -13,7 +13,57 @@ pub fn get_home_dir() -> Option<String> {
 pub fn get_default_ssh_key() -> Option<String> {
     if let Some(home) = get_home_dir() {
         let path: PathBuf = [&home, ".ssh", "id_rsa"].iter().collect();
-        return Some(path.to_str().unwrap().to_owned());
+        return path.to_str().map(|x| x.to_owned());
     }
     None
 }
+
+/// UTC calendar date (year, month, day) from seconds since epoch.
+pub fn civil_date(seconds: i64) -> (i64, i64, i64) {
+    // civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let days = seconds.div_euclid(86400) + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// UTC time as `YYYY-MM-DDTHH:MM:SSZ` from seconds since epoch.
+pub fn format_timestamp(seconds: i64) -> String {
+    let (year, month, day) = civil_date(seconds);
+    let time = seconds.rem_euclid(86400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// Seconds since epoch.
+pub fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|x| x.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(civil_date(951782400), (2000, 2, 29));
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_timestamp(1700000000), "2023-11-14T22:13:20Z");
+    }
+}
@@ -0,0 +1,83 @@
+use base::Result;
+use base::{cve, mkfile, package::Packages, reader, report};
+
+use log::{error, info};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "brcve",
+    about = "check a BR package set for known vulnerabilities using local NVD JSON feeds"
+)]
+struct Options {
+    #[structopt(
+        short = "i",
+        long = "input",
+        default_value = "show-info.json",
+        help = "path to the show-info JSON, SBOM, legal-info, mk(s) or defconfig"
+    )]
+    input: String,
+
+    #[structopt(
+        short = "d",
+        long = "nvd",
+        help = "NVD JSON feed (.json or .json.gz) or a directory with feeds"
+    )]
+    nvd: String,
+
+    #[structopt(
+        long = "ignore-from",
+        help = "mk file(s) to read <PKG>_IGNORE_CVES from, for inputs without them"
+    )]
+    ignore_from: Option<String>,
+
+    #[structopt(
+        long = "duplicates",
+        default_value = "first",
+        help = "package defined in several mk files: use the first or the last one in path order, or stop with an error"
+    )]
+    duplicates: mkfile::Precedence,
+
+    #[structopt(short = "o", long = "output", help = "output file to report")]
+    output: Option<String>,
+}
+
+fn read_packages(opts: &Options) -> Result<Packages> {
+    let mut packages = reader::guess(&opts.input, opts.duplicates)?.read()?;
+    if let Some(path) = &opts.ignore_from {
+        let other = reader::guess(path, opts.duplicates)?.read()?;
+        cve::merge_ignored(&mut packages, &other);
+    }
+    Ok(packages)
+}
+
+fn run(opts: Options) -> Result<()> {
+    let packages = read_packages(&opts)?;
+    let unchecked = packages.values().filter(|x| x.cpe_id.is_none()).count();
+    if unchecked > 0 {
+        info!("{} package(s) without CPE id are not checked", unchecked);
+    }
+
+    let db = cve::CveDb::load(&opts.nvd)?;
+    let vulnerabilities = db.check(&packages);
+    report::print_vulnerabilities(&packages, &vulnerabilities);
+    if let Some(file) = &opts.output {
+        report::write_vulnerabilities(file, &packages, &vulnerabilities)?;
+    }
+    println!(
+        "{} vulnerable package(s) of {}",
+        vulnerabilities.len(),
+        packages.len()
+    );
+    Ok(())
+}
+
+fn main() {
+    env_logger::init();
+
+    let opts = Options::from_args();
+    if let Err(err) = run(opts) {
+        error!("check fails:{}", err);
+        std::process::exit(2)
+    }
+}
@@ -1,9 +1,11 @@
-mod show_info;
-
-use base::{diffs, githistory, gitworkspace, mkfile, package::PackageReader, report, utils};
+use base::{
+    buildtime, configdiff, cve, defconfig, diffs, filelist, githistory, gittree, gitworkspace,
+    legalinfo, mkfile, package::Packages, pattern, reader, report, utils,
+};
+use base::{Error, Result};
 
 use log::{debug, error, info};
-use std::io::Error;
+use std::path::Path;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -16,7 +18,7 @@ struct Options {
         short = "f",
         long = "first",
         default_value = "first.json",
-        help = "path to the first JSON,mk(s)"
+        help = "path to the first JSON, SBOM, mk(s), defconfig or .config"
     )]
     path_first: String,
 
@@ -24,15 +26,48 @@ struct Options {
         short = "s",
         long = "second",
         default_value = "second.json",
-        help = "path to the second JSON,mk(s)"
+        help = "path to the second JSON, SBOM, mk(s), defconfig or .config"
     )]
     path_second: String,
 
+    #[structopt(
+        long = "first-rev",
+        help = "read the first package set from mk files of this revision of the repository"
+    )]
+    rev_first: Option<String>,
+
+    #[structopt(
+        long = "second-rev",
+        help = "read the second package set from mk files of this revision of the repository"
+    )]
+    rev_second: Option<String>,
+
+    #[structopt(
+        long = "repo",
+        default_value = ".",
+        help = "path to the Buildroot (or br2-external) git repository for --first-rev/--second-rev"
+    )]
+    repo: String,
+
+    #[structopt(
+        long = "package-dir",
+        default_value = "package",
+        help = "directory with mk files inside the repository"
+    )]
+    package_dir: String,
+
+    #[structopt(
+        long = "duplicates",
+        default_value = "first",
+        help = "package defined in several mk files: use the first or the last one in path order, or stop with an error"
+    )]
+    duplicates: mkfile::Precedence,
+
     #[structopt(
         short = "m",
         long = "mode",
         default_value = "fast",
-        help = "type of a report [fast,full]"
+        help = "type of a report [fast,full,license,size,files]"
     )]
     mode: String,
 
@@ -69,24 +104,180 @@ struct Options {
     )]
     short_history: bool,
 
+    #[structopt(
+        long = "dl-dir",
+        help = "BR download directory: its git caches are used instead of cloning"
+    )]
+    dl_dir: Option<String>,
+
+    #[structopt(
+        long = "cve-db",
+        help = "NVD JSON feed or a directory with feeds to report CVEs fixed and introduced by the second set"
+    )]
+    cve_db: Option<String>,
+
+    #[structopt(
+        long = "path",
+        help = "files mode: only report installed files matching the glob (or /regex/), can be repeated"
+    )]
+    paths: Vec<String>,
+
+    #[structopt(
+        long = "first-build-time",
+        help = "build-time.log of the first set, found in output/build next to the input by default"
+    )]
+    build_time_first: Option<String>,
+
+    #[structopt(
+        long = "second-build-time",
+        help = "build-time.log of the second set, found in output/build next to the input by default"
+    )]
+    build_time_second: Option<String>,
+
     #[structopt(short = "o", long = "output", help = "output file to report")]
     output: Option<String>,
 }
 
-fn guess_reader(filename: &str) -> Result<Box<dyn PackageReader<Error = Error>>, Error> {
-    if filename.ends_with(".json") {
-        info!("use ShowInfo reader for {}", filename);
-        Ok(Box::new(show_info::ReportReader::new(filename)))
-    } else if filename.ends_with(".mk") {
-        info!("use MkFile reader for {}", filename);
-        Ok(Box::new(mkfile::MkFileReader::new(filename)))
-    } else if std::fs::read_dir(filename).is_ok() {
-        info!("use default dir. reader for {}", filename);
-        Ok(Box::new(mkfile::MkFileDirReader::new(filename)))
-    } else {
-        info!("use default file reader for {}", filename);
-        Ok(Box::new(show_info::ReportReader::new(filename)))
+/// Read packages and report the ones defined more than once.
+fn read_with(mut reader: reader::BoxedReader) -> Result<Packages> {
+    let packages = reader.read()?;
+    report::print_duplicates(reader.duplicates());
+    Ok(packages)
+}
+
+fn read_packages(filename: &str, precedence: mkfile::Precedence) -> Result<Packages> {
+    read_with(reader::guess(filename, precedence)?)
+}
+
+/// A full `.config` has no package versions: compared with anything, it only
+/// takes part in the config diff.
+fn is_full_config(path: &str, rev: &Option<String>) -> bool {
+    rev.is_none() && configdiff::is_config(path) && !defconfig::is_defconfig(path)
+}
+
+fn read_revision(opts: &Options, rev: &str) -> Result<Packages> {
+    info!("use git tree reader for {} at {}", opts.repo, rev);
+    read_with(Box::new(
+        gittree::GitTreeReader::new(&opts.repo, rev)
+            .subdir(&opts.package_dir)
+            .precedence(opts.duplicates),
+    ))
+}
+
+fn read_config_diffs(first: &str, second: &str) -> Result<configdiff::ConfigDiffs> {
+    match (
+        configdiff::find_config(first),
+        configdiff::find_config(second),
+    ) {
+        (Some(first), Some(second)) => {
+            info!("compare {} and {}", first, second);
+            let first = configdiff::Config::read(&first)?;
+            let second = configdiff::Config::read(&second)?;
+            Ok(configdiff::build(&first, &second))
+        }
+        _ => Ok(configdiff::ConfigDiffs::new()),
+    }
+}
+
+fn read_build_time_diff(
+    opts: &Options,
+    first: &Packages,
+    second: &Packages,
+) -> Result<Option<buildtime::BuildTimeDiff>> {
+    let log = |log: &Option<String>, path: &str, rev: &Option<String>| match (log, rev) {
+        (Some(log), _) => Some(log.clone()),
+        (None, None) => buildtime::find_log(path),
+        _ => None,
+    };
+    match (
+        log(&opts.build_time_first, &opts.path_first, &opts.rev_first),
+        log(&opts.build_time_second, &opts.path_second, &opts.rev_second),
+    ) {
+        (Some(first_log), Some(second_log)) => {
+            let same = std::fs::canonicalize(&first_log)
+                .is_ok_and(|x| std::fs::canonicalize(&second_log).is_ok_and(|y| x == y));
+            if same {
+                info!("{} is the log of both inputs, skip build times", first_log);
+                return Ok(None);
+            }
+            info!("compare build times of {} and {}", first_log, second_log);
+            let diff = buildtime::BuildTimeDiff::build(
+                &buildtime::BuildTimes::read(&first_log)?,
+                &buildtime::BuildTimes::read(&second_log)?,
+                buildtime::MIN_SECONDS,
+                buildtime::MIN_PERCENT,
+            );
+            Ok(Some(diff.with_versions(first, second)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// `build/packages-file-list.txt` of the output an input is in.
+fn find_file_list(path: &str) -> Result<String> {
+    filelist::find_file_list(path)
+        .ok_or_else(|| Error::missing_object(path, filelist::FILE_LIST_NAME))
+}
+
+/// Packages of a size or files mode input. Output directories and file
+/// lists have none, only show-info JSON and other package set inputs do.
+fn read_output_packages(opts: &Options, path: &str, rev: &Option<String>) -> Result<Packages> {
+    let is_list = Path::new(path)
+        .file_name()
+        .is_some_and(|x| x == filelist::FILE_LIST_NAME);
+    match rev {
+        Some(rev) => read_revision(opts, rev),
+        None if is_list || Path::new(path).is_dir() => Ok(Packages::new()),
+        None => read_packages(path, opts.duplicates),
+    }
+}
+
+/// Sizes of the target directory of an output.
+fn read_sizes(list: &str) -> Result<filelist::PackageSizes> {
+    info!("read target sizes from {}", list);
+    filelist::read_sizes(list, None)
+}
+
+fn run_size(opts: &Options) -> Result<()> {
+    let first_list = find_file_list(&opts.path_first)?;
+    let second_list = find_file_list(&opts.path_second)?;
+    let first = read_output_packages(opts, &opts.path_first, &opts.rev_first)?;
+    let second = read_output_packages(opts, &opts.path_second, &opts.rev_second)?;
+    let sizes = filelist::SizeDiff::build(&read_sizes(&first_list)?, &read_sizes(&second_list)?, 0)
+        .with_versions(&first, &second);
+    let diffs = diffs::build(&first, &second);
+    report::print_diffs(&diffs);
+    report::print_size_diff(&sizes);
+    if let Some(file) = &opts.output {
+        report::write_diffs(file, &diffs, &configdiff::ConfigDiffs::new())?;
+        report::append_size_diff(file, &sizes)?;
+    }
+    Ok(())
+}
+
+fn run_files(opts: &Options) -> Result<()> {
+    let patterns = opts
+        .paths
+        .iter()
+        .map(|x| pattern::Pattern::new(x))
+        .collect::<Result<Vec<pattern::Pattern>>>()?;
+    let first_list = find_file_list(&opts.path_first)?;
+    let second_list = find_file_list(&opts.path_second)?;
+    let first = read_output_packages(opts, &opts.path_first, &opts.rev_first)?;
+    let second = read_output_packages(opts, &opts.path_second, &opts.rev_second)?;
+    let files = filelist::diff_files(
+        &filelist::FileList::read(&first_list)?,
+        &filelist::FileList::read(&second_list)?,
+        &patterns,
+    );
+    let diffs = diffs::build(&first, &second);
+    report::print_diffs(&diffs);
+    report::print_file_diffs(&files);
+    if let Some(file) = &opts.output {
+        report::write_diffs(file, &diffs, &configdiff::ConfigDiffs::new())?;
+        report::append_file_diffs(file, &files)?;
     }
+    Ok(())
 }
 
 fn with_default_key(key: &String, default: Option<String>) -> String {
@@ -98,22 +289,91 @@ fn with_default_key(key: &String, default: Option<String>) -> String {
     key.to_owned()
 }
 
-fn run(opts: Options) -> Result<(), Error> {
-    let first = guess_reader(&opts.path_first)?.read()?;
-    let second = guess_reader(&opts.path_second)?.read()?;
+fn run(opts: Options) -> Result<()> {
+    if opts.mode == "size" {
+        return run_size(&opts);
+    }
+    if opts.mode == "files" {
+        return run_files(&opts);
+    }
+    let configs_only = is_full_config(&opts.path_first, &opts.rev_first)
+        || is_full_config(&opts.path_second, &opts.rev_second);
+    let first = match &opts.rev_first {
+        _ if configs_only => Packages::new(),
+        Some(rev) => read_revision(&opts, rev)?,
+        None => read_packages(&opts.path_first, opts.duplicates)?,
+    };
+    let second = match &opts.rev_second {
+        _ if configs_only => Packages::new(),
+        Some(rev) => read_revision(&opts, rev)?,
+        None => read_packages(&opts.path_second, opts.duplicates)?,
+    };
+    let config_diffs = match (&opts.rev_first, &opts.rev_second) {
+        (None, None) => read_config_diffs(&opts.path_first, &opts.path_second)?,
+        _ => configdiff::ConfigDiffs::new(),
+    };
+    if opts.mode == "license" {
+        let licenses = legalinfo::LicenseReport::build(&first, &second);
+        report::print_license_report(&licenses);
+        if let Some(file) = &opts.output {
+            std::fs::File::create(file).map_err(|err| Error::io(file, err))?;
+            report::append_license_report(file, &licenses)?;
+        }
+        return Ok(());
+    }
+
+    let licenses = match (&opts.rev_first, &opts.rev_second) {
+        (None, None)
+            if legalinfo::is_legal_info(&opts.path_first)
+                && legalinfo::is_legal_info(&opts.path_second) =>
+        {
+            Some(legalinfo::LicenseReport::build(&first, &second))
+        }
+        _ => None,
+    };
+    let cves = match &opts.cve_db {
+        Some(path) => {
+            let db = cve::CveDb::load(path)?;
+            Some(cve::CveDiff::build(&db.check(&first), &db.check(&second)))
+        }
+        None => None,
+    };
+    let build_times = read_build_time_diff(&opts, &first, &second)?;
     let mut diffs = diffs::build(&first, &second);
+    let mut failures = Vec::new();
     if opts.mode == "full" {
         debug!("try to build full history for {} package(s)", diffs.len());
         let mut wsopts = gitworkspace::Options::new(&opts.path_workdir);
         wsopts.key = with_default_key(&opts.key, utils::get_default_ssh_key());
         wsopts.clean_workspace = opts.clean;
         wsopts.short_history = opts.short_history;
-        githistory::append(&mut diffs, &wsopts)?;
+        wsopts.dl_dir = opts.dl_dir.clone();
+        failures = githistory::append(&mut diffs, &wsopts)?;
     };
     report::print_diffs(&diffs);
+    report::print_config_diffs(&config_diffs);
+    if let Some(licenses) = &licenses {
+        report::print_license_report(licenses);
+    }
+    if let Some(cves) = &cves {
+        report::print_cve_diff(cves);
+    }
+    if let Some(build_times) = &build_times {
+        report::print_build_time_diff(build_times);
+    }
     if let Some(file) = opts.output {
-        report::write_diffs(&file, &diffs)?;
+        report::write_diffs(&file, &diffs, &config_diffs)?;
+        if let Some(licenses) = &licenses {
+            report::append_license_report(&file, licenses)?;
+        }
+        if let Some(cves) = &cves {
+            report::append_cve_diff(&file, cves)?;
+        }
+        if let Some(build_times) = &build_times {
+            report::append_build_time_diff(&file, build_times)?;
+        }
     }
+    report::print_failures(&failures);
     Ok(())
 }
 
@@ -126,7 +386,7 @@ fn main() {
             println!("Done")
         }
         Err(err) => {
-            error!("diff fails:{:?}", err)
+            error!("diff fails:{}", err)
         }
     }
 }
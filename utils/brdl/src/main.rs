@@ -0,0 +1,108 @@
+use base::Result;
+use base::{dldir, mkfile, package::Packages, reader};
+
+use log::error;
+use std::path::Path;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "brdl",
+    about = "inventory a BR download directory and verify it against a package set"
+)]
+struct Options {
+    #[structopt(
+        short = "d",
+        long = "dl-dir",
+        default_value = "dl",
+        help = "path to the download directory"
+    )]
+    dl_dir: String,
+
+    #[structopt(
+        short = "i",
+        long = "input",
+        help = "path to the mk(s), show-info JSON, SBOM, legal-info or defconfig to verify against; without it the content is listed"
+    )]
+    input: Option<String>,
+
+    #[structopt(
+        short = "t",
+        long = "tree",
+        help = "Buildroot tree and br2-external trees, separated by colons, to find hash files of packages read without mk files"
+    )]
+    tree: Option<String>,
+
+    #[structopt(
+        short = "a",
+        long = "all",
+        parse(try_from_str),
+        default_value = "false",
+        help = "print valid files too"
+    )]
+    all: bool,
+
+    #[structopt(
+        long = "duplicates",
+        default_value = "first",
+        help = "package defined in several mk files: use the first or the last one in path order, or stop with an error"
+    )]
+    duplicates: mkfile::Precedence,
+}
+
+fn list(dl: &Path) -> Result<()> {
+    for (name, package) in dldir::inventory(dl)? {
+        println!(
+            "{}: {} file(s){}",
+            name,
+            package.files.len(),
+            if package.git { ", git cache" } else { "" }
+        );
+        for file in &package.files {
+            println!("      {}", file);
+        }
+    }
+    Ok(())
+}
+
+/// Returns false if files are missing, corrupt, extra or unchecked.
+fn verify(dl: &Path, input: &str, opts: &Options) -> Result<bool> {
+    let packages: Packages = reader::guess(input, opts.duplicates)?.read()?;
+    let trees: Vec<&str> = opts
+        .tree
+        .iter()
+        .flat_map(|x| x.split(':'))
+        .filter(|x| !x.is_empty())
+        .collect();
+    let checks = dldir::verify(dl, &packages, &dldir::index_hash_dirs(&trees))?;
+    let mut clean = true;
+    for check in &checks {
+        if opts.all || !check.is_ok() {
+            println!("{}", check);
+        }
+        clean &= check.is_ok();
+    }
+    Ok(clean)
+}
+
+fn run(opts: Options) -> Result<bool> {
+    let dl = Path::new(&opts.dl_dir);
+    match &opts.input {
+        Some(input) => verify(dl, input, &opts),
+        None => list(dl).map(|_| true),
+    }
+}
+
+fn main() {
+    env_logger::init();
+
+    let opts = Options::from_args();
+    match run(opts) {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(err) => {
+            error!("check fails:{}", err);
+            std::process::exit(2)
+        }
+    }
+}
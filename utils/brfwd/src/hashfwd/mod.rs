@@ -1,44 +1,206 @@
-use base::gitworkspace;
-use base::{gitworkspace::GitWorkspace, package::Package};
+use crate::policy::NameSet;
+use crate::summary::SkipReason;
+use crate::template;
+use base::gitworkspace::{self, RefKind};
+use base::{gitworkspace::GitWorkspace, package::Package, tags::TagFilter};
+use base::{Error, Result};
+use git2::{DescribeFormatOptions, DescribeOptions, Object, Oid, Repository};
 
-use log::info;
-use std::collections::HashSet;
+use log::{info, warn};
 use std::fs::{File, OpenOptions};
-use std::io::Error;
 use std::io::{Read, Write};
 
 pub struct CheckPackageParameters {
-    pub allow: HashSet<String>,
-    pub deny: HashSet<String>,
+    pub allow: NameSet,
+    pub deny: NameSet,
 }
 
-pub fn check_package(package: &Package, params: &CheckPackageParameters) -> bool {
+pub fn check_package(
+    package: &Package,
+    params: &CheckPackageParameters,
+) -> std::result::Result<(), SkipReason> {
     let name = &package.name;
     if !params.allow.is_empty() && !params.allow.contains(name) {
         info!("{} package not in allowlist", name);
-        return false;
+        return Err(SkipReason::NotAllowed);
     }
 
     if params.deny.contains(name) {
         info!("{} package in denylist", name);
-        return false;
+        return Err(SkipReason::Denied);
     }
 
     if package.get_git_source().is_none() {
         info!("{} not a git package", name);
-        return false;
+        return Err(SkipReason::NotGit);
     }
-    true
+    Ok(())
+}
+
+/// How to spell a version written into mk files. Without a template, the
+/// version is a hash (or a tag name) and a nonzero `abbrev` switches to the
+/// long `git describe` format.
+#[derive(Debug, Clone, Default)]
+pub struct VersionFormat {
+    pub abbrev: u32,
+    pub template: Option<String>,
+}
+
+/// A version to write into mk files and the commit it was rendered from.
+#[derive(Debug, Clone)]
+pub struct NewVersion {
+    pub version: String,
+    pub commit: Oid,
+}
+
+const TAG_PLACEHOLDERS: [&str; 4] = ["{tag}", "{tag_stripped}", "{distance}", "{describe}"];
+
+fn describe(url: &str, object: &Object, abbrev: Option<u32>, long: bool) -> Result<String> {
+    gitworkspace::get_object_abbrev(object, abbrev, long)
+        .ok_or_else(|| Error::missing_object(url, &format!("description of {}", object.id())))
+}
+
+/// Nearest tag reachable from the object (lightweight tags included) and the
+/// number of commits since it.
+fn nearest_tag(object: &Object) -> Option<(String, usize)> {
+    let mut opts = DescribeOptions::new();
+    opts.describe_tags();
+    let mut fopts = DescribeFormatOptions::new();
+    fopts.always_use_long_format(true);
+    let described = object.describe(&opts).ok()?.format(Some(&fopts)).ok()?;
+
+    let mut parts = described.rsplitn(3, '-');
+    let _hash = parts.next()?;
+    let distance = parts.next()?.parse().ok()?;
+    let tag = parts.next()?;
+    Some((tag.to_owned(), distance))
+}
+
+/// Render the version template. `tag` is the tag the object was selected
+/// by, if any; otherwise the nearest reachable tag is used.
+fn render_version(
+    url: &str,
+    object: &Object,
+    tag: Option<&str>,
+    format: &VersionFormat,
+    template: &str,
+) -> Result<String> {
+    let commit = object
+        .peel_to_commit()
+        .map_err(|err| Error::git(url, err))?;
+    let hash = commit.id().to_string();
+    let short_hash = if format.abbrev > 0 {
+        hash.chars().take(format.abbrev as usize).collect()
+    } else {
+        commit
+            .as_object()
+            .short_id()
+            .ok()
+            .and_then(|id| id.as_str().map(|x| x.to_owned()))
+            .unwrap_or_else(|| hash.chars().take(7).collect())
+    };
+    let date = template::date(commit.time().seconds());
+
+    let mut values = vec![
+        ("hash", hash.clone()),
+        ("short_hash", short_hash.clone()),
+        ("date", date),
+    ];
+
+    let described = match tag {
+        Some(tag) => Some((tag.to_owned(), 0)),
+        None => nearest_tag(object),
+    };
+    match described {
+        Some((tag, distance)) => {
+            let stripped = tag
+                .strip_prefix(['v', 'V'])
+                .filter(|x| x.starts_with(|symb: char| symb.is_ascii_digit()))
+                .unwrap_or(&tag)
+                .to_owned();
+            let describe = if distance > 0 {
+                format!("{}-{}-g{}", tag, distance, short_hash)
+            } else {
+                tag.clone()
+            };
+            values.push(("tag", tag));
+            values.push(("tag_stripped", stripped));
+            values.push(("distance", distance.to_string()));
+            values.push(("describe", describe));
+        }
+        None if TAG_PLACEHOLDERS.iter().any(|x| template.contains(x)) => {
+            return Err(Error::missing_object(
+                url,
+                &format!("tag reachable from {}", hash),
+            ));
+        }
+        None => {}
+    }
+
+    let values: Vec<(&str, &str)> = values.iter().map(|(k, v)| (*k, v.as_str())).collect();
+    Ok(template::render(template, &values))
+}
+
+/// The version is the tag name if `tag` is a tag, and the commit hash if it
+/// only resolved as a branch or a revspec.
+pub fn get_tag(
+    ws: &mut GitWorkspace,
+    url: &str,
+    tag: &str,
+    format: &VersionFormat,
+) -> Result<NewVersion> {
+    let repo = ws.create_repo(url)?;
+    let (object, matched) = gitworkspace::resolve_ref(&repo, RefKind::Tag, tag)?;
+    let name = matched
+        .as_deref()
+        .and_then(|x| x.strip_prefix("refs/tags/"));
+    if name.is_none() {
+        warn!("{}: {} is not a tag", url, tag);
+    }
+    let version = if let Some(template) = &format.template {
+        render_version(url, &object, name, format, template)?
+    } else if format.abbrev > 0 {
+        describe(url, &object, Some(format.abbrev), true)?
+    } else {
+        match name {
+            Some(name) => name.to_owned(),
+            None => gitworkspace::get_object_hash(&object)
+                .ok_or_else(|| Error::missing_object(url, &object.id().to_string()))?,
+        }
+    };
+    Ok(NewVersion {
+        version,
+        commit: object.id(),
+    })
 }
 
-pub fn get_tag(ws: &mut GitWorkspace, url: &str, tag: &str, abbrev: u32) -> Option<String> {
-    let repo = ws.create_repo(url).unwrap();
-    gitworkspace::get_tag(&repo, tag).and_then(|object| {
-        if abbrev > 0 {
-            gitworkspace::get_object_abbrev(&object, Some(abbrev), true)
+pub fn get_latest_tag(
+    ws: &mut GitWorkspace,
+    url: &str,
+    filter: &TagFilter,
+    format: &VersionFormat,
+) -> Result<NewVersion> {
+    let repo = ws.create_repo(url)?;
+    let tags = gitworkspace::get_tags(&repo)?;
+    let tag = filter
+        .select_latest(&tags)
+        .ok_or_else(|| Error::missing_object(url, "tag matching the filter"))?;
+    info!("{}: {} is the latest matching tag", url, tag.name);
+    let version = if format.template.is_none() && format.abbrev == 0 {
+        tag.name.clone()
+    } else {
+        let object = repo
+            .find_object(tag.commit, None)
+            .map_err(|err| Error::git(url, err))?;
+        if let Some(template) = &format.template {
+            render_version(url, &object, Some(&tag.name), format, template)?
         } else {
-            gitworkspace::get_object_abbrev(&object, None, false)
+            describe(url, &object, Some(format.abbrev), true)?
         }
+    };
+    Ok(NewVersion {
+        version,
+        commit: tag.commit,
     })
 }
 
@@ -46,29 +208,178 @@ pub fn get_latest_commit(
     ws: &mut GitWorkspace,
     url: &str,
     head: &str,
-    abbrev: u32,
-) -> Option<String> {
-    let repo = ws.create_repo(url).unwrap();
-    gitworkspace::get_latest_commit(&repo, head).and_then(|object| {
-        if abbrev > 0 {
-            gitworkspace::get_object_abbrev(&object, Some(abbrev), true)
-        } else {
-            gitworkspace::get_object_hash(&object)
-        }
+    format: &VersionFormat,
+) -> Result<NewVersion> {
+    let repo = ws.create_repo(url)?;
+    let object = gitworkspace::get_latest_commit(&repo, head)?;
+    let version = if let Some(template) = &format.template {
+        render_version(url, &object, None, format, template)?
+    } else if format.abbrev > 0 {
+        describe(url, &object, Some(format.abbrev), true)?
+    } else {
+        gitworkspace::get_object_hash(&object)
+            .ok_or_else(|| Error::missing_object(url, &object.id().to_string()))?
+    };
+    Ok(NewVersion {
+        version,
+        commit: object.id(),
     })
 }
 
-pub fn replace_commit(file: &str, old: &str, new: &str) -> Result<(), Error> {
+/// Find the commit a version from an mk file stands for: a ref or a hash,
+/// the tag with a `v` prefix, or the hash embedded in a rendered version
+/// (`1.2.0-20000302-1a2b3c4d`, `v1.2.0-2-g1a2b3c4d`).
+pub fn resolve_version(repo: &Repository, url: &str, version: &str) -> Result<Oid> {
+    let peel = |object: Object| {
+        object
+            .peel_to_commit()
+            .map(|commit| commit.id())
+            .map_err(|err| Error::git(url, err))
+    };
+    let err = match gitworkspace::resolve(repo, RefKind::Any, version) {
+        Ok(object) => return peel(object),
+        Err(err) => err,
+    };
+    if let Ok(object) = gitworkspace::resolve(repo, RefKind::Tag, &format!("v{}", version)) {
+        return peel(object);
+    }
+
+    let hashes = version
+        .rsplit(|symb: char| !symb.is_ascii_alphanumeric())
+        .map(|part| match part.strip_prefix('g') {
+            Some(rest) if rest.len() >= 7 => rest,
+            _ => part,
+        })
+        .filter(|part| part.len() >= 7 && part.chars().all(|symb| symb.is_ascii_hexdigit()));
+    for hash in hashes {
+        if let Ok(object) = repo.revparse_single(hash) {
+            return peel(object);
+        }
+    }
+    Err(err)
+}
+
+pub fn replace_commit(file: &str, old: &str, new: &str) -> Result<()> {
     let mut input = String::new();
-    File::open(file)?.read_to_string(&mut input)?;
+    File::open(file)
+        .and_then(|mut f| f.read_to_string(&mut input))
+        .map_err(|err| Error::io(file, err))?;
 
     let data = input.replace(old, new);
     let tmpfile = format!("{}.tmp", file);
-    let mut out = OpenOptions::new().write(true).create(true).open(&tmpfile)?;
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&tmpfile)
+        .and_then(|mut out| {
+            out.write_all(data.as_bytes())?;
+            out.sync_all()
+        })
+        .map_err(|err| Error::io(&tmpfile, err))?;
 
-    out.write_all(data.as_bytes())?;
-    out.sync_all()?;
+    std::fs::rename(&tmpfile, file).map_err(|err| Error::io(file, err))
+}
 
-    std::fs::rename(&tmpfile, file)?;
-    Ok(())
+#[cfg(test)]
+mod test {
+    use super::*;
+    use base::testutils::TempDir;
+    use git2::{Oid, Repository, Signature, Time};
+
+    const URL: &str = "https://host/repo.git";
+
+    fn commit(repo: &Repository, time: i64, message: &str) -> Oid {
+        let signature = Signature::new("test", "test@localhost", &Time::new(time, 0)).unwrap();
+        let tree = repo
+            .treebuilder(None)
+            .and_then(|builder| builder.write())
+            .and_then(|id| repo.find_tree(id))
+            .unwrap();
+        let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )
+        .unwrap()
+    }
+
+    fn init(name: &str) -> (TempDir, Repository) {
+        let dir = TempDir::new(name);
+        let repo = Repository::init(&*dir).unwrap();
+        (dir, repo)
+    }
+
+    #[test]
+    fn tags_and_templates() {
+        let (_dir, repo) = init("render");
+        let first = commit(&repo, 951782400, "first");
+        let object = repo.find_object(first, None).unwrap();
+        repo.tag_lightweight("v1.2.0", &object, false).unwrap();
+        commit(&repo, 951782400 + 86400, "second");
+        let last = commit(&repo, 951782400 + 2 * 86400, "third");
+        let object = repo.find_object(last, None).unwrap();
+        let hash = last.to_string();
+
+        assert_eq!(nearest_tag(&object), Some(("v1.2.0".to_owned(), 2)));
+
+        let format = VersionFormat {
+            abbrev: 8,
+            template: None,
+        };
+        let version = render_version(
+            URL,
+            &object,
+            None,
+            &format,
+            "{tag_stripped}-{date}-{short_hash}",
+        )
+        .unwrap();
+        assert_eq!(version, format!("1.2.0-20000302-{}", &hash[..8]));
+
+        let version = render_version(URL, &object, None, &format, "{describe}").unwrap();
+        assert_eq!(version, format!("v1.2.0-2-g{}", &hash[..8]));
+
+        let version = render_version(URL, &object, Some("v2.0"), &format, "{tag}+{hash}").unwrap();
+        assert_eq!(version, format!("v2.0+{}", hash));
+    }
+
+    #[test]
+    fn no_tags() {
+        let (_dir, repo) = init("no-tags");
+        let last = commit(&repo, 0, "first");
+        let object = repo.find_object(last, None).unwrap();
+
+        assert_eq!(nearest_tag(&object), None);
+        let format = VersionFormat::default();
+        assert!(render_version(URL, &object, None, &format, "{tag}").is_err());
+        let version = render_version(URL, &object, None, &format, "{date}-{hash}").unwrap();
+        assert_eq!(version, format!("19700101-{}", last));
+    }
+
+    #[test]
+    fn rendered_versions() {
+        let (_dir, repo) = init("resolve");
+        let first = commit(&repo, 0, "first");
+        let object = repo.find_object(first, None).unwrap();
+        repo.tag_lightweight("v1.2.0", &object, false).unwrap();
+        let last = commit(&repo, 86400, "second");
+        let hash = last.to_string();
+
+        let resolve = |version: &str| resolve_version(&repo, URL, version).ok();
+        assert_eq!(resolve("v1.2.0"), Some(first));
+        assert_eq!(resolve("1.2.0"), Some(first));
+        assert_eq!(resolve(&hash[..10]), Some(last));
+        assert_eq!(
+            resolve(&format!("1.2.0-19700102-{}", &hash[..8])),
+            Some(last)
+        );
+        assert_eq!(resolve(&format!("v1.2.0-1-g{}", &hash[..8])), Some(last));
+        assert_eq!(resolve("1.3.0"), None);
+    }
 }
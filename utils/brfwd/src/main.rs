@@ -1,16 +1,24 @@
 mod hashfwd;
+mod policy;
+mod summary;
+mod template;
+mod treecommit;
 
 use base::{
+    defconfig, githistory,
     gitworkspace::{GitWorkspace, Options as GitWorkspaceOptions},
     mkfile,
-    package::PackageReader,
-    utils,
+    package::Package,
+    patches::{self, PatchResult},
+    reader, report, utils,
 };
 
+use base::{Error, Result};
+use hashfwd::NewVersion;
 use log::{error, info, warn};
-use std::collections::HashSet;
-use std::io::{Error, ErrorKind};
+use policy::{NameSet, Policy, Settings};
 use structopt::StructOpt;
+use summary::{Outcome, SkipReason, Summary};
 #[derive(Debug, StructOpt)]
 #[structopt(
     name = "brfwd",
@@ -21,10 +29,17 @@ struct Options {
         short = "i",
         long = "input",
         default_value = "package.mk",
-        help = "path to the mk file or directory with mk files"
+        help = "path to the mk file, directory with mk files or defconfig"
     )]
     input: String,
 
+    #[structopt(
+        long = "duplicates",
+        default_value = "first",
+        help = "package defined in several mk files: use the first or the last one in path order, or stop with an error"
+    )]
+    duplicates: mkfile::Precedence,
+
     #[structopt(
         short = "w",
         long = "workdir",
@@ -33,6 +48,12 @@ struct Options {
     )]
     workdir: String,
 
+    #[structopt(
+        long = "dl-dir",
+        help = "BR download directory: its git caches are used instead of cloning"
+    )]
+    dl_dir: Option<String>,
+
     #[structopt(
         short = "k",
         long = "key",
@@ -54,7 +75,7 @@ struct Options {
         short = "b",
         long = "branch",
         default_value = "origin/master",
-        help = "branch name"
+        help = "branch name, ref or revspec (HEAD is the remote default branch)"
     )]
     branch: String,
 
@@ -66,6 +87,24 @@ struct Options {
     )]
     tag: String,
 
+    #[structopt(
+        long = "tag-pattern",
+        help = "use the latest tag matching the glob (v*.*.*) or /regex/"
+    )]
+    tag_pattern: Option<String>,
+
+    #[structopt(
+        long = "tag-exclude",
+        help = "skip tags matching the glob (*-rc*) or /regex/"
+    )]
+    tag_exclude: Option<String>,
+
+    #[structopt(
+        long = "semver",
+        help = "use the latest tag matching the semver requirement (^2.3, <3)"
+    )]
+    semver: Option<String>,
+
     #[structopt(
         short = "a",
         long = "abbrev",
@@ -75,11 +114,18 @@ struct Options {
     )]
     abbrev: u32,
 
+    #[structopt(
+        short = "f",
+        long = "format",
+        help = "version template. Placeholders: {tag}, {tag_stripped}, {hash}, {short_hash}, {date}, {distance}, {describe}"
+    )]
+    format: Option<String>,
+
     #[structopt(
         short = "s",
         long = "skip",
         default_value = "",
-        help = "don't process packages presented here"
+        help = "don't process packages presented here (names or globs, comma separated)"
     )]
     skip: String,
 
@@ -87,7 +133,7 @@ struct Options {
         short = "d",
         long = "direct",
         default_value = "",
-        help = "process only packages presented here"
+        help = "process only packages presented here (names or globs, comma separated)"
     )]
     direct: String,
 
@@ -99,53 +145,258 @@ struct Options {
         help = "process not more packages than limit. 0 - no limit"
     )]
     limit: usize,
+
+    #[structopt(
+        short = "p",
+        long = "policy",
+        help = "TOML file with per-package forwarding rules"
+    )]
+    policy: Option<String>,
+
+    #[structopt(long = "summary", help = "write a JSON summary of processed packages")]
+    summary: Option<String>,
+
+    #[structopt(
+        long = "commit",
+        parse(try_from_str),
+        default_value = "false",
+        help = "commit every updated package into the git repository of the mk-file"
+    )]
+    commit: bool,
+
+    #[structopt(
+        long = "commit-message",
+        default_value = "package/{name}: bump to {version}\n\n{shortlog}",
+        help = "commit message template. Placeholders: {name}, {old}, {version}, {shortlog}"
+    )]
+    commit_message: String,
+
+    #[structopt(
+        long = "check-patches",
+        parse(try_from_str),
+        default_value = "false",
+        help = "check that patches of updated packages still apply to the new version"
+    )]
+    check_patches: bool,
+
+    #[structopt(
+        long = "drop-upstream-patches",
+        parse(try_from_str),
+        default_value = "false",
+        help = "remove patches already upstream in the new version (requires --check-patches)"
+    )]
+    drop_upstream_patches: bool,
+}
+
+fn set_from_string(input: &str) -> Result<NameSet> {
+    NameSet::new(
+        input
+            .split(',')
+            .filter(|value| !value.is_empty())
+            .map(|value| value.to_owned()),
+    )
+}
+
+fn set_print(prefix: &str, set: &NameSet) {
+    for r in set.keys() {
+        info!("add {} into {}", r, prefix);
+    }
 }
 
-fn guess_reader(filename: &str) -> Result<Box<dyn PackageReader<Error = Error>>, Error> {
-    if filename.ends_with(".mk") {
-        info!("use MkFile reader for {}", filename);
-        Ok(Box::new(mkfile::MkFileReader::new(filename)))
-    } else if std::fs::read_dir(filename).is_ok() {
-        info!("use default dir. reader for {}", filename);
-        Ok(Box::new(mkfile::MkFileDirReader::new(filename)))
+fn get_new_version(ws: &mut GitWorkspace, url: &str, settings: &Settings) -> Result<NewVersion> {
+    let (msg, result) = if !settings.tag.is_empty() {
+        (
+            format!("{}: switching to {}", url, settings.tag),
+            hashfwd::get_tag(ws, url, &settings.tag, &settings.format),
+        )
+    } else if !settings.filter.is_empty() {
+        (
+            format!("{}: switching to the latest matching tag", url),
+            hashfwd::get_latest_tag(ws, url, &settings.filter, &settings.format),
+        )
     } else {
-        info!("use default file reader for {}", filename);
-        Ok(Box::new(mkfile::MkFileReader::new(filename)))
+        (
+            format!(
+                "{}: switching to the last commit on {}",
+                url, settings.branch
+            ),
+            hashfwd::get_latest_commit(ws, url, &settings.branch, &settings.format),
+        )
+    };
+
+    match &result {
+        Ok(_) => info!("{} done", msg),
+        Err(err) => warn!("{} failed: {}", msg, err),
     }
+    result
 }
 
-fn set_from_string(input: &str) -> HashSet<String> {
-    input.split(',').fold(HashSet::new(), |mut acc, value| {
-        if !value.is_empty() {
-            acc.insert(value.to_owned());
+fn forward_package(
+    ws: &mut GitWorkspace,
+    package: &Package,
+    settings: &Settings,
+    opts: &Options,
+) -> Result<Outcome> {
+    let url = package
+        .get_git_source()
+        .ok_or_else(|| Error::unsupported_source(&package.name))?;
+    let location = package
+        .location
+        .as_ref()
+        .ok_or_else(|| Error::parse(&package.name, None, "unknown mk-file location"))?;
+    let version = package
+        .version
+        .as_ref()
+        .ok_or_else(|| Error::parse(location, None, "version not found"))?;
+
+    let NewVersion {
+        version: hash,
+        commit,
+    } = get_new_version(ws, &url, settings)?;
+    if &hash == version {
+        return Ok(Outcome::UpToDate { version: hash });
+    }
+
+    info!("use {} for {}", hash, url);
+    if defconfig::is_defconfig(location) {
+        defconfig::write_version(location, &package.name, &hash)?;
+    } else {
+        hashfwd::replace_commit(location, version, &hash)?;
+    }
+
+    let old = resolve_old_version(ws, &url, version);
+    let new = commit.to_string();
+    let patches = if opts.check_patches && !defconfig::is_defconfig(location) {
+        check_patches(ws, &url, location, old.as_deref(), &new)
+    } else {
+        Vec::new()
+    };
+    // like a failed commit, a failed drop doesn't revert the mk file
+    let (dropped, drop_error) = if opts.drop_upstream_patches {
+        match patches::drop_upstream(&patches) {
+            Ok(dropped) => (dropped, None),
+            Err(err) => {
+                warn!("{}: can't drop upstream patches: {}", package.name, err);
+                (Vec::new(), Some(err))
+            }
         }
-        acc
+    } else {
+        (Vec::new(), None)
+    };
+
+    // the files are already changed, a failed commit doesn't revert them
+    let commit = if opts.commit && drop_error.is_none() {
+        let shortlog = build_shortlog(ws, &url, package, old.as_deref(), &new, opts);
+        Some(commit_package(
+            package, version, &hash, &shortlog, &dropped, opts,
+        ))
+    } else {
+        None
+    };
+
+    Ok(Outcome::Updated {
+        old: version.to_owned(),
+        new: hash,
+        commit,
+        patches,
+        drop_error,
     })
 }
 
-fn set_print(prefix: &str, set: &HashSet<String>) {
-    for r in set {
-        info!("add {} into {}", r, prefix);
+/// The commit the version in the mk file stands for, if it can be found.
+fn resolve_old_version(ws: &GitWorkspace, url: &str, version: &str) -> Option<String> {
+    let result = ws
+        .create_repo(url)
+        .and_then(|repo| hashfwd::resolve_version(&repo, url, version));
+    match result {
+        Ok(id) => Some(id.to_string()),
+        Err(err) => {
+            warn!("{}: can't find the commit of {}: {}", url, version, err);
+            None
+        }
     }
 }
 
-fn get_new_version(ws: &mut GitWorkspace, url: &str, opts: &Options) -> Option<String> {
-    let mut result = None;
-    let mut msg = String::new();
-    if !opts.tag.is_empty() {
-        msg = format!("{}: switching to {}", url, opts.tag);
-        result = hashfwd::get_tag(ws, url, &opts.tag, opts.abbrev);
-    } else if !opts.branch.is_empty() {
-        msg = format!("{}: switching to the last commit on {}", url, opts.branch);
-        result = hashfwd::get_latest_commit(ws, url, &opts.branch, opts.abbrev);
-    };
+/// Patch problems don't stop forwarding, the mk file is already updated.
+fn check_patches(
+    ws: &mut GitWorkspace,
+    url: &str,
+    location: &str,
+    old: Option<&str>,
+    new: &str,
+) -> Vec<PatchResult> {
+    let files = patches::find_patches(location, Some(new));
+    if files.is_empty() {
+        return Vec::new();
+    }
+    let result = ws
+        .create_repo(url)
+        .and_then(|repo| patches::check(&repo, url, old, new, &files));
+    match result {
+        Ok(result) => {
+            for patch in &result {
+                info!("{}", patch);
+            }
+            result
+        }
+        Err(err) => {
+            warn!("{}: can't check patches: {}", url, err);
+            Vec::new()
+        }
+    }
+}
 
-    if result.is_some() {
-        info!("{} done", msg);
-    } else {
-        warn!("{} failed", msg);
+/// The shortlog goes from the old to the new commit: the versions themselves
+/// may be rendered from a template or abbreviated.
+fn build_shortlog(
+    ws: &GitWorkspace,
+    url: &str,
+    package: &Package,
+    old: Option<&str>,
+    new: &str,
+    opts: &Options,
+) -> String {
+    let shortlog = match old {
+        Some(old) => ws
+            .create_repo(url)
+            .and_then(|repo| githistory::build(&repo, url, old, new, true))
+            .map(|changes| treecommit::shortlog(&changes))
+            .unwrap_or_else(|err| {
+                warn!("{}: no shortlog: {}", package.name, err);
+                String::new()
+            }),
+        None => String::new(),
+    };
+    if shortlog.is_empty() && opts.commit_message.contains("{shortlog}") {
+        warn!("{}: the shortlog is empty", package.name);
     }
-    result
+    shortlog
+}
+
+fn commit_package(
+    package: &Package,
+    old: &str,
+    new: &str,
+    shortlog: &str,
+    dropped: &[std::path::PathBuf],
+    opts: &Options,
+) -> Result<String> {
+    let template = opts.commit_message.replace("\\n", "\n");
+    let message = template::render(
+        &template,
+        &[
+            ("name", &package.name),
+            ("old", old),
+            ("version", new),
+            ("shortlog", shortlog),
+        ],
+    );
+    let message = format!("{}\n", message.trim_end());
+
+    let location = package.location.as_deref().unwrap_or_default();
+    let mut files = treecommit::package_files(location);
+    files.extend(dropped.iter().cloned());
+    treecommit::commit_files(&files, &message)
 }
 
 fn with_default_key(key: &String, default: Option<String>) -> String {
@@ -157,19 +408,44 @@ fn with_default_key(key: &String, default: Option<String>) -> String {
     key.to_owned()
 }
 
-fn run(opts: Options) -> Result<(), Error> {
-    let packages = guess_reader(&opts.input)?.read()?;
+fn run(opts: &Options) -> Result<Summary> {
+    let mut reader = reader::guess_mk(&opts.input, opts.duplicates)?;
+    let packages = reader.read()?;
+    report::print_duplicates(reader.duplicates());
+    let policy = match &opts.policy {
+        Some(file) => Policy::read(file)?,
+        None => Policy::default(),
+    };
+    let settings = Settings::new(
+        &opts.branch,
+        &opts.tag,
+        opts.tag_pattern.as_deref(),
+        opts.tag_exclude.as_deref(),
+        opts.semver.as_deref(),
+        hashfwd::VersionFormat {
+            abbrev: opts.abbrev,
+            template: opts.format.clone(),
+        },
+    )?;
     let mut wsopts = GitWorkspaceOptions::new(&opts.workdir);
-    let params = hashfwd::CheckPackageParameters {
-        allow: set_from_string(&opts.direct),
-        deny: set_from_string(&opts.skip),
+    // command line lists replace the ones of the policy file
+    let mut params = hashfwd::CheckPackageParameters {
+        allow: set_from_string(&opts.direct)?,
+        deny: set_from_string(&opts.skip)?,
     };
+    if params.allow.is_empty() {
+        params.allow = policy.allow.clone();
+    }
+    if params.deny.is_empty() {
+        params.deny = policy.deny.clone();
+    }
 
     set_print("denylist", &params.deny);
     set_print("allowlist", &params.allow);
 
     wsopts.key = with_default_key(&opts.key, utils::get_default_ssh_key());
     wsopts.clean_workspace = opts.clean;
+    wsopts.dl_dir = opts.dl_dir.clone();
 
     let mut wsgit = GitWorkspace::new(&wsopts);
     wsgit.init()?;
@@ -181,49 +457,135 @@ fn run(opts: Options) -> Result<(), Error> {
     };
 
     let mut processed = 0;
-    for (_, package) in packages.iter() {
-        info!("{} processing", package.name);
-        if !hashfwd::check_package(package, &params) {
+    let mut summary = Summary::new();
+    let mut names: Vec<&String> = packages.keys().collect();
+    names.sort();
+    for name in params.allow.unmatched(&names) {
+        warn!("{} package not found", name);
+        summary.push(
+            name,
+            Outcome::Skipped {
+                reason: SkipReason::NotFound,
+            },
+        );
+    }
+
+    for package in names.into_iter().map(|x| &packages[x]) {
+        match hashfwd::check_package(package, &params) {
+            Ok(()) => {}
+            // only packages asked for (or that could be) are reported
+            Err(SkipReason::NotAllowed) => continue,
+            Err(SkipReason::NotGit) if params.allow.is_empty() => continue,
+            Err(reason) => {
+                summary.push(&package.name, Outcome::Skipped { reason });
+                continue;
+            }
+        }
+
+        let package_settings = match policy.get(&package.name) {
+            Some(rules) if rules.skip || rules.pin => {
+                let reason = if rules.skip {
+                    SkipReason::Policy
+                } else {
+                    SkipReason::Pinned
+                };
+                info!("{} package {}", package.name, reason);
+                summary.push(&package.name, Outcome::Skipped { reason });
+                continue;
+            }
+            Some(rules) => settings.with_policy(rules),
+            None => Ok(settings.clone()),
+        };
+        if processed >= limit {
+            summary.push(
+                &package.name,
+                Outcome::Skipped {
+                    reason: SkipReason::Limit,
+                },
+            );
             continue;
         }
 
-        let url = package.get_git_source().unwrap();
-        if let Some(hash) = get_new_version(&mut wsgit, &url, &opts) {
-            info!("use {} for {}", hash, url);
-            hashfwd::replace_commit(
-                package.location.as_ref().unwrap(),
-                package.version.as_ref().unwrap(),
-                &hash,
-            )
-            .unwrap();
-
-            processed += 1;
-            if processed >= limit {
-                info!("got limit of processed packages");
-                break;
+        info!("{} processing", package.name);
+        let result = package_settings
+            .and_then(|settings| forward_package(&mut wsgit, package, &settings, opts));
+        match result {
+            Ok(outcome) => {
+                summary.push(&package.name, outcome);
+                processed += 1;
+                if processed == limit {
+                    info!("got limit of processed packages");
+                }
             }
+            Err(error) => summary.push(&package.name, Outcome::Failed { error }),
         }
     }
-    Ok(())
+    Ok(summary)
 }
 
-fn check_opts(opts: Options) -> Result<Options, Error> {
+fn check_opts(opts: Options) -> Option<Options> {
     if !(opts.direct.is_empty() || opts.skip.is_empty()) {
         println!("'skip' and 'direct' parameters are both set at the same time. Please choose only one of them.");
-        return Err(Error::new(ErrorKind::InvalidInput, "invalid parameters"));
+        return None;
     }
-    Ok(opts)
+    if opts.drop_upstream_patches && !opts.check_patches {
+        println!("'drop-upstream-patches' needs 'check-patches': upstream patches are found by the check.");
+        return None;
+    }
+    Some(opts)
 }
 
-fn main() -> Result<(), Error> {
+fn main() {
     env_logger::init();
-    let opts = check_opts(Options::from_args())?;
-    run(opts)
-        .map(|_| {
-            println!("Done");
-        })
-        .map_err(|err| {
-            error!("brfwd failed:{:?}", err);
-            err
-        })
+    let opts = check_opts(Options::from_args()).unwrap_or_else(|| std::process::exit(1));
+    let summary = run(&opts).unwrap_or_else(|err| {
+        error!("brfwd failed:{}", err);
+        std::process::exit(1);
+    });
+
+    summary.print();
+    if let Some(file) = &opts.summary {
+        if let Err(err) = summary.write_json(file) {
+            error!("can't write summary:{}", err);
+            std::process::exit(1);
+        }
+    }
+
+    if summary.has_failures() {
+        std::process::exit(1);
+    }
+    println!("Done");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use base::testutils::TempDir;
+
+    #[test]
+    fn pinned_version_kept() {
+        let dir = TempDir::new("brfwd-pinned");
+
+        let mkfile = dir.join("app.mk");
+        let data = "APP_VERSION = 3e87ac5fafde20bc4e66811314e54bd261d4ca37\n\
+                    APP_SITE = https://github.com/eidheim/Simple-Web-Server.git\n\
+                    APP_SITE_METHOD = git\n";
+        std::fs::write(&mkfile, data).unwrap();
+        let policy = dir.join("policy.toml");
+        std::fs::write(&policy, "[packages.app]\npin = true\n").unwrap();
+
+        let opts = Options::from_iter([
+            "brfwd",
+            "-i",
+            &mkfile.to_string_lossy(),
+            "-p",
+            &policy.to_string_lossy(),
+            "-w",
+            &dir.join("work").to_string_lossy(),
+        ]);
+        let summary = run(&opts).unwrap();
+        assert!(!summary.has_failures());
+        assert!(summary.to_string().contains("app      skipped     pinned"));
+        assert_eq!(std::fs::read_to_string(&mkfile).unwrap(), data);
+    }
 }
@@ -0,0 +1,329 @@
+use crate::hashfwd::VersionFormat;
+use base::pattern::Pattern;
+use base::tags::{parse_requirement, TagFilter};
+use base::{Error, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Forwarding rules for a single package (or a group of packages). Unset
+/// fields fall back to the command line parameters.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PackagePolicy {
+    pub branch: Option<String>,
+    pub tag: Option<String>,
+    pub tag_pattern: Option<String>,
+    pub tag_exclude: Option<String>,
+    pub semver: Option<String>,
+    pub abbrev: Option<u32>,
+    pub format: Option<String>,
+    #[serde(default)]
+    pub pin: bool,
+    #[serde(default)]
+    pub skip: bool,
+}
+
+impl PackagePolicy {
+    fn has_selector(&self) -> bool {
+        self.branch.is_some()
+            || self.tag.is_some()
+            || self.tag_pattern.is_some()
+            || self.tag_exclude.is_some()
+            || self.semver.is_some()
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default)]
+    allow: Vec<String>,
+    #[serde(default)]
+    deny: Vec<String>,
+    #[serde(default)]
+    packages: BTreeMap<String, PackagePolicy>,
+}
+
+/// Package names, globs and `/regex/`es of an allowlist or a denylist.
+#[derive(Debug, Default, Clone)]
+pub struct NameSet {
+    keys: Vec<String>,
+    patterns: Vec<Option<Pattern>>,
+}
+
+impl NameSet {
+    pub fn new<I: IntoIterator<Item = String>>(keys: I) -> Result<NameSet> {
+        let mut result = NameSet::default();
+        for key in keys {
+            if result.keys.contains(&key) {
+                continue;
+            }
+            let pattern = if is_pattern(&key) {
+                Some(Pattern::new(&key)?)
+            } else {
+                None
+            };
+            result.keys.push(key);
+            result.patterns.push(pattern);
+        }
+        Ok(result)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    fn key_matches(&self, index: usize, name: &str) -> bool {
+        match &self.patterns[index] {
+            Some(pattern) => pattern.is_match(name),
+            None => self.keys[index] == name,
+        }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        (0..self.keys.len()).any(|index| self.key_matches(index, name))
+    }
+
+    /// Keys that match none of `names`.
+    pub fn unmatched<'a, S: AsRef<str>>(&'a self, names: &[S]) -> Vec<&'a str> {
+        (0..self.keys.len())
+            .filter(|index| !names.iter().any(|x| self.key_matches(*index, x.as_ref())))
+            .map(|index| self.keys[index].as_str())
+            .collect()
+    }
+}
+
+struct PolicyEntry {
+    key: String,
+    pattern: Option<Pattern>,
+    policy: PackagePolicy,
+}
+
+/// Policy file. Packages are keyed by name or by glob/regex; an exact name
+/// wins over patterns, and a longer pattern wins over a shorter one. The
+/// `allow` and `deny` lists take names and patterns as well.
+///
+/// ```toml
+/// deny = ["busybox", "host-*"]
+///
+/// [packages.linux]
+/// branch = "origin/linux-6.6.y"
+///
+/// [packages."lib*"]
+/// tag-pattern = "v*.*.*"
+/// tag-exclude = "*-rc*"
+/// format = "{tag_stripped}"
+/// ```
+#[derive(Default)]
+pub struct Policy {
+    pub allow: NameSet,
+    pub deny: NameSet,
+    entries: Vec<PolicyEntry>,
+}
+
+fn is_pattern(key: &str) -> bool {
+    key.contains(['*', '?']) || (key.len() > 1 && key.starts_with('/') && key.ends_with('/'))
+}
+
+impl Policy {
+    pub fn read(path: &str) -> Result<Policy> {
+        let data = std::fs::read_to_string(path).map_err(|err| Error::io(path, err))?;
+        Policy::parse(path, &data)
+    }
+
+    pub fn parse(path: &str, data: &str) -> Result<Policy> {
+        let file: PolicyFile = toml::from_str(data).map_err(|err| {
+            let line = err
+                .span()
+                .map(|span| data[..span.start].matches('\n').count() + 1);
+            Error::parse(path, line, err.message())
+        })?;
+
+        let mut entries = Vec::new();
+        for (key, policy) in file.packages {
+            let pattern = if is_pattern(&key) {
+                Some(Pattern::new(&key)?)
+            } else {
+                None
+            };
+            entries.push(PolicyEntry {
+                key,
+                pattern,
+                policy,
+            });
+        }
+
+        Ok(Policy {
+            allow: NameSet::new(file.allow)?,
+            deny: NameSet::new(file.deny)?,
+            entries,
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PackagePolicy> {
+        let exact = self
+            .entries
+            .iter()
+            .find(|x| x.pattern.is_none() && x.key == name);
+
+        exact
+            .or_else(|| {
+                self.entries
+                    .iter()
+                    .filter(|x| x.pattern.as_ref().is_some_and(|p| p.is_match(name)))
+                    .max_by_key(|x| x.key.len())
+            })
+            .map(|x| &x.policy)
+    }
+}
+
+/// Effective parameters to find a new version of a package.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub branch: String,
+    pub tag: String,
+    pub filter: TagFilter,
+    pub format: VersionFormat,
+}
+
+fn make_filter(
+    pattern: Option<&str>,
+    exclude: Option<&str>,
+    semver: Option<&str>,
+) -> Result<TagFilter> {
+    Ok(TagFilter {
+        pattern: pattern.map(Pattern::new).transpose()?,
+        exclude: exclude.map(Pattern::new).transpose()?,
+        requirement: semver.map(parse_requirement).transpose()?,
+    })
+}
+
+impl Settings {
+    pub fn new(
+        branch: &str,
+        tag: &str,
+        tag_pattern: Option<&str>,
+        tag_exclude: Option<&str>,
+        semver: Option<&str>,
+        format: VersionFormat,
+    ) -> Result<Settings> {
+        Ok(Settings {
+            branch: branch.to_owned(),
+            tag: tag.to_owned(),
+            filter: make_filter(tag_pattern, tag_exclude, semver)?,
+            format,
+        })
+    }
+
+    /// Apply package policy. If the policy sets any of branch/tag options,
+    /// they replace the command line ones entirely, so a package that follows
+    /// a branch is not affected by a global tag pattern.
+    pub fn with_policy(&self, policy: &PackagePolicy) -> Result<Settings> {
+        let mut result = self.clone();
+        if policy.has_selector() {
+            result.branch = policy.branch.clone().unwrap_or_else(|| self.branch.clone());
+            result.tag = policy.tag.clone().unwrap_or_default();
+            result.filter = make_filter(
+                policy.tag_pattern.as_deref(),
+                policy.tag_exclude.as_deref(),
+                policy.semver.as_deref(),
+            )?;
+        }
+        if let Some(abbrev) = policy.abbrev {
+            result.format.abbrev = abbrev;
+        }
+        if let Some(template) = &policy.format {
+            result.format.template = Some(template.clone());
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const POLICY: &str = r#"
+allow = ["linux", "lib*", "app"]
+deny = ["busybox", "/^host-/"]
+
+[packages.linux]
+branch = "origin/linux-6.6.y"
+
+[packages."lib*"]
+tag-pattern = "v*.*.*"
+abbrev = 8
+format = "{tag_stripped}"
+
+[packages."libfoo-*"]
+semver = "^2"
+
+[packages.app]
+pin = true
+"#;
+
+    #[test]
+    fn lookup() {
+        let policy = Policy::parse("policy.toml", POLICY).unwrap();
+        assert!(policy.allow.contains("linux"));
+        assert!(policy.allow.contains("libfoo-extra"));
+        assert!(!policy.allow.contains("zlib"));
+        assert!(policy.deny.contains("busybox"));
+        assert!(policy.deny.contains("host-zlib"));
+        assert!(!policy.deny.contains("zlib-host"));
+        assert_eq!(policy.allow.unmatched(&["linux", "libz"]), ["app"]);
+
+        let linux = policy.get("linux").unwrap();
+        assert_eq!(linux.branch.as_deref(), Some("origin/linux-6.6.y"));
+
+        let libfoo = policy.get("libfoo").unwrap();
+        assert_eq!(libfoo.tag_pattern.as_deref(), Some("v*.*.*"));
+
+        let extra = policy.get("libfoo-extra").unwrap();
+        assert_eq!(extra.semver.as_deref(), Some("^2"));
+
+        assert!(policy.get("app").unwrap().pin);
+        assert!(policy.get("zlib").is_none());
+    }
+
+    #[test]
+    fn invalid() {
+        match Policy::parse("policy.toml", "[packages.linux]\nbrunch = \"x\"") {
+            Err(Error::Parse { line, .. }) => assert_eq!(line, Some(2)),
+            _ => unreachable!(),
+        }
+        assert!(Policy::parse("policy.toml", "[packages.\"/[/\"]\npin = true").is_err());
+        assert!(Policy::parse("policy.toml", "deny = [\"/[/\"]").is_err());
+    }
+
+    #[test]
+    fn settings() {
+        let policy = Policy::parse("policy.toml", POLICY).unwrap();
+        let defaults = Settings::new(
+            "origin/master",
+            "",
+            Some("v*"),
+            None,
+            None,
+            VersionFormat::default(),
+        )
+        .unwrap();
+
+        let linux = defaults.with_policy(policy.get("linux").unwrap()).unwrap();
+        assert_eq!(linux.branch, "origin/linux-6.6.y");
+        assert!(linux.filter.is_empty());
+        assert_eq!(linux.format.abbrev, 0);
+        assert_eq!(linux.format.template, None);
+
+        let libfoo = defaults.with_policy(policy.get("libfoo").unwrap()).unwrap();
+        assert_eq!(libfoo.branch, "origin/master");
+        assert!(libfoo.filter.is_match("v1.2.3"));
+        assert!(!libfoo.filter.is_match("v1"));
+        assert_eq!(libfoo.format.abbrev, 8);
+        assert_eq!(libfoo.format.template.as_deref(), Some("{tag_stripped}"));
+    }
+}
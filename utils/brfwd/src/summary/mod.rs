@@ -0,0 +1,335 @@
+use base::patches::{PatchResult, PatchStatus};
+use base::{Error, Result};
+use serde::Serialize;
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    NotAllowed,
+    Denied,
+    NotGit,
+    Pinned,
+    Policy,
+    /// Left after `--limit` packages were processed.
+    Limit,
+    /// Allowlisted, but not in the input.
+    NotFound,
+}
+
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            SkipReason::NotAllowed => "not in allowlist",
+            SkipReason::Denied => "in denylist",
+            SkipReason::NotGit => "not a git package",
+            SkipReason::Pinned => "pinned",
+            SkipReason::Policy => "skipped by policy",
+            SkipReason::Limit => "over the limit",
+            SkipReason::NotFound => "not found in the input",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+#[derive(Debug)]
+pub enum Outcome {
+    Updated {
+        old: String,
+        new: String,
+        /// Result of `--commit`: the mk file stays updated if it fails.
+        commit: Option<Result<String>>,
+        /// Results of `--check-patches`.
+        patches: Vec<PatchResult>,
+        /// Failure of `--drop-upstream-patches`: the mk file stays updated and
+        /// isn't committed.
+        drop_error: Option<Error>,
+    },
+    UpToDate {
+        version: String,
+    },
+    Skipped {
+        reason: SkipReason,
+    },
+    Failed {
+        error: Error,
+    },
+}
+
+#[derive(Debug)]
+pub struct PackageOutcome {
+    pub name: String,
+    pub outcome: Outcome,
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    name: &'a str,
+    status: &'static str,
+    old_version: Option<&'a str>,
+    new_version: Option<&'a str>,
+    commit: Option<&'a str>,
+    reason: Option<String>,
+    error: Option<String>,
+    failed_patches: Vec<String>,
+    upstream_patches: Vec<String>,
+}
+
+fn patch_names(patches: &[PatchResult], upstream: bool) -> Vec<String> {
+    patches
+        .iter()
+        .filter(|x| match x.status {
+            PatchStatus::Applies => false,
+            PatchStatus::Fails(_) => !upstream,
+            PatchStatus::Upstream(_) => upstream,
+        })
+        .map(|x| x.file_name())
+        .collect()
+}
+
+impl PackageOutcome {
+    fn status(&self) -> &'static str {
+        match self.outcome {
+            Outcome::Updated { .. } => "updated",
+            Outcome::UpToDate { .. } => "up-to-date",
+            Outcome::Skipped { .. } => "skipped",
+            Outcome::Failed { .. } => "failed",
+        }
+    }
+
+    fn details(&self) -> String {
+        match &self.outcome {
+            Outcome::Updated {
+                old,
+                new,
+                commit,
+                patches,
+                drop_error,
+            } => {
+                let mut details = format!("{} -> {}", old, new);
+                match commit {
+                    Some(Ok(commit)) => details.push_str(&format!(" (commit {})", commit)),
+                    Some(Err(err)) => details.push_str(&format!(" (commit fails: {})", err)),
+                    None => {}
+                }
+                if let Some(err) = drop_error {
+                    details.push_str(&format!(" (patch drop fails: {})", err));
+                }
+                for (upstream, state) in [(false, "fails"), (true, "upstream")] {
+                    for name in patch_names(patches, upstream) {
+                        details.push_str(&format!(", {} {}", name, state));
+                    }
+                }
+                details
+            }
+            Outcome::UpToDate { version } => version.to_owned(),
+            Outcome::Skipped { reason } => reason.to_string(),
+            Outcome::Failed { error } => error.to_string(),
+        }
+    }
+
+    fn record(&self) -> Record<'_> {
+        let mut record = Record {
+            name: &self.name,
+            status: self.status(),
+            old_version: None,
+            new_version: None,
+            commit: None,
+            reason: None,
+            error: None,
+            failed_patches: Vec::new(),
+            upstream_patches: Vec::new(),
+        };
+        match &self.outcome {
+            Outcome::Updated {
+                old,
+                new,
+                commit,
+                patches,
+                drop_error,
+            } => {
+                record.old_version = Some(old);
+                record.new_version = Some(new);
+                match commit {
+                    Some(Ok(commit)) => record.commit = Some(commit),
+                    Some(Err(err)) => record.error = Some(err.to_string()),
+                    None => {}
+                }
+                if let Some(err) = drop_error {
+                    record.error = Some(err.to_string());
+                }
+                record.failed_patches = patch_names(patches, false);
+                record.upstream_patches = patch_names(patches, true);
+            }
+            Outcome::UpToDate { version } => {
+                record.old_version = Some(version);
+                record.new_version = Some(version);
+            }
+            Outcome::Skipped { reason } => record.reason = Some(reason.to_string()),
+            Outcome::Failed { error } => record.error = Some(error.to_string()),
+        }
+        record
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Summary {
+    outcomes: Vec<PackageOutcome>,
+}
+
+impl Summary {
+    pub fn new() -> Summary {
+        Summary::default()
+    }
+
+    pub fn push(&mut self, name: &str, outcome: Outcome) {
+        self.outcomes.push(PackageOutcome {
+            name: name.to_owned(),
+            outcome,
+        });
+    }
+
+    pub fn has_failures(&self) -> bool {
+        self.outcomes.iter().any(|x| match &x.outcome {
+            Outcome::Updated {
+                commit, drop_error, ..
+            } => matches!(commit, Some(Err(_))) || drop_error.is_some(),
+            Outcome::Failed { .. } => true,
+            _ => false,
+        })
+    }
+
+    fn sorted(&self) -> Vec<&PackageOutcome> {
+        let mut outcomes: Vec<&PackageOutcome> = self.outcomes.iter().collect();
+        outcomes.sort_by(|a, b| a.name.cmp(&b.name));
+        outcomes
+    }
+
+    pub fn print(&self) {
+        print!("{}", self);
+    }
+
+    pub fn write_json(&self, file: &str) -> Result<()> {
+        let records: Vec<Record> = self.sorted().iter().map(|x| x.record()).collect();
+        let data = serde_json::to_string_pretty(&records)
+            .map_err(|err| Error::parse(file, None, &err.to_string()))?;
+        std::fs::write(file, data).map_err(|err| Error::io(file, err))
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let outcomes = self.sorted();
+        let width = outcomes
+            .iter()
+            .map(|x| x.name.len())
+            .chain(std::iter::once("package".len()))
+            .max()
+            .unwrap_or(0);
+
+        writeln!(f, "{:width$}  {:10}  details", "package", "status")?;
+        for outcome in outcomes {
+            writeln!(
+                f,
+                "{:width$}  {:10}  {}",
+                outcome.name,
+                outcome.status(),
+                outcome.details()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn table() {
+        let mut summary = Summary::new();
+        summary.push(
+            "zlib",
+            Outcome::Updated {
+                old: "v1".to_owned(),
+                new: "v2".to_owned(),
+                commit: None,
+                patches: Vec::new(),
+                drop_error: None,
+            },
+        );
+        summary.push(
+            "libfoo",
+            Outcome::Updated {
+                old: "1.0".to_owned(),
+                new: "1.1".to_owned(),
+                commit: None,
+                patches: vec![
+                    PatchResult {
+                        path: "package/libfoo/0001-fix.patch".into(),
+                        status: PatchStatus::Upstream("4bf291e9".to_owned()),
+                    },
+                    PatchResult {
+                        path: "package/libfoo/0002-build.patch".into(),
+                        status: PatchStatus::Fails("conflict".to_owned()),
+                    },
+                ],
+                drop_error: None,
+            },
+        );
+        summary.push(
+            "boost",
+            Outcome::Skipped {
+                reason: SkipReason::Denied,
+            },
+        );
+        assert!(!summary.has_failures());
+        assert_eq!(
+            summary.to_string(),
+            "package  status      details\n\
+             boost    skipped     in denylist\n\
+             libfoo   updated     1.0 -> 1.1, 0002-build.patch fails, 0001-fix.patch upstream\n\
+             zlib     updated     v1 -> v2\n"
+        );
+
+        summary.push(
+            "magic-package",
+            Outcome::Failed {
+                error: Error::missing_object("https://host/magic.git", "v3"),
+            },
+        );
+        assert!(summary.has_failures());
+
+        let mut summary = Summary::new();
+        summary.push(
+            "zlib",
+            Outcome::Updated {
+                old: "v1".to_owned(),
+                new: "v2".to_owned(),
+                commit: Some(Err(Error::missing_object("/tmp/br", "HEAD"))),
+                patches: Vec::new(),
+                drop_error: None,
+            },
+        );
+        assert!(summary.has_failures());
+        assert!(summary.to_string().contains("v1 -> v2 (commit fails: "));
+
+        let mut summary = Summary::new();
+        summary.push(
+            "libfoo",
+            Outcome::Updated {
+                old: "1.0".to_owned(),
+                new: "1.1".to_owned(),
+                commit: None,
+                patches: Vec::new(),
+                drop_error: Some(Error::io(
+                    "package/libfoo/0001-fix.patch",
+                    std::io::Error::from(std::io::ErrorKind::PermissionDenied),
+                )),
+            },
+        );
+        assert!(summary.has_failures());
+        assert!(summary
+            .to_string()
+            .contains("1.0 -> 1.1 (patch drop fails: "));
+    }
+}
@@ -0,0 +1,52 @@
+/// Replace `{key}` placeholders with values. Unknown placeholders are kept
+/// as is, so a typo is visible in the output rather than silently dropped.
+pub fn render(template: &str, values: &[(&str, &str)]) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(begin) = rest.find('{') {
+        output.push_str(&rest[..begin]);
+        rest = &rest[begin..];
+        let end = match rest.find('}') {
+            Some(end) => end,
+            None => break,
+        };
+        let key = &rest[1..end];
+        match values.iter().find(|(k, _)| *k == key) {
+            Some((_, value)) => output.push_str(value),
+            None => output.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// UTC date as `YYYYMMDD` from seconds since epoch.
+pub fn date(seconds: i64) -> String {
+    let (year, month, day) = base::utils::civil_date(seconds);
+    format!("{:04}{:02}{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dates() {
+        assert_eq!(date(0), "19700101");
+        assert_eq!(date(951782400), "20000229");
+        assert_eq!(date(1700000000), "20231114");
+    }
+
+    #[test]
+    fn placeholders() {
+        let values = [("name", "zlib"), ("version", "1.3")];
+        assert_eq!(
+            render("package/{name}: bump to {version}", &values),
+            "package/zlib: bump to 1.3"
+        );
+        assert_eq!(render("{unknown} {name}", &values), "{unknown} zlib");
+        assert_eq!(render("{name", &values), "{name");
+        assert_eq!(render("no placeholders", &values), "no placeholders");
+    }
+}
@@ -0,0 +1,224 @@
+use base::diffs::PackageChange;
+use base::{Error, Result};
+use git2::{Index, IndexEntry, IndexTime, Repository};
+use log::{info, warn};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+/// Files brfwd may touch for a package: the mk file and, if exists, the
+/// `.hash` file next to it.
+pub fn package_files(mkfile: &str) -> Vec<PathBuf> {
+    let mkfile = PathBuf::from(mkfile);
+    let hash = mkfile.with_extension("hash");
+    let mut files = vec![mkfile];
+    if hash.is_file() {
+        files.push(hash);
+    }
+    files
+}
+
+/// One line per upstream commit: short id and summary.
+pub fn shortlog(changes: &[PackageChange]) -> String {
+    changes
+        .iter()
+        .map(|change| {
+            let id = change.id.as_deref().unwrap_or_default();
+            let id = &id[..id.len().min(12)];
+            let summary = change.summary.as_deref().unwrap_or_default();
+            format!("{} {}", id, summary)
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn relative_path(workdir: &Path, file: &Path) -> std::io::Result<PathBuf> {
+    let workdir = workdir.canonicalize()?;
+    let file = file.canonicalize()?;
+    file.strip_prefix(&workdir)
+        .map(|x| x.to_path_buf())
+        .map_err(|_| std::io::Error::other("file is outside of the repository"))
+}
+
+fn relative_file(workdir: &Path, file: &Path) -> Result<PathBuf> {
+    let path = file.to_string_lossy();
+    if file.exists() {
+        return relative_path(workdir, file).map_err(|err| Error::io(&path, err));
+    }
+    // removed files (like dropped patches) can't be canonicalized
+    let dir = file.parent().unwrap_or(Path::new("."));
+    let relative = relative_path(workdir, dir).map_err(|err| Error::io(&path, err))?;
+    Ok(relative.join(file.file_name().unwrap_or_default()))
+}
+
+fn file_entry(repo: &Repository, file: &Path, relative: &Path) -> Result<IndexEntry> {
+    let path = file.to_string_lossy();
+    let data = std::fs::read(file).map_err(|err| Error::io(&path, err))?;
+    let meta = std::fs::metadata(file).map_err(|err| Error::io(&path, err))?;
+    let id = repo.blob(&data).map_err(|err| Error::git(&path, err))?;
+    Ok(IndexEntry {
+        ctime: IndexTime::new(0, 0),
+        mtime: IndexTime::new(0, 0),
+        dev: 0,
+        ino: 0,
+        mode: if meta.permissions().mode() & 0o111 != 0 {
+            0o100755
+        } else {
+            0o100644
+        },
+        uid: 0,
+        gid: 0,
+        file_size: data.len() as u32,
+        id,
+        flags: 0,
+        flags_extended: 0,
+        path: relative.to_string_lossy().into_owned().into_bytes(),
+    })
+}
+
+/// Commit `files` into the git repository they belong to; files that don't
+/// exist anymore are removed from it. The repository is discovered from the
+/// location of the first file.
+///
+/// The commit tree is the tree of HEAD with only `files` changed: whatever
+/// else is staged stays staged and uncommitted.
+pub fn commit_files(files: &[PathBuf], message: &str) -> Result<String> {
+    let first = files
+        .first()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let dir = Path::new(&first)
+        .parent()
+        .filter(|x| !x.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let repo = Repository::discover(dir).map_err(|err| Error::git(&first, err))?;
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| Error::git(&first, git2::Error::from_str("bare repository")))?
+        .to_path_buf();
+    let url = workdir.to_string_lossy().into_owned();
+    let git_error = |err| Error::git(&url, err);
+
+    let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+    let mut index = Index::new().map_err(git_error)?;
+    if let Some(parent) = &parent {
+        let tree = parent.tree().map_err(git_error)?;
+        index.read_tree(&tree).map_err(git_error)?;
+    }
+
+    let mut relatives = Vec::new();
+    for file in files {
+        let relative = relative_file(&workdir, file)?;
+        if file.exists() {
+            index
+                .add(&file_entry(&repo, file, &relative)?)
+                .map_err(git_error)?;
+        } else {
+            index.remove_path(&relative).map_err(git_error)?;
+        }
+        relatives.push(relative);
+    }
+
+    let tree = index
+        .write_tree_to(&repo)
+        .and_then(|id| repo.find_tree(id))
+        .map_err(git_error)?;
+    let signature = repo.signature().map_err(git_error)?;
+    let parents: Vec<&git2::Commit> = parent.iter().collect();
+
+    let id = repo
+        .commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )
+        .map_err(git_error)?;
+    info!("committed {} into {}", id, url);
+
+    // like `git commit <files>`: the committed files are not shown as changed
+    let updated = repo.index().and_then(|mut index| {
+        for (file, relative) in files.iter().zip(&relatives) {
+            if file.exists() {
+                index.add_path(relative)?;
+            } else {
+                index.remove_path(relative)?;
+            }
+        }
+        index.write()
+    });
+    if let Err(err) = updated {
+        warn!("{}: index is not updated: {}", url, err);
+    }
+    Ok(id.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use base::testutils::TempDir;
+
+    #[test]
+    fn shortlog_lines() {
+        let changes = vec![
+            PackageChange {
+                summary: Some("Fix build".to_owned()),
+                author: None,
+                id: Some("294ab5c377ae00c0e659c03bb7cc6eef40e724df".to_owned()),
+                reversed: None,
+            },
+            PackageChange {
+                summary: Some("Add feature".to_owned()),
+                author: None,
+                id: Some("4bf291e9".to_owned()),
+                reversed: None,
+            },
+        ];
+        assert_eq!(
+            shortlog(&changes),
+            "294ab5c377ae Fix build\n4bf291e9 Add feature"
+        );
+    }
+
+    #[test]
+    fn commit_only_files() {
+        let dir = TempDir::new("brfwd-treecommit");
+        std::fs::create_dir_all(dir.join("package/foo")).unwrap();
+        let repo = Repository::init(&*dir).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "test").unwrap();
+        config.set_str("user.email", "test@localhost").unwrap();
+
+        let mkfile = dir.join("package/foo/foo.mk");
+        let patch = dir.join("package/foo/0001-fix.patch");
+        let other = dir.join("other.txt");
+        std::fs::write(&mkfile, "FOO_VERSION = 1\n").unwrap();
+        std::fs::write(&patch, "patch\n").unwrap();
+        commit_files(&[mkfile.clone(), patch.clone()], "initial").unwrap();
+
+        // staged by the user, must not get into the commit
+        std::fs::write(&other, "staged\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("other.txt")).unwrap();
+        index.write().unwrap();
+
+        std::fs::write(&mkfile, "FOO_VERSION = 2\n").unwrap();
+        std::fs::remove_file(&patch).unwrap();
+        commit_files(&[mkfile, patch], "bump").unwrap();
+
+        let tree = repo.head().unwrap().peel_to_tree().unwrap();
+        let entry = tree.get_path(Path::new("package/foo/foo.mk")).unwrap();
+        let blob = repo.find_blob(entry.id()).unwrap();
+        assert_eq!(blob.content(), b"FOO_VERSION = 2\n");
+        assert!(tree
+            .get_path(Path::new("package/foo/0001-fix.patch"))
+            .is_err());
+        assert!(tree.get_path(Path::new("other.txt")).is_err());
+
+        let status = repo.status_file(Path::new("other.txt")).unwrap();
+        assert!(status.contains(git2::Status::INDEX_NEW));
+        let status = repo.status_file(Path::new("package/foo/foo.mk")).unwrap();
+        assert!(status.is_empty());
+    }
+}
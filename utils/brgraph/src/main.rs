@@ -0,0 +1,128 @@
+use base::{
+    graph::{Graph, GraphDiff, GraphFormat},
+    mkfile,
+    package::Packages,
+    reader, showinfo,
+};
+use base::{Error, Result};
+
+use log::{error, warn};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "brgraph",
+    about = "export the dependency graph of a BR package set and query it"
+)]
+struct Options {
+    #[structopt(
+        short = "i",
+        long = "input",
+        default_value = "show-info.json",
+        help = "path to the show-info JSON or mk(s)"
+    )]
+    input: String,
+
+    #[structopt(
+        short = "s",
+        long = "second",
+        help = "second package set: report dependencies added and removed by it"
+    )]
+    second: Option<String>,
+
+    #[structopt(
+        short = "f",
+        long = "format",
+        default_value = "tree",
+        help = "output format [dot,json,tree]"
+    )]
+    format: GraphFormat,
+
+    #[structopt(long = "why", help = "print paths from the root set to the package")]
+    why: Option<String>,
+
+    #[structopt(long = "rdeps", help = "print packages that depend on the package")]
+    rdeps: Option<String>,
+
+    #[structopt(long = "root", help = "print the tree of the package only")]
+    root: Option<String>,
+
+    #[structopt(
+        long = "duplicates",
+        default_value = "first",
+        help = "package defined in several mk files: use the first or the last one in path order, or stop with an error"
+    )]
+    duplicates: mkfile::Precedence,
+
+    #[structopt(short = "o", long = "output", help = "output file, stdout by default")]
+    output: Option<String>,
+}
+
+fn read_graph(filename: &str, precedence: mkfile::Precedence) -> Result<Graph> {
+    // virtual packages of show-info have no version, but are links of chains
+    let mut reader: reader::BoxedReader = if reader::is_show_info(filename) {
+        Box::new(showinfo::ReportReader::new(filename).all_packages(true))
+    } else {
+        reader::guess(filename, precedence)?
+    };
+    let packages: Packages = reader.read()?;
+    if packages.values().all(|x| x.dependencies.is_empty()) {
+        warn!("{}: no dependencies found", filename);
+    }
+    Ok(Graph::build(&packages))
+}
+
+fn known<'a>(opts: &Options, graph: &Graph, name: &'a str) -> Result<&'a str> {
+    if graph.contains(name) {
+        Ok(name)
+    } else {
+        Err(Error::missing_object(&opts.input, name))
+    }
+}
+
+fn render(opts: &Options) -> Result<String> {
+    let graph = read_graph(&opts.input, opts.duplicates)?;
+    if let Some(second) = &opts.second {
+        let second = read_graph(second, opts.duplicates)?;
+        return Ok(GraphDiff::build(&graph, &second).export(opts.format));
+    }
+
+    if let Some(name) = &opts.why {
+        let name = known(opts, &graph, name)?;
+        return Ok(graph.why(name).to_string());
+    }
+    if let Some(name) = &opts.rdeps {
+        let name = known(opts, &graph, name)?;
+        let rdeps: Vec<String> = graph
+            .rdeps(name)
+            .into_iter()
+            .map(|x| format!("{}\n", x))
+            .collect();
+        return Ok(rdeps.concat());
+    }
+    match (&opts.root, opts.format) {
+        (Some(root), GraphFormat::Tree) => Ok(graph.to_tree(Some(known(opts, &graph, root)?))),
+        _ => Ok(graph.export(opts.format)),
+    }
+}
+
+fn run(opts: Options) -> Result<()> {
+    let data = render(&opts)?;
+    match &opts.output {
+        Some(file) => std::fs::write(file, data).map_err(|err| Error::io(file, err)),
+        None => {
+            print!("{}", data);
+            Ok(())
+        }
+    }
+}
+
+fn main() {
+    env_logger::init();
+
+    let opts = Options::from_args();
+    if let Err(err) = run(opts) {
+        error!("graph fails:{}", err);
+        std::process::exit(2)
+    }
+}
@@ -0,0 +1,74 @@
+use base::lint::{Linter, Rule};
+use base::Result;
+
+use log::error;
+use std::str::FromStr;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "brlint",
+    about = "check BR mk files for common mistakes and coding style issues"
+)]
+struct Options {
+    #[structopt(
+        short = "i",
+        long = "input",
+        default_value = "package",
+        help = "path to the mk file or directory with mk files"
+    )]
+    input: String,
+
+    #[structopt(
+        short = "d",
+        long = "disable",
+        default_value = "",
+        help = "comma-separated ids of rules to skip"
+    )]
+    disable: String,
+
+    #[structopt(
+        long = "fix",
+        parse(try_from_str),
+        default_value = "false",
+        help = "fix mechanical issues (trailing whitespace, git site method) in place"
+    )]
+    fix: bool,
+
+    #[structopt(long = "list-rules", help = "print rule ids and exit")]
+    list_rules: bool,
+}
+
+/// Number of remaining issues.
+fn run(opts: Options) -> Result<usize> {
+    let mut linter = Linter::new();
+    for id in opts.disable.split(',').filter(|x| !x.trim().is_empty()) {
+        linter = linter.disable(Rule::from_str(id)?);
+    }
+
+    let diagnostics = linter.run(&opts.input, opts.fix)?;
+    diagnostics.iter().for_each(|x| println!("{}", x));
+    Ok(diagnostics.len())
+}
+
+fn main() {
+    env_logger::init();
+
+    let opts = Options::from_args();
+    if opts.list_rules {
+        for rule in Rule::ALL {
+            let fixable = if rule.is_fixable() { " (fixable)" } else { "" };
+            println!("{}{}", rule, fixable);
+        }
+        return;
+    }
+
+    match run(opts) {
+        Ok(0) => {}
+        Ok(_) => std::process::exit(1),
+        Err(err) => {
+            error!("lint fails:{}", err);
+            std::process::exit(2)
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use base::Result;
+use base::{
+    gitworkspace::{GitWorkspace, Options as GitWorkspaceOptions},
+    mkfile,
+    package::Package,
+    patches::{self, PatchResult, PatchStatus},
+    reader, utils,
+};
+
+use log::{error, warn};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "brpatch",
+    about = "check that patches of BR git packages apply to the package version (or another one)"
+)]
+struct Options {
+    #[structopt(
+        short = "i",
+        long = "input",
+        default_value = "package",
+        help = "path to the mk file or directory with mk files"
+    )]
+    input: String,
+
+    #[structopt(
+        short = "t",
+        long = "to",
+        help = "check against this tag, branch or commit instead of the version of the mk file"
+    )]
+    to: Option<String>,
+
+    #[structopt(
+        long = "drop-upstream",
+        parse(try_from_str),
+        default_value = "false",
+        help = "remove patches already upstream"
+    )]
+    drop_upstream: bool,
+
+    #[structopt(
+        long = "duplicates",
+        default_value = "first",
+        help = "package defined in several mk files: use the first or the last one in path order, or stop with an error"
+    )]
+    duplicates: mkfile::Precedence,
+
+    #[structopt(
+        short = "w",
+        long = "workdir",
+        default_value = "/tmp/brpatch",
+        help = "path to the working directory"
+    )]
+    workdir: String,
+
+    #[structopt(
+        long = "dl-dir",
+        help = "BR download directory: its git caches are used instead of cloning"
+    )]
+    dl_dir: Option<String>,
+
+    #[structopt(
+        short = "k",
+        long = "key",
+        help = "path to the SSH key. Empty by default, that means $HOME/.ssh/id_rsa will be used",
+        default_value = ""
+    )]
+    key: String,
+
+    #[structopt(
+        short = "c",
+        long = "clean",
+        parse(try_from_str),
+        default_value = "false",
+        help = "clean working directory before run"
+    )]
+    clean: bool,
+}
+
+/// Patch results of a git package, `None` if it has no patches.
+fn check_package(
+    ws: &GitWorkspace,
+    package: &Package,
+    opts: &Options,
+) -> Result<Option<(String, Vec<PatchResult>)>> {
+    let (url, location, version) = match (
+        package.get_git_source(),
+        &package.location,
+        &package.version,
+    ) {
+        (Some(url), Some(location), Some(version)) => (url, location, version),
+        _ => return Ok(None),
+    };
+    let (old, new) = match &opts.to {
+        Some(to) => (Some(version.as_str()), to.as_str()),
+        None => (None, version.as_str()),
+    };
+
+    let files = patches::find_patches(location, Some(new));
+    if files.is_empty() {
+        return Ok(None);
+    }
+    let repo = ws.create_repo(&url)?;
+    let results = patches::check(&repo, &url, old, new, &files)?;
+    Ok(Some((new.to_owned(), results)))
+}
+
+fn run(opts: Options) -> Result<bool> {
+    let packages = reader::guess_mk(&opts.input, opts.duplicates)?.read()?;
+    let mut wsopts = GitWorkspaceOptions::new(&opts.workdir);
+    wsopts.key = if opts.key.is_empty() {
+        utils::get_default_ssh_key().unwrap_or_default()
+    } else {
+        opts.key.clone()
+    };
+    wsopts.clean_workspace = opts.clean;
+    wsopts.dl_dir = opts.dl_dir.clone();
+    let ws = GitWorkspace::new(&wsopts);
+    ws.init()?;
+
+    let mut names: Vec<&String> = packages.keys().collect();
+    names.sort();
+
+    let mut clean = true;
+    for name in names {
+        let (version, results) = match check_package(&ws, &packages[name], &opts) {
+            Ok(Some(result)) => result,
+            Ok(None) => continue,
+            Err(err) => {
+                warn!("{}: patches not checked: {}", name, err);
+                println!("[!] {}: {}", name, err);
+                clean = false;
+                continue;
+            }
+        };
+
+        println!("[*] {} {}", name, version);
+        for result in &results {
+            println!("      {}", result);
+        }
+        if opts.drop_upstream {
+            for file in patches::drop_upstream(&results)? {
+                println!("      dropped {}", file.display());
+            }
+        }
+        // dropped upstream patches are fixed, failing ones are not
+        clean &= results.iter().all(|x| match x.status {
+            PatchStatus::Applies => true,
+            PatchStatus::Upstream(_) => opts.drop_upstream,
+            PatchStatus::Fails(_) => false,
+        });
+    }
+    Ok(clean)
+}
+
+fn main() {
+    env_logger::init();
+
+    let opts = Options::from_args();
+    match run(opts) {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(err) => {
+            error!("check fails:{}", err);
+            std::process::exit(2)
+        }
+    }
+}
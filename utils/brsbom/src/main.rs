@@ -0,0 +1,119 @@
+use base::{
+    gitworkspace, mkfile, reader,
+    sbom::{self, Commits, Document, SbomFormat},
+    utils,
+};
+use base::{Error, Result};
+
+use log::{error, info, warn};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "brsbom",
+    about = "export a BR package set as SPDX or CycloneDX JSON SBOM"
+)]
+struct Options {
+    #[structopt(
+        short = "i",
+        long = "input",
+        default_value = "show-info.json",
+        help = "path to the show-info JSON, SBOM, legal-info, mk(s) or defconfig"
+    )]
+    input: String,
+
+    #[structopt(
+        short = "f",
+        long = "format",
+        default_value = "spdx",
+        help = "SBOM format [spdx,cyclonedx]"
+    )]
+    format: SbomFormat,
+
+    #[structopt(
+        short = "n",
+        long = "name",
+        default_value = "buildroot",
+        help = "name of the image described by the SBOM"
+    )]
+    name: String,
+
+    #[structopt(
+        long = "duplicates",
+        default_value = "first",
+        help = "package defined in several mk files: use the first or the last one in path order, or stop with an error"
+    )]
+    duplicates: mkfile::Precedence,
+
+    #[structopt(
+        long = "resolve-commits",
+        parse(try_from_str),
+        default_value = "false",
+        help = "resolve versions of git packages to exact commits"
+    )]
+    resolve_commits: bool,
+
+    #[structopt(
+        short = "w",
+        long = "workdir",
+        default_value = "/tmp/brsbom",
+        help = "path to the working directory"
+    )]
+    workdir: String,
+
+    #[structopt(
+        short = "k",
+        long = "key",
+        help = "path to the SSH key. Empty by default, that means $HOME/.ssh/id_rsa will be used",
+        default_value = ""
+    )]
+    key: String,
+
+    #[structopt(short = "o", long = "output", help = "output file, stdout by default")]
+    output: Option<String>,
+}
+
+fn run(opts: Options) -> Result<()> {
+    let packages = reader::guess(&opts.input, opts.duplicates)?.read()?;
+    info!("{} package(s) found", packages.len());
+
+    let commits = if opts.resolve_commits {
+        let mut wsopts = gitworkspace::Options::new(&opts.workdir);
+        wsopts.key = if opts.key.is_empty() {
+            utils::get_default_ssh_key().unwrap_or_default()
+        } else {
+            opts.key.clone()
+        };
+        let (commits, failures) = sbom::resolve_commits(&packages, &wsopts)?;
+        for (name, err) in failures {
+            warn!("{}: version is kept as is: {}", name, err);
+        }
+        commits
+    } else {
+        Commits::new()
+    };
+
+    let doc = Document {
+        name: opts.name.clone(),
+        tool: format!("brsbom-{}", env!("CARGO_PKG_VERSION")),
+        created: utils::now(),
+    };
+    let data = serde_json::to_string_pretty(&sbom::export(opts.format, &packages, &doc, &commits))
+        .unwrap_or_default();
+    match &opts.output {
+        Some(file) => std::fs::write(file, data).map_err(|err| Error::io(file, err)),
+        None => {
+            println!("{}", data);
+            Ok(())
+        }
+    }
+}
+
+fn main() {
+    env_logger::init();
+
+    let opts = Options::from_args();
+    if let Err(err) = run(opts) {
+        error!("export fails:{}", err)
+    }
+}
@@ -0,0 +1,117 @@
+use base::{
+    filelist::{self, PackageSizes, SizeDiff, SizeReport},
+    package::{PackageReader, Packages},
+    showinfo,
+};
+use base::{Error, Result};
+
+use log::{error, info};
+use std::path::Path;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "brsize",
+    about = "report the installed size of BR packages in the target filesystem, or compare two outputs"
+)]
+struct Options {
+    #[structopt(
+        short = "i",
+        long = "input",
+        default_value = "output",
+        help = "path to the output directory, packages-file-list.txt or show-info JSON in the output directory"
+    )]
+    input: String,
+
+    #[structopt(
+        short = "t",
+        long = "target",
+        help = "target directory, <output>/target by default"
+    )]
+    target: Option<String>,
+
+    #[structopt(
+        short = "s",
+        long = "second",
+        help = "second output: report size changes of packages"
+    )]
+    second: Option<String>,
+
+    #[structopt(
+        long = "second-target",
+        help = "target directory of the second output, <second>/target by default"
+    )]
+    second_target: Option<String>,
+
+    #[structopt(
+        short = "n",
+        long = "count",
+        default_value = "0",
+        help = "number of packages to report, all of them by default"
+    )]
+    count: usize,
+
+    #[structopt(
+        long = "min-size",
+        default_value = "0",
+        help = "ignore size changes below that many bytes"
+    )]
+    min_size: u64,
+
+    #[structopt(short = "o", long = "output", help = "output file, stdout by default")]
+    output: Option<String>,
+}
+
+fn read_sizes(input: &str, target: Option<&str>) -> Result<PackageSizes> {
+    let list = filelist::find_file_list(input)
+        .ok_or_else(|| Error::missing_object(input, filelist::FILE_LIST_NAME))?;
+    info!("read {}", list);
+    filelist::read_sizes(&list, target.map(Path::new))
+}
+
+/// Versions from a show-info JSON input, to show them next to size changes.
+fn read_versions(input: &str) -> Result<Packages> {
+    if input.ends_with(".json") {
+        showinfo::ReportReader::new(input).read()
+    } else {
+        Ok(Packages::new())
+    }
+}
+
+fn render(opts: &Options) -> Result<String> {
+    let sizes = read_sizes(&opts.input, opts.target.as_deref())?;
+    match &opts.second {
+        Some(second) => {
+            let second_sizes = read_sizes(second, opts.second_target.as_deref())?;
+            let diff = SizeDiff::build(&sizes, &second_sizes, opts.min_size)
+                .with_versions(&read_versions(&opts.input)?, &read_versions(second)?);
+            Ok(diff.to_string())
+        }
+        None => Ok(SizeReport {
+            sizes: &sizes,
+            count: opts.count,
+        }
+        .to_string()),
+    }
+}
+
+fn run(opts: Options) -> Result<()> {
+    let data = render(&opts)?;
+    match &opts.output {
+        Some(file) => std::fs::write(file, data).map_err(|err| Error::io(file, err)),
+        None => {
+            print!("{}", data);
+            Ok(())
+        }
+    }
+}
+
+fn main() {
+    env_logger::init();
+
+    let opts = Options::from_args();
+    if let Err(err) = run(opts) {
+        error!("report fails:{}", err);
+        std::process::exit(2)
+    }
+}
@@ -0,0 +1,105 @@
+use base::buildtime::{BuildTimeDiff, BuildTimes};
+use base::{Error, Result};
+
+use log::error;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "brtime",
+    about = "report the slowest packages and steps of a BR build-time.log, or compare two logs"
+)]
+struct Options {
+    #[structopt(
+        short = "i",
+        long = "input",
+        default_value = "output/build/build-time.log",
+        help = "path to the build-time.log"
+    )]
+    input: String,
+
+    #[structopt(
+        short = "s",
+        long = "second",
+        help = "second build-time.log: report packages built slower or faster"
+    )]
+    second: Option<String>,
+
+    #[structopt(
+        short = "n",
+        long = "count",
+        default_value = "10",
+        help = "number of packages and steps to report"
+    )]
+    count: usize,
+
+    #[structopt(
+        long = "min-seconds",
+        default_value = "1",
+        help = "ignore changes below that many seconds"
+    )]
+    min_seconds: f64,
+
+    #[structopt(
+        long = "min-percent",
+        default_value = "10",
+        help = "ignore changes below that percentage"
+    )]
+    min_percent: f64,
+
+    #[structopt(short = "o", long = "output", help = "output file, stdout by default")]
+    output: Option<String>,
+}
+
+fn summary(times: &BuildTimes, count: usize) -> String {
+    let mut result = format!(
+        "[*] build time: {:.1}s, {} package(s)\n",
+        times.total_time(),
+        times.packages.len()
+    );
+    result.push_str("slowest packages:\n");
+    for (name, time) in times.slowest(count) {
+        result.push_str(&format!("      {}: {:.1}s\n", name, time));
+    }
+    result.push_str("slowest steps:\n");
+    for (name, step, time) in times.slowest_steps(count) {
+        result.push_str(&format!("      {} {}: {:.1}s\n", name, step, time));
+    }
+    result.push_str("steps:\n");
+    for (step, time) in times.step_totals() {
+        result.push_str(&format!("      {}: {:.1}s\n", step, time));
+    }
+    result
+}
+
+fn render(opts: &Options) -> Result<String> {
+    let times = BuildTimes::read(&opts.input)?;
+    match &opts.second {
+        Some(second) => {
+            let second = BuildTimes::read(second)?;
+            let diff = BuildTimeDiff::build(&times, &second, opts.min_seconds, opts.min_percent);
+            Ok(diff.to_string())
+        }
+        None => Ok(summary(&times, opts.count)),
+    }
+}
+
+fn run(opts: Options) -> Result<()> {
+    let data = render(&opts)?;
+    match &opts.output {
+        Some(file) => std::fs::write(file, data).map_err(|err| Error::io(file, err)),
+        None => {
+            print!("{}", data);
+            Ok(())
+        }
+    }
+}
+
+fn main() {
+    env_logger::init();
+
+    let opts = Options::from_args();
+    if let Err(err) = run(opts) {
+        error!("report fails:{}", err)
+    }
+}